@@ -0,0 +1,827 @@
+//! Censoring of decoded, interleaved samples in memory.
+
+use crate::{process, Error, Generator, Replace, Tempo};
+use std::ops;
+
+/// A single replacement applied to a buffer, in resolved samples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Applied {
+    /// The word which was censored.
+    pub word: String,
+    /// Index of the replacement in the ones passed to [apply_replacements].
+    pub index: usize,
+    /// The range of interleaved samples which was replaced.
+    pub range: ops::Range<usize>,
+    /// Name of the generator used for the replacement.
+    pub generator: String,
+    /// The samples which were replaced.
+    pub original: Vec<i16>,
+}
+
+/// Options for how replacements are applied.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options<'a> {
+    /// Tempo used to resolve positions in bars and beats, which are an error without it.
+    pub tempo: Option<&'a Tempo>,
+    /// Milliseconds to pad every range with on each side, unless the replacement specifies its
+    /// own padding. Padding never extends into a neighboring range, and where the padding of two
+    /// neighbors meet the gap between them is split evenly.
+    pub pad: u32,
+    /// Ranges separated by a gap shorter than this many milliseconds are bridged into one
+    /// continuous region.
+    pub min_silence: u32,
+    /// Samples at or below this amplitude are considered silent when resolving `^^` and `$$`.
+    pub silence_threshold: i16,
+}
+
+/// Apply all replacements in place to the interleaved samples in `data`, which has the given spec
+/// and duration in frames.
+///
+/// Returns the replacements which were applied, in order. Empty ranges are skipped.
+///
+/// Replacements whose ranges touch or overlap are generated as one region, as long as they have
+/// the same tone settings. Each separate region is generated on its own, so a tone restarts at
+/// phase 0 in each of them.
+pub fn apply_replacements(
+    data: &mut [i16],
+    spec: hound::WavSpec,
+    duration: u32,
+    replaces: &[&Replace],
+    generator: &dyn Generator,
+    options: &Options<'_>,
+) -> Result<Vec<Applied>, Error> {
+    let tempo = options.tempo;
+
+    // NB: ranges are resolved against the duration, so it has to match the buffer.
+    match duration.checked_mul(u32::from(spec.channels)) {
+        Some(len) if len as usize == data.len() => (),
+        _ => {
+            return Err(Error::BufferLength {
+                duration,
+                channels: spec.channels,
+                len: data.len(),
+            })
+        }
+    };
+
+    // NB: the audible part of the file is only scanned for if it's needed.
+    let audible = replaces
+        .iter()
+        .any(|r| r.range.start.uses_audio() || r.range.end.uses_audio())
+        .then(|| {
+            let range = process::audible_range(data, spec.channels, options.silence_threshold);
+            range.start as u32..range.end as u32
+        });
+
+    // resolved ranges, and the padded ranges which are actually replaced.
+    let mut resolved = Vec::new();
+
+    for (index, replace) in replaces.iter().enumerate() {
+        let ops::Range { start, end } = replace
+            .range
+            .resolve(&spec, duration, tempo, audible.as_ref())
+            .map_err(|e| Error::Replace {
+                replace: Box::new((*replace).clone()),
+                error: Box::new(e),
+            })?;
+
+        if start == end {
+            continue;
+        }
+
+        let pad = ms_samples(replace.pad.unwrap_or(options.pad), spec);
+        let padded = start.saturating_sub(pad)..usize::min(end.saturating_add(pad), data.len());
+        resolved.push((*replace, start..end, padded, index));
+    }
+
+    split_padding(&mut resolved, usize::from(spec.channels));
+    bridge_gaps(&mut resolved, ms_samples(options.min_silence, spec));
+
+    // NB: originals are taken before anything is replaced, so that a range which overlaps an
+    // earlier region doesn't keep the samples generated for it.
+    let mut originals = resolved
+        .iter()
+        .map(|(_, _, range, _)| data[range.clone()].to_vec())
+        .collect::<Vec<_>>();
+
+    let mut applied = Vec::new();
+
+    // NB: touching or overlapping ranges are generated as a single region, so that a tone is
+    // continuous across them instead of restarting its phase at every join.
+    for (ops::Range { start, end }, region) in regions(&resolved) {
+        let tuned = tuned_generator(generator, resolved[region[0]].0)?;
+        let generator = tuned.as_deref().unwrap_or(generator);
+        let generated =
+            generator.process_in_context(data, start..end, spec.sample_rate, spec.channels);
+
+        for i in region {
+            let (replace, _, ref range, index) = resolved[i];
+
+            applied.push(Applied {
+                word: replace.word.clone(),
+                index,
+                range: range.clone(),
+                generator: generator.name().to_string(),
+                original: std::mem::take(&mut originals[i]),
+            });
+        }
+
+        data[start..end].copy_from_slice(&generated);
+    }
+
+    applied.sort_by_key(|a| a.index);
+    return Ok(applied);
+
+    /// Group the replacements into regions of touching or overlapping ranges which have the same
+    /// tone settings, ordered by where they start.
+    ///
+    /// Each region is the range it covers, and the indexes of its replacements in `resolved`.
+    fn regions(resolved: &[Resolved<'_>]) -> Vec<(ops::Range<usize>, Vec<usize>)> {
+        let mut order = (0..resolved.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| resolved[*i].2.start);
+
+        let mut regions = Vec::<(ops::Range<usize>, Vec<usize>)>::new();
+
+        for i in order {
+            let (replace, _, ref range, _) = resolved[i];
+
+            if let Some((covered, members)) = regions.last_mut() {
+                let first = resolved[members[0]].0;
+
+                if range.start <= covered.end
+                    && replace.frequency == first.frequency
+                    && replace.amplitude == first.amplitude
+                {
+                    covered.end = usize::max(covered.end, range.end);
+                    members.push(i);
+                    continue;
+                }
+            }
+
+            regions.push((range.clone(), vec![i]));
+        }
+
+        regions
+    }
+
+    /// Convert milliseconds into interleaved samples.
+    fn ms_samples(ms: u32, s: hound::WavSpec) -> usize {
+        let frames = u64::from(ms) * u64::from(s.sample_rate) / 1000;
+        (frames * u64::from(s.channels)) as usize
+    }
+
+    /// Limit padding so that it doesn't overlap neighboring ranges.
+    fn split_padding(resolved: &mut [Resolved<'_>], channels: usize) {
+        let mut order = (0..resolved.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| resolved[*i].1.start);
+
+        for w in order.windows(2) {
+            let (a, b) = (w[0], w[1]);
+            let (a_end, b_start) = (resolved[a].1.end, resolved[b].1.start);
+
+            // NB: overlapping ranges are left as they are.
+            if a_end > b_start || resolved[a].2.end <= resolved[b].2.start {
+                continue;
+            }
+
+            let mid = a_end + (b_start - a_end) / 2;
+            let mid = usize::max(mid - mid % channels.max(1), a_end);
+            resolved[a].2.end = usize::min(resolved[a].2.end, mid);
+            resolved[b].2.start = usize::max(resolved[b].2.start, mid);
+        }
+    }
+
+    /// Bridge gaps shorter than `min_gap` samples by extending the range before each gap.
+    fn bridge_gaps(resolved: &mut [Resolved<'_>], min_gap: usize) {
+        if min_gap == 0 {
+            return;
+        }
+
+        for (a, b) in merge_order(resolved) {
+            let (a_end, b_start) = (resolved[a].2.end, resolved[b].2.start);
+
+            if a_end < b_start && b_start - a_end < min_gap {
+                resolved[a].2.end = b_start;
+            }
+        }
+    }
+
+    /// Pairs of neighboring ranges, ordered by where they start.
+    fn merge_order(resolved: &[Resolved<'_>]) -> Vec<(usize, usize)> {
+        let mut order = (0..resolved.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| resolved[*i].1.start);
+        order.windows(2).map(|w| (w[0], w[1])).collect()
+    }
+}
+
+/// A replacement, with its resolved range, the padded range which is replaced, and its index.
+type Resolved<'a> = (&'a Replace, ops::Range<usize>, ops::Range<usize>, usize);
+
+/// The replacements which were skipped by [apply_replacements] since their range is empty, like
+/// `01.000-01.000` or a range past the end of the file.
+pub fn skipped<'a>(replaces: &[&'a Replace], applied: &[Applied]) -> Vec<&'a Replace> {
+    replaces
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !applied.iter().any(|a| a.index == *i))
+        .map(|(_, r)| *r)
+        .collect()
+}
+
+/// Invert replacements which have been applied to `data`, so that only the replaced ranges keep
+/// their original samples and everything else is silenced.
+pub fn invert(data: &mut [i16], applied: &[Applied]) {
+    let mut kept = vec![0i16; data.len()];
+
+    for a in applied {
+        kept[a.range.clone()].copy_from_slice(&a.original);
+    }
+
+    data.copy_from_slice(&kept);
+}
+
+/// Calculate the fraction of a buffer with `len` samples which is covered by the applied
+/// replacements, counting overlapping ranges once.
+pub fn censored_ratio(applied: &[Applied], len: usize) -> f32 {
+    if len == 0 {
+        return 0f32;
+    }
+
+    let mut ranges = applied.iter().map(|a| a.range.clone()).collect::<Vec<_>>();
+    ranges.sort_by_key(|r| r.start);
+
+    let mut covered = 0;
+    let mut end = 0;
+
+    for range in ranges {
+        let start = usize::max(range.start, end);
+
+        if range.end > start {
+            covered += range.end - start;
+            end = range.end;
+        }
+    }
+
+    covered as f32 / len as f32
+}
+
+/// Construct a generator tuned with the tone settings of the given replacement, if it has any.
+///
+/// Replacements without tone settings, or generators which don't support them, return `None` to
+/// use the generator as-is.
+fn tuned_generator(
+    generator: &dyn Generator,
+    replace: &Replace,
+) -> Result<Option<Box<dyn Generator>>, Error> {
+    if replace.frequency.is_none() && replace.amplitude.is_none() {
+        return Ok(None);
+    }
+
+    let error = match (replace.frequency, replace.amplitude) {
+        (Some(frequency), _) if !(frequency > 0f32 && frequency.is_finite()) => {
+            Error::BadFrequency(frequency)
+        }
+        (_, Some(amplitude)) if !(0f32..=1f32).contains(&amplitude) => {
+            Error::BadAmplitude(amplitude)
+        }
+        _ => return Ok(generator.with_tone(replace.frequency, replace.amplitude)),
+    };
+
+    Err(Error::Replace {
+        replace: Box::new(replace.clone()),
+        error: Box::new(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_replacements, censored_ratio, invert, skipped, Applied, Options};
+    use crate::{
+        generator::{Silence, Tone},
+        Error, Generator, Range, Replace,
+    };
+
+    #[test]
+    fn test_apply_replacements() -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut data = vec![100i16; 2000];
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse("^-.100").expect("valid range"),
+        );
+        let b = Replace::new(
+            String::from("b"),
+            Range::parse("$-.100-$").expect("valid range"),
+        );
+        let empty = Replace::new(
+            String::from("c"),
+            Range::parse(".500-.500").expect("valid range"),
+        );
+
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&a, &empty, &b],
+            &Silence::new(),
+            &Options::default(),
+        )?;
+
+        assert_eq!(2, applied.len());
+        assert_eq!(0..200, applied[0].range);
+        assert_eq!(1800..2000, applied[1].range);
+        assert_eq!(
+            vec![0, 2],
+            applied.iter().map(|a| a.index).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![&empty], skipped(&[&a, &empty, &b], &applied));
+        assert_eq!(vec![100i16; 200], applied[1].original);
+        assert!(data[..200].iter().all(|s| *s == 0));
+        assert!(data[200..1800].iter().all(|s| *s == 100));
+        assert!(data[1800..].iter().all(|s| *s == 0));
+
+        // the buffer must match the duration.
+        assert!(apply_replacements(
+            &mut data,
+            spec,
+            999,
+            &[&a],
+            &Silence::new(),
+            &Options::default()
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_replacements_overflow() {
+        let spec = hound::WavSpec {
+            channels: 8,
+            sample_rate: 192_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut data = vec![0i16; 8000];
+        let huge = Replace::new(
+            String::from("a"),
+            Range::parse("^-9999:00.000").expect("valid range"),
+        );
+
+        let e = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&huge],
+            &Silence::new(),
+            &Options::default(),
+        )
+        .expect_err("position should overflow");
+
+        let message = e.to_string();
+        assert!(message.contains("[a]{^-9999:00.000}"), "{}", message);
+        assert!(message.contains("192000"), "{}", message);
+    }
+
+    #[test]
+    fn test_censored_ratio() {
+        let applied = |range| Applied {
+            word: String::from("a"),
+            index: 0,
+            range,
+            generator: String::from("silence"),
+            original: vec![],
+        };
+
+        assert_eq!(0f32, censored_ratio(&[], 100));
+        assert_eq!(0f32, censored_ratio(&[], 0));
+        assert_eq!(0.5f32, censored_ratio(&[applied(50..100)], 100));
+        // overlapping and nested ranges are only counted once.
+        let ranges = [
+            applied(40..60),
+            applied(0..20),
+            applied(10..30),
+            applied(45..50),
+        ];
+        assert_eq!(0.5f32, censored_ratio(&ranges, 100));
+    }
+
+    #[test]
+    fn test_apply_replacements_pad() -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut data = vec![100i16; 2000];
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse(".100-.200").expect("valid range"),
+        );
+        let b = Replace::new(
+            String::from("b"),
+            Range::parse(".230-.300").expect("valid range"),
+        );
+        let mut c = Replace::new(
+            String::from("c"),
+            Range::parse(".980-$").expect("valid range"),
+        );
+        c.pad = Some(5);
+
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&a, &b, &c],
+            &Silence::new(),
+            &Options {
+                pad: 20,
+                ..Options::default()
+            },
+        )?;
+
+        let ranges = applied.iter().map(|a| a.range.clone()).collect::<Vec<_>>();
+        // 20ms at 1000Hz in stereo is 40 samples on each side, but the gap between `a` and `b`
+        // is split evenly, and `c` uses its own padding and is clamped to the end.
+        assert_eq!(vec![160..430, 430..640, 1950..2000], ranges);
+        assert!(data[..160].iter().all(|s| *s == 100));
+        assert!(data[160..640].iter().all(|s| *s == 0));
+        assert!(data[640..1950].iter().all(|s| *s == 100));
+
+        // an isolated range grows symmetrically.
+        let mut data = vec![100i16; 2000];
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&a],
+            &Silence::new(),
+            &Options {
+                pad: 20,
+                ..Options::default()
+            },
+        )?;
+        assert_eq!(200 - 40..400 + 40, applied[0].range);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invert() -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut data = (0..1000).map(|i| i as i16).collect::<Vec<_>>();
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse(".100-.300").expect("valid range"),
+        );
+        let b = Replace::new(
+            String::from("b"),
+            Range::parse(".200-.400").expect("valid range"),
+        );
+
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&a, &b],
+            &Silence::new(),
+            &Options::default(),
+        )?;
+        invert(&mut data, &applied);
+
+        assert!(data[..100].iter().all(|s| *s == 0));
+        assert!(data[100..400]
+            .iter()
+            .enumerate()
+            .all(|(i, s)| *s == i as i16 + 100));
+        assert!(data[400..].iter().all(|s| *s == 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_replacements_min_silence() -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let options = Options {
+            min_silence: 20,
+            ..Options::default()
+        };
+
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse(".100-.200").expect("valid range"),
+        );
+        let close = Replace::new(
+            String::from("b"),
+            Range::parse(".215-.300").expect("valid range"),
+        );
+        let far = Replace::new(
+            String::from("b"),
+            Range::parse(".225-.300").expect("valid range"),
+        );
+
+        let mut data = vec![100i16; 1000];
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&close, &a],
+            &Silence::new(),
+            &options,
+        )?;
+        assert_eq!(215..300, applied[0].range);
+        assert_eq!(100..215, applied[1].range);
+        assert!(data[100..300].iter().all(|s| *s == 0));
+
+        let mut data = vec![100i16; 1000];
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&a, &far],
+            &Silence::new(),
+            &options,
+        )?;
+        assert_eq!(100..200, applied[0].range);
+        assert!(data[200..225].iter().all(|s| *s == 100));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_replacements_sample_bounds() -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        // NB: 1001 frames isn't a whole number of milliseconds at 44.1kHz, so this can't be
+        // expressed exactly with a time position.
+        let mut data = vec![100i16; 4000];
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse("s1001-s1500").expect("valid range"),
+        );
+
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            2000,
+            &[&a],
+            &Silence::new(),
+            &Options::default(),
+        )?;
+
+        assert_eq!(
+            vec![2002..3000],
+            applied.iter().map(|a| a.range.clone()).collect::<Vec<_>>()
+        );
+        assert!(data[..2002].iter().all(|s| *s == 100));
+        assert!(data[2002..3000].iter().all(|s| *s == 0));
+        assert!(data[3000..].iter().all(|s| *s == 100));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_replacements_audible_bounds() -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        // a clip with 200ms of leading and 100ms of trailing near-silence.
+        let mut clip = vec![5i16; 1000];
+        clip[200..900].iter_mut().for_each(|s| *s = 1000);
+
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse("^^-^^+00.100").expect("valid range"),
+        );
+        let b = Replace::new(
+            String::from("b"),
+            Range::parse("$$-00.100-$$").expect("valid range"),
+        );
+
+        let options = Options {
+            silence_threshold: 10,
+            ..Options::default()
+        };
+
+        let mut data = clip.clone();
+        let applied =
+            apply_replacements(&mut data, spec, 1000, &[&a, &b], &Silence::new(), &options)?;
+
+        assert_eq!(
+            vec![200..300, 800..900],
+            applied.iter().map(|a| a.range.clone()).collect::<Vec<_>>()
+        );
+
+        // with a threshold below the noise, the whole clip is audible.
+        let mut data = clip.clone();
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&a, &b],
+            &Silence::new(),
+            &Options::default(),
+        )?;
+
+        assert_eq!(
+            vec![0..100, 900..1000],
+            applied.iter().map(|a| a.range.clone()).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_replacements_continuous_tone() -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let tone = Tone::new();
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse(".100-.200").expect("valid range"),
+        );
+        let b = Replace::new(
+            String::from("b"),
+            Range::parse(".150-.300").expect("valid range"),
+        );
+        let c = Replace::new(
+            String::from("c"),
+            Range::parse(".300-.400").expect("valid range"),
+        );
+
+        let mut data = vec![100i16; 8000];
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            8000,
+            &[&c, &a, &b],
+            &tone,
+            &Options::default(),
+        )?;
+
+        // one continuous tone, without restarting at .150 or .300.
+        assert_eq!(tone.generate(800..3200, 8000), data[800..3200].to_vec());
+        assert_eq!(
+            vec![2400..3200, 800..1600, 1200..2400],
+            applied.iter().map(|a| a.range.clone()).collect::<Vec<_>>()
+        );
+        assert!(applied
+            .iter()
+            .all(|a| a.original == vec![100i16; a.range.len()]));
+
+        // separate tone settings are separate regions, which each start at phase 0.
+        let mut high = c.clone();
+        high.frequency = Some(2000f32);
+
+        let mut data = vec![100i16; 8000];
+        apply_replacements(
+            &mut data,
+            spec,
+            8000,
+            &[&a, &high],
+            &tone,
+            &Options::default(),
+        )?;
+
+        assert_eq!(tone.generate(800..1600, 8000), data[800..1600].to_vec());
+        let high = tone.with_tone(Some(2000f32), None).expect("tone");
+        assert_eq!(high.generate(2400..3200, 8000), data[2400..3200].to_vec());
+        assert!(data[1600..2400].iter().all(|s| *s == 100));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_replacements_untouched_originals() -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse(".100-.300").expect("valid range"),
+        );
+        let mut b = Replace::new(
+            String::from("b"),
+            Range::parse(".200-.400").expect("valid range"),
+        );
+        b.frequency = Some(2000f32);
+
+        // different tone settings overlap, so they are generated as separate regions.
+        let mut data = (0..1000).map(|i| i as i16).collect::<Vec<_>>();
+        let applied = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&a, &b],
+            &Tone::new(),
+            &Options::default(),
+        )?;
+
+        assert_eq!(2, applied.len());
+        assert_eq!((100..300).collect::<Vec<i16>>(), applied[0].original);
+        assert_eq!((200..400).collect::<Vec<i16>>(), applied[1].original);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_replacements_errors() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse("b1-b2").expect("valid range"),
+        );
+
+        let mut data = vec![0i16; 4000];
+        let e = apply_replacements(
+            &mut data,
+            spec,
+            1000,
+            &[&a],
+            &Silence::new(),
+            &Options::default(),
+        )
+        .expect_err("buffer length");
+        assert!(matches!(
+            e,
+            Error::BufferLength {
+                duration: 1000,
+                channels: 2,
+                len: 4000
+            }
+        ));
+
+        let e = apply_replacements(
+            &mut data,
+            spec,
+            2000,
+            &[&a],
+            &Silence::new(),
+            &Options::default(),
+        )
+        .expect_err("missing tempo");
+
+        match e {
+            Error::Replace { replace, error } => {
+                assert_eq!(a, *replace);
+                assert!(matches!(*error, Error::MissingTempo(..)));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let mut b = Replace::new(String::from("b"), Range::parse("^-$").expect("valid range"));
+        b.amplitude = Some(2f32);
+
+        let e = apply_replacements(
+            &mut data,
+            spec,
+            2000,
+            &[&b],
+            &Tone::new(),
+            &Options::default(),
+        )
+        .expect_err("bad amplitude");
+        assert_eq!("[b]{^-$}: amplitude must be in 0..1: 2", e.to_string());
+        assert!(matches!(
+            e,
+            Error::Replace { ref error, .. } if matches!(**error, Error::BadAmplitude(..))
+        ));
+    }
+}
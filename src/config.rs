@@ -1,8 +1,12 @@
 //! Models for a single configuration file.
 
-use crate::{Replace, Transcript};
+use crate::{utils, Bound, Error, Pos, Replace, Transcript};
 use relative_path::{RelativePath, RelativePathBuf};
-use std::slice;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    slice,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize)]
 pub struct ReplaceFile {
@@ -12,17 +16,109 @@ pub struct ReplaceFile {
     #[serde(skip_serializing_if = "Option::is_none")]
     transcript: Option<Transcript>,
     /// Replacements. If empty, file is clean.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_replace")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     replace: Vec<Replace>,
+    /// The file has been verified to not need any censoring.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    clean: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Deserialize replacements, either as a list or in the compact form of a transcript where every
+/// marked word has a range, like `"[a]{0-1.0} [b]{2.0-3.0}"`.
+fn deserialize_replace<'de, D>(deserializer: D) -> Result<Vec<Replace>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::{de::Error as _, Deserialize as _};
+
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum ReplaceList {
+        Compact(String),
+        List(Vec<Replace>),
+    }
+
+    let text = match ReplaceList::deserialize(deserializer)? {
+        ReplaceList::Compact(text) => text,
+        ReplaceList::List(list) => return Ok(list),
+    };
+
+    let transcript = Transcript::parse(&text).map_err(|e| D::Error::custom(e.to_string()))?;
+
+    if !transcript.missing.is_empty() {
+        return Err(D::Error::custom(format!(
+            "replacements without a range: {}",
+            transcript.missing.join(", ")
+        )));
+    }
+
+    Ok(transcript.replace)
+}
+
+/// The value of a single file in the map forms of [Files].
+///
+/// This is either a plain transcript, or a struct with explicit replacements and an optional
+/// transcript.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum FileValue {
+    Transcript(Transcript),
+    Entry {
+        /// Transcript of the recording.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        transcript: Option<Transcript>,
+        /// Replacements.
+        #[serde(default, deserialize_with = "deserialize_replace")]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        replace: Vec<Replace>,
+    },
+}
+
+impl FileValue {
+    /// Access the transcript of the file, if any.
+    pub fn transcript(&self) -> Option<&Transcript> {
+        match *self {
+            FileValue::Transcript(ref transcript) => Some(transcript),
+            FileValue::Entry { ref transcript, .. } => transcript.as_ref(),
+        }
+    }
+
+    /// Access the explicit replacements of the file.
+    pub fn replace(&self) -> &[Replace] {
+        match *self {
+            FileValue::Transcript(..) => &[],
+            FileValue::Entry { ref replace, .. } => replace,
+        }
+    }
+
+    /// Convert into a transcript and replacements.
+    fn into_parts(self) -> (Option<Transcript>, Vec<Replace>) {
+        match self {
+            FileValue::Transcript(transcript) => (Some(transcript), vec![]),
+            FileValue::Entry {
+                transcript,
+                replace,
+            } => (transcript, replace),
+        }
+    }
 }
 
+/// A map from file paths to their transcripts or replacements.
+pub type FileMap = linked_hash_map::LinkedHashMap<RelativePathBuf, FileValue>;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize)]
 #[serde(untagged)]
 pub enum Files {
     List(Vec<ReplaceFile>),
-    Map(linked_hash_map::LinkedHashMap<RelativePathBuf, Transcript>),
-    ListOfMaps(Vec<linked_hash_map::LinkedHashMap<RelativePathBuf, Transcript>>),
+    Map(FileMap),
+    ListOfMaps(Vec<FileMap>),
 }
 
 impl Files {
@@ -47,6 +143,185 @@ impl Files {
         }
     }
 
+    /// Sort all files by path.
+    ///
+    /// Files are left in their original order if any path uses enumeration, since the position
+    /// of each file determines what it is enumerated as.
+    pub fn sort(&mut self) {
+        if self.is_enumerated() {
+            return;
+        }
+
+        match *self {
+            Files::List(ref mut list) => list.sort(),
+            Files::Map(ref mut map) => sort_map(map),
+            Files::ListOfMaps(ref mut list) => {
+                for map in list.iter_mut() {
+                    sort_map(map);
+                }
+
+                list.sort();
+            }
+        }
+
+        fn sort_map(map: &mut FileMap) {
+            let mut entries = std::mem::take(map).into_iter().collect::<Vec<_>>();
+            entries.sort();
+            map.extend(entries);
+        }
+    }
+
+    /// Sort the explicit replacements of every file by where they start, and remove exact
+    /// duplicates.
+    ///
+    /// Replacements which start at an offset from the start of the file (`^+1.0` or `01.000`) come
+    /// first in the order they start, and the ones which start at an offset from the end of the
+    /// file (`$-1.0`) come last in the order they start. Starts which can't be placed in time
+    /// without the file are in between, grouped by kind: frames, bars and beats, and then offsets
+    /// from the audible start and end.
+    ///
+    /// The order of replacements doesn't affect the output, since their ranges are merged when
+    /// they are applied.
+    pub fn sort_replace(&mut self) {
+        match *self {
+            Files::List(ref mut list) => {
+                for file in list {
+                    sort(&mut file.replace);
+                }
+            }
+            Files::Map(ref mut map) => sort_map(map),
+            Files::ListOfMaps(ref mut list) => {
+                for map in list {
+                    sort_map(map);
+                }
+            }
+        }
+
+        fn sort_map(map: &mut FileMap) {
+            for (_, value) in map.iter_mut() {
+                if let FileValue::Entry {
+                    ref mut replace, ..
+                } = *value
+                {
+                    sort(replace);
+                }
+            }
+        }
+
+        fn sort(replace: &mut Vec<Replace>) {
+            replace.sort_by(|a, b| {
+                key(&a.range.start)
+                    .cmp(&key(&b.range.start))
+                    .then_with(|| a.cmp(b))
+            });
+            replace.dedup();
+        }
+
+        /// Key to sort a bound by, with the group it's in and its offset in that group.
+        fn key(bound: &Bound) -> (u8, u64) {
+            match *bound {
+                Bound::Start(ref offset) => (0, ms(offset.as_ref())),
+                Bound::Pos(ref pos) => (0, ms(Some(pos))),
+                Bound::Sample(frame) => (1, u64::from(frame)),
+                Bound::Beat(bar, beat) => (2, u64::from(bar) << 32 | u64::from(beat)),
+                Bound::AudibleStart(ref offset) => (3, ms(offset.as_ref())),
+                // NB: a larger offset from the end comes earlier.
+                Bound::AudibleEnd(ref offset) => (4, u64::MAX - ms(offset.as_ref())),
+                Bound::End(ref offset) => (5, u64::MAX - ms(offset.as_ref())),
+            }
+        }
+
+        fn ms(pos: Option<&Pos>) -> u64 {
+            pos.map_or(0, |pos| pos.as_samples(1000).map_or(u64::MAX, u64::from))
+        }
+    }
+
+    /// Test if any file uses enumeration.
+    fn is_enumerated(&self) -> bool {
+        self.iter().any(|(path, ..)| path.as_str().contains('$'))
+    }
+
+    /// Append all files from `other` to this collection.
+    ///
+    /// Files which are already present with the same content are skipped. A file which is present
+    /// in both collections with different content is an error, since one of them would otherwise
+    /// be lost.
+    ///
+    /// If the two collections are of different kinds, they are both converted into a list.
+    pub fn append(&mut self, mut other: Files) -> Result<(), Error> {
+        let existing = self
+            .iter()
+            .map(|(path, replace, transcript, clean)| (path, (replace, transcript, clean)))
+            .collect::<HashMap<_, _>>();
+
+        let mut duplicates = HashSet::new();
+
+        for (path, replace, transcript, clean) in other.iter() {
+            if let Some(e) = existing.get(path) {
+                if *e != (replace, transcript, clean) {
+                    return Err(Error::ConflictingFile(path.as_str().to_string()));
+                }
+
+                duplicates.insert(path.to_owned());
+            }
+        }
+
+        other.retain(|path| !duplicates.contains(path));
+
+        match (&mut *self, other) {
+            (Files::List(a), Files::List(b)) => a.extend(b),
+            (Files::Map(a), Files::Map(b)) => a.extend(b),
+            (Files::ListOfMaps(a), Files::ListOfMaps(b)) => a.extend(b),
+            (_, other) => {
+                let mut list = std::mem::take(self).into_list();
+                list.extend(other.into_list());
+                *self = Files::List(list);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retain only the files whose path matches the given predicate.
+    fn retain(&mut self, mut f: impl FnMut(&RelativePath) -> bool) {
+        match *self {
+            Files::List(ref mut list) => list.retain(|file| f(&file.path)),
+            Files::Map(ref mut map) => retain_map(map, &mut f),
+            Files::ListOfMaps(ref mut list) => {
+                for map in list.iter_mut() {
+                    retain_map(map, &mut f);
+                }
+
+                list.retain(|map| !map.is_empty());
+            }
+        }
+
+        fn retain_map(map: &mut FileMap, f: &mut impl FnMut(&RelativePath) -> bool) {
+            let entries = std::mem::take(map);
+            map.extend(entries.into_iter().filter(|(path, _)| f(path)));
+        }
+    }
+
+    /// Convert into a list of files.
+    fn into_list(self) -> Vec<ReplaceFile> {
+        let file = |(path, value): (RelativePathBuf, FileValue)| {
+            let (transcript, replace) = value.into_parts();
+
+            ReplaceFile {
+                path,
+                transcript,
+                replace,
+                clean: false,
+            }
+        };
+
+        match self {
+            Files::List(list) => list,
+            Files::Map(map) => map.into_iter().map(file).collect(),
+            Files::ListOfMaps(list) => list.into_iter().flatten().map(file).collect(),
+        }
+    }
+
     /// Insert the given transcript for the specified path.
     fn insert(&mut self, path: RelativePathBuf, transcript: Transcript) {
         match *self {
@@ -54,13 +329,14 @@ impl Files {
                 path,
                 transcript: Some(transcript),
                 replace: vec![],
+                clean: false,
             }),
             Files::Map(ref mut map) => {
-                map.insert(path, transcript);
+                map.insert(path, FileValue::Transcript(transcript));
             }
             Files::ListOfMaps(ref mut list) => {
-                let mut map = linked_hash_map::LinkedHashMap::new();
-                map.insert(path, transcript);
+                let mut map = FileMap::new();
+                map.insert(path, FileValue::Transcript(transcript));
                 list.push(map);
             }
         }
@@ -75,47 +351,55 @@ impl Default for Files {
 
 impl<'a> IntoIterator for &'a Files {
     type IntoIter = FilesIter<'a>;
-    type Item = (&'a RelativePath, Vec<&'a Replace>, Option<&'a Transcript>);
+    type Item = FilesItem<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
 
+/// A single file, with its replacements, transcript, and whether it is marked as clean.
+pub type FilesItem<'a> = (
+    &'a RelativePath,
+    Vec<&'a Replace>,
+    Option<&'a Transcript>,
+    bool,
+);
+
 /// An iterator over replacements.
 pub enum FilesIter<'a> {
     List(slice::Iter<'a, ReplaceFile>),
-    Map(linked_hash_map::Iter<'a, RelativePathBuf, Transcript>),
+    Map(linked_hash_map::Iter<'a, RelativePathBuf, FileValue>),
     ListOfMaps {
-        current: Option<linked_hash_map::Iter<'a, RelativePathBuf, Transcript>>,
-        it: slice::Iter<'a, linked_hash_map::LinkedHashMap<RelativePathBuf, Transcript>>,
+        current: Option<linked_hash_map::Iter<'a, RelativePathBuf, FileValue>>,
+        it: slice::Iter<'a, FileMap>,
     },
 }
 
 impl<'a> Iterator for FilesIter<'a> {
-    type Item = (&'a RelativePath, Vec<&'a Replace>, Option<&'a Transcript>);
+    type Item = FilesItem<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match *self {
+        return match *self {
             FilesIter::List(ref mut it) => {
                 let ReplaceFile {
-                    ref path,
-                    ref transcript,
-                    ref replace,
+                    path,
+                    transcript,
+                    replace,
+                    clean,
                 } = it.next()?;
-                Some((path, replace.iter().collect(), transcript.as_ref()))
+                Some((path, replace.iter().collect(), transcript.as_ref(), *clean))
             }
             FilesIter::Map(ref mut it) => {
-                let (ref path, ref transcript) = it.next()?;
-                Some((path, vec![], Some(transcript)))
+                let (path, value) = it.next()?;
+                Some(map_item(path, value))
             }
             FilesIter::ListOfMaps {
                 ref mut current,
                 ref mut it,
             } => loop {
-                if let Some((ref path, ref transcript)) = current.as_mut().and_then(|it| it.next())
-                {
-                    return Some((path, vec![], Some(transcript)));
+                if let Some((path, value)) = current.as_mut().and_then(|it| it.next()) {
+                    return Some(map_item(path, value));
                 }
 
                 *current = match it.next() {
@@ -123,6 +407,15 @@ impl<'a> Iterator for FilesIter<'a> {
                     None => return None,
                 }
             },
+        };
+
+        fn map_item<'a>(path: &'a RelativePathBuf, value: &'a FileValue) -> FilesItem<'a> {
+            (
+                path,
+                value.replace().iter().collect(),
+                value.transcript(),
+                false,
+            )
         }
     }
 }
@@ -140,6 +433,15 @@ pub struct ReplaceDir {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub file_extension: Option<String>,
+    /// Extension to use for output files. Defaults to the extension of the input file.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_extension: Option<String>,
+    /// Companion file of the directory, relative to the directory of the configuration file. This
+    /// is copied instead of the one next to the directory, like `ar2.oac` for `ar2`.
+    #[serde(default, alias = "oac")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub companion: Option<RelativePathBuf>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Files::is_empty")]
     pub files: Files,
@@ -153,26 +455,69 @@ impl ReplaceDir {
             prefix: None,
             suffix: None,
             file_extension: None,
+            output_extension: None,
+            companion: None,
             files: Files::List(vec![]),
         }
     }
 
     /// Insert the given file into the configuration.
+    ///
+    /// Files which are already present are left untouched.
     pub fn insert_file(
         &mut self,
         file_extension: Option<&str>,
-        mut file: RelativePathBuf,
+        file: RelativePathBuf,
         transcript: Transcript,
-    ) -> Result<(), failure::Error> {
-        let file_extension = self
-            .file_extension
-            .as_ref()
-            .map(|s| s.as_str())
-            .or(file_extension);
+    ) -> Result<(), Error> {
+        let file = self.strip_file(file_extension, file)?;
+
+        if self.files.iter().any(|(path, ..)| path == file) {
+            return Ok(());
+        }
+
+        self.files.insert(file, transcript);
+        Ok(())
+    }
+
+    /// Resolve the path of the file at `index` in this directory, relative to the directory.
+    ///
+    /// This replaces every `$` run with the enumeration, and applies the prefix, suffix, and
+    /// extension of this directory. The extension of this directory takes precedence over
+    /// `file_extension`.
+    pub fn resolve_file<'a>(
+        &self,
+        file_extension: Option<&str>,
+        index: usize,
+        file: &'a RelativePath,
+    ) -> Cow<'a, RelativePath> {
+        let mut path = Cow::Borrowed(file);
+
+        path = utils::path_enumeration(index, path);
+        path = utils::path_file_prefix(self.prefix.as_deref(), path);
+        path = utils::path_file_suffix(self.suffix.as_deref(), path);
+
+        if let Some(e) = self.file_extension.as_deref().or(file_extension) {
+            path = Cow::Owned(path.with_extension(e));
+        }
+
+        path
+    }
+
+    /// Strip the extension, prefix, and suffix of this directory from the given file, resulting in
+    /// the path used to identify it in the configuration.
+    ///
+    /// The extension of this directory takes precedence over `file_extension`.
+    fn strip_file(
+        &self,
+        file_extension: Option<&str>,
+        mut file: RelativePathBuf,
+    ) -> Result<RelativePathBuf, Error> {
+        let file_extension = self.file_extension.as_deref().or(file_extension);
 
         if let Some(e) = file_extension {
             if Some(e) != file.extension() {
-                failure::bail!("extension does not match");
+                return Err(Error::ExtensionMismatch(file.as_str().to_string()));
             }
 
             file = match file.file_stem() {
@@ -182,89 +527,123 @@ impl ReplaceDir {
         }
 
         if let Some(prefix) = self.prefix.as_ref() {
-            let mut name = match file.file_name() {
+            let name = match file.file_name() {
                 Some(name) => name,
-                None => failure::bail!("expected file name"),
+                None => return Err(Error::ExpectedFileName),
             };
 
-            if !name.starts_with(prefix) {
-                failure::bail!("bad prefix in file");
-            }
+            let name = match name.strip_prefix(prefix.as_str()) {
+                Some(name) => name,
+                None => return Err(Error::PrefixMismatch(file.as_str().to_string())),
+            };
 
-            name = &name[prefix.len()..];
             file = file.with_file_name(name);
         }
 
         if let Some(suffix) = self.suffix.as_ref() {
-            let mut name = match file.file_name() {
+            let name = match file.file_name() {
                 Some(name) => name,
-                None => failure::bail!("expected file name"),
+                None => return Err(Error::ExpectedFileName),
             };
 
-            if !name.ends_with(suffix) {
-                failure::bail!("bad prefix in file");
-            }
+            let name = match name.strip_suffix(suffix.as_str()) {
+                Some(name) => name,
+                None => return Err(Error::SuffixMismatch(file.as_str().to_string())),
+            };
 
-            name = &name[..(name.len() - suffix.len())];
             file = file.with_file_name(name);
         }
 
-        self.files.insert(file, transcript);
-        Ok(())
+        Ok(file)
     }
 
-    /// Test if the dir contains the given path.
-    pub fn contains(&self, path: &RelativePath) -> bool {
-        let stem = match path.file_stem() {
-            Some(stem) => stem,
-            None => return false,
-        };
-
-        if let Some(prefix) = self.prefix.as_ref() {
-            if !stem.starts_with(prefix) {
-                return false;
-            }
-        }
-
-        if let Some(suffix) = self.suffix.as_ref() {
-            if !stem.ends_with(suffix) {
-                return false;
-            }
-        }
-
-        if let Some(extension) = self.file_extension.as_ref() {
-            match path.extension() {
-                Some(e) if e == extension => {}
-                _ => return false,
-            }
-        }
+    /// Test if the files of `other` can be merged into this directory.
+    ///
+    /// This requires that both directories use the same settings, and that neither uses
+    /// enumeration since that depends on the position of each file.
+    fn can_merge(&self, other: &ReplaceDir) -> bool {
+        self.path == other.path
+            && self.prefix == other.prefix
+            && self.suffix == other.suffix
+            && self.file_extension == other.file_extension
+            && self.output_extension == other.output_extension
+            && self.companion == other.companion
+            && !self.files.is_enumerated()
+            && !other.files.is_enumerated()
+    }
 
-        true
+    /// Test if the given path matches the extension, prefix, and suffix of this directory.
+    ///
+    /// This applies the same rules as `insert_file`.
+    pub fn contains(&self, file_extension: Option<&str>, path: &RelativePath) -> bool {
+        self.strip_file(file_extension, path.to_owned()).is_ok()
     }
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Config {
+    /// Other configurations to include, relative to this configuration.
+    ///
+    /// Directories in included configurations use the same root as this configuration.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<RelativePathBuf>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub file_extension: Option<String>,
+    /// Extension to use for output files. Defaults to the extension of the input file.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_extension: Option<String>,
+    /// Generator to use for censored sections when none is specified on the command line, like
+    /// `tone` or `tone:square`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_generator: Option<String>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub dirs: Vec<ReplaceDir>,
 }
 
 impl Config {
+    /// Iterate over every file in this configuration, with its path resolved the same way as when
+    /// censoring.
+    ///
+    /// Paths are relative to the root of the configuration, which is the directory it's in.
+    pub fn resolved_files(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            &ReplaceDir,
+            RelativePathBuf,
+            Vec<&Replace>,
+            Option<&Transcript>,
+        ),
+    > {
+        let file_extension = self.file_extension.as_deref();
+
+        self.dirs.iter().flat_map(move |dir| {
+            dir.files
+                .iter()
+                .enumerate()
+                .map(move |(i, (path, replace, transcript, _))| {
+                    let path = dir.path.join(dir.resolve_file(file_extension, i, path));
+                    (dir, path, replace, transcript)
+                })
+        })
+    }
+
     /// Insert the given file.
-    pub fn insert_file<'a>(
-        &'a mut self,
+    pub fn insert_file(
+        &mut self,
         file_dir: &RelativePath,
         file: RelativePathBuf,
         transcript: Transcript,
-    ) -> Result<(), failure::Error> {
+    ) -> Result<(), Error> {
         let mut found = None;
 
         for (i, dir) in self.dirs.iter().enumerate() {
-            if dir.path == file_dir && dir.contains(&file) {
+            if dir.path == file_dir && dir.contains(self.file_extension.as_deref(), &file) {
                 found = Some(i);
                 break;
             }
@@ -296,9 +675,673 @@ impl Config {
         Ok(())
     }
 
+    /// Merge another configuration into this one.
+    ///
+    /// Directories with identical settings are merged, and a file which is in both
+    /// configurations with different content is an error. The file extensions of each
+    /// configuration are kept with their directories, so that they don't apply to the
+    /// directories of the other configuration. The default generator can't be
+    /// kept per directory, so it must agree between the two configurations.
+    pub fn merge(&mut self, mut other: Config) -> Result<(), Error> {
+        self.apply_defaults();
+        other.apply_defaults();
+
+        self.file_extension = merge_extension(
+            "file_extension",
+            self.file_extension.take(),
+            other.file_extension,
+        )?;
+        self.output_extension = merge_extension(
+            "output_extension",
+            self.output_extension.take(),
+            other.output_extension,
+        )?;
+
+        self.default_generator = match (self.default_generator.take(), other.default_generator) {
+            (Some(a), Some(b)) if a != b => {
+                return Err(Error::ConflictingSetting {
+                    name: "default_generator",
+                    a,
+                    b,
+                });
+            }
+            (Some(a), Some(_)) => Some(a),
+            (None, None) => None,
+            _ => return Err(Error::PartialSetting("default_generator")),
+        };
+
+        for dir in other.dirs {
+            match self.dirs.iter_mut().find(|d| d.can_merge(&dir)) {
+                Some(existing) => existing.files.append(dir.files)?,
+                None => self.dirs.push(dir),
+            }
+        }
+
+        return Ok(());
+
+        /// Only an extension which both configurations agree on is kept at the top level, since
+        /// it has already been applied to every directory.
+        fn merge_extension(
+            name: &'static str,
+            a: Option<String>,
+            b: Option<String>,
+        ) -> Result<Option<String>, Error> {
+            match (a, b) {
+                (Some(a), Some(b)) if a != b => Err(Error::ConflictingSetting { name, a, b }),
+                (a, b) if a == b => Ok(a),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    /// Include a base configuration into this one.
+    ///
+    /// Settings in this configuration take precedence over the ones in `base`. The top-level
+    /// extensions of each configuration are applied to its own directories first, so that they
+    /// don't apply to the directories of the other configuration.
+    pub fn include(&mut self, mut base: Config) {
+        self.apply_defaults();
+        base.apply_defaults();
+
+        self.file_extension = self.file_extension.take().or(base.file_extension);
+        self.output_extension = self.output_extension.take().or(base.output_extension);
+        self.default_generator = self.default_generator.take().or(base.default_generator);
+        self.dirs.extend(base.dirs);
+    }
+
+    /// Apply top-level settings to all directories which don't override them.
+    pub fn apply_defaults(&mut self) {
+        for dir in &mut self.dirs {
+            if dir.file_extension.is_none() {
+                dir.file_extension = self.file_extension.clone();
+            }
+
+            if dir.output_extension.is_none() {
+                dir.output_extension = self.output_extension.clone();
+            }
+        }
+    }
+
     /// Optimize configuration.
-    pub fn optimize(&mut self) -> Result<(), failure::Error> {
+    ///
+    /// This merges directories with identical settings, and sorts directories, the files within
+    /// them, and the replacements of each file, so that the serialized configuration is stable.
+    pub fn optimize(&mut self) -> Result<(), Error> {
+        let mut dirs = Vec::<ReplaceDir>::with_capacity(self.dirs.len());
+
+        for dir in std::mem::take(&mut self.dirs) {
+            match dirs.iter_mut().find(|d| d.can_merge(&dir)) {
+                Some(existing) => existing.files.append(dir.files)?,
+                None => dirs.push(dir),
+            }
+        }
+
+        self.dirs = dirs;
+
+        for dir in &mut self.dirs {
+            dir.files.sort_replace();
+            dir.files.sort();
+        }
+
         self.dirs.sort();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ReplaceFile};
+    use crate::Error;
+
+    #[test]
+    fn test_optimize_sorts_files() -> Result<(), Error> {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+dirs:
+- path: b
+  files:
+  - C: "[a]"
+  - A: "[b]"
+    B: "[c]"
+- path: a
+  files:
+  - Z$: "[a]"
+  - Y$: "[b]"
+"#,
+        )?;
+
+        config.optimize()?;
+
+        let files = config
+            .dirs
+            .iter()
+            .map(|d| {
+                let files = d.files.iter().map(|f| f.0.as_str()).collect::<Vec<_>>();
+                (d.path.as_str(), files)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![("a", vec!["Z$", "Y$"]), ("b", vec!["A", "B", "C"])],
+            files
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> Result<(), Error> {
+        let mut a: Config = serde_yaml::from_str(
+            r#"
+file_extension: wav
+dirs:
+- path: a
+  files:
+  - A: "[a]"
+"#,
+        )?;
+
+        let b: Config = serde_yaml::from_str(
+            r#"
+file_extension: wav
+dirs:
+- path: a
+  files:
+  - A: "[a]"
+  - B: "[b]"
+- path: b
+"#,
+        )?;
+
+        a.merge(b)?;
+        a.optimize()?;
+
+        assert_eq!(Some("wav"), a.file_extension.as_deref());
+        assert_eq!(2, a.dirs.len());
+
+        // the same file with the same content is only kept once.
+        let files = a.dirs[0]
+            .files
+            .iter()
+            .map(|f| f.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["A", "B"], files);
+
+        let c: Config = serde_yaml::from_str(
+            r#"
+file_extension: wav
+dirs:
+- path: a
+  files:
+  - A: "[b]"
+"#,
+        )?;
+        assert!(matches!(
+            a.clone().merge(c),
+            Err(Error::ConflictingFile(ref file)) if file == "A"
+        ));
+
+        let c: Config = serde_yaml::from_str("file_extension: WAV")?;
+        assert!(matches!(
+            a.clone().merge(c),
+            Err(Error::ConflictingSetting {
+                name: "file_extension",
+                a: ref x,
+                b: ref y,
+            }) if x == "wav" && y == "WAV"
+        ));
+
+        let c: Config = serde_yaml::from_str("file_extension: wav\ndefault_generator: tone")?;
+        assert!(matches!(
+            a.merge(c),
+            Err(Error::PartialSetting("default_generator"))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_keeps_settings_per_dir() -> Result<(), Error> {
+        let mut a: Config = serde_yaml::from_str(
+            r#"
+file_extension: wav
+output_extension: WAV
+dirs:
+- path: a
+  files:
+  - A: "[a]"
+"#,
+        )?;
+
+        let b: Config = serde_yaml::from_str(
+            r#"
+dirs:
+- path: a
+  files:
+  - B: "[b]"
+- path: b
+"#,
+        )?;
+
+        a.merge(b)?;
+        a.optimize()?;
+
+        assert_eq!(None, a.file_extension);
+        assert_eq!(None, a.output_extension);
+
+        let dirs = a
+            .dirs
+            .iter()
+            .map(|d| {
+                let files = d.files.iter().map(|f| f.0.as_str()).collect::<Vec<_>>();
+                (
+                    d.path.as_str(),
+                    d.file_extension.as_deref(),
+                    d.output_extension.as_deref(),
+                    files,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                ("a", None, None, vec!["B"]),
+                ("a", Some("wav"), Some("WAV"), vec!["A"]),
+                ("b", None, None, vec![]),
+            ],
+            dirs
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_file_multibyte() -> Result<(), Error> {
+        use super::ReplaceDir;
+        use crate::Transcript;
+        use relative_path::RelativePathBuf;
+
+        let mut dir = ReplaceDir::new(RelativePathBuf::from("dir"));
+        dir.prefix = Some(String::from("ä_"));
+        dir.suffix = Some(String::from("_ö"));
+
+        dir.insert_file(
+            Some("wav"),
+            RelativePathBuf::from("ä_FILE_ö.wav"),
+            Transcript::parse("[a]")?,
+        )?;
+
+        let files = dir.files.iter().map(|f| f.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["FILE"], files);
+
+        // names which only partially match the multi-byte prefix or suffix.
+        let e = dir
+            .insert_file(
+                Some("wav"),
+                RelativePathBuf::from("äFILE_ö.wav"),
+                Transcript::parse("[a]")?,
+            )
+            .expect_err("expected bad prefix");
+        assert!(matches!(e, Error::PrefixMismatch(ref file) if file == "äFILE_ö"));
+
+        let e = dir
+            .insert_file(
+                Some("wav"),
+                RelativePathBuf::from("ä_FILEö.wav"),
+                Transcript::parse("[a]")?,
+            )
+            .expect_err("expected bad suffix");
+        assert!(matches!(e, Error::SuffixMismatch(ref file) if file == "FILEö"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_contains_agrees_with_insert_file() -> Result<(), Error> {
+        use super::ReplaceDir;
+        use crate::Transcript;
+        use relative_path::{RelativePath, RelativePathBuf};
+
+        let mut dir = ReplaceDir::new(RelativePathBuf::from("dir"));
+        dir.prefix = Some(String::from("AR2_"));
+        dir.suffix = Some(String::from("_EN"));
+        dir.file_extension = Some(String::from("wav"));
+
+        assert!(dir.contains(None, RelativePath::new("AR2_FILE_EN.wav")));
+        assert!(!dir.contains(None, RelativePath::new("AR2_FILE_EN.ogg")));
+        assert!(!dir.contains(None, RelativePath::new("FILE_EN.wav")));
+        assert!(!dir.contains(None, RelativePath::new("AR2_FILE.wav")));
+
+        dir.insert_file(
+            None,
+            RelativePathBuf::from("AR2_FILE_EN.wav"),
+            Transcript::parse("[a]")?,
+        )?;
+
+        let files = dir.files.iter().map(|f| f.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["FILE"], files);
+
+        // without an extension, the suffix applies to the whole file name.
+        dir.file_extension = None;
+        assert!(!dir.contains(None, RelativePath::new("AR2_FILE_EN.wav")));
+        assert!(dir.contains(Some("wav"), RelativePath::new("AR2_FILE_EN.wav")));
+        assert!(dir.contains(None, RelativePath::new("AR2_FILE_EN")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_insert_file_uses_matching_dir() -> Result<(), Error> {
+        use crate::Transcript;
+        use relative_path::{RelativePath, RelativePathBuf};
+
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+file_extension: wav
+dirs:
+- path: dir
+  file_prefix: AR2_
+  suffix: _EN
+  files: []
+"#,
+        )?;
+
+        config.insert_file(
+            RelativePath::new("dir"),
+            RelativePathBuf::from("AR2_FILE_EN.wav"),
+            Transcript::parse("[a]")?,
+        )?;
+
+        assert_eq!(1, config.dirs.len());
+        let files = config.dirs[0]
+            .files
+            .iter()
+            .map(|f| f.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["FILE"], files);
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_round_trip() -> Result<(), Error> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+dirs:
+- path: a
+  files:
+  - path: A
+    clean: true
+  - path: B
+    transcript: "[b]"
+"#,
+        )?;
+
+        let clean = config.dirs[0]
+            .files
+            .iter()
+            .map(|f| (f.0.as_str(), f.3))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("A", true), ("B", false)], clean);
+
+        let yaml = serde_yaml::to_string(&config)?;
+        assert_eq!(1, yaml.matches("clean: true").count());
+        assert!(!yaml.contains("clean: false"));
+
+        let config2: Config = serde_yaml::from_str(&yaml)?;
+        assert_eq!(config.dirs, config2.dirs);
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_replacements() -> Result<(), Error> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+dirs:
+- path: a
+  files:
+    A: "[a]{^-$}"
+    B:
+      replace:
+      - kind: b
+        range: "01.000-02.000"
+    C:
+      transcript: "[c]"
+      replace:
+      - kind: d
+        range: "^-$"
+"#,
+        )?;
+
+        let files = config.dirs[0]
+            .files
+            .iter()
+            .map(|(path, replace, transcript, _)| {
+                let words = replace.iter().map(|r| r.word.as_str()).collect::<Vec<_>>();
+                (path.as_str(), words, transcript.is_some())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                ("A", vec![], true),
+                ("B", vec!["b"], false),
+                ("C", vec!["d"], true),
+            ],
+            files
+        );
+
+        let yaml = serde_yaml::to_string(&config)?;
+        let config2: Config = serde_yaml::from_str(&yaml)?;
+        assert_eq!(config.dirs, config2.dirs);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_defaults() -> Result<(), Error> {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+file_extension: wav
+output_extension: ogg
+dirs:
+- path: a
+- path: b
+  file_extension: flac
+"#,
+        )?;
+
+        config.apply_defaults();
+
+        let extensions = config
+            .dirs
+            .iter()
+            .map(|d| (d.file_extension.as_deref(), d.output_extension.as_deref()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![(Some("wav"), Some("ogg")), (Some("flac"), Some("ogg"))],
+            extensions
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_keeps_extensions_per_dir() -> Result<(), Error> {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+file_extension: flac
+output_extension: ogg
+dirs:
+- path: a
+"#,
+        )?;
+
+        let base: Config = serde_yaml::from_str(
+            r#"
+file_extension: wav
+dirs:
+- path: b
+"#,
+        )?;
+
+        config.include(base);
+        assert_eq!(Some("flac"), config.file_extension.as_deref());
+        assert_eq!(Some("ogg"), config.output_extension.as_deref());
+
+        let extensions = config
+            .dirs
+            .iter()
+            .map(|d| {
+                (
+                    d.path.as_str(),
+                    d.file_extension.as_deref(),
+                    d.output_extension.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![("a", Some("flac"), Some("ogg")), ("b", Some("wav"), None)],
+            extensions
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_optimize_sorts_replace() -> Result<(), Error> {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+dirs:
+- path: a
+  files:
+  - path: A
+    replace:
+    - {kind: b, range: "00:02.000-00:03.000"}
+    - {kind: c, range: "00:01.000-00:02.000"}
+    - {kind: a, range: "00:01.000-00:01.500"}
+    - {kind: b, range: "00:02.000-00:03.000"}
+    - {kind: d, range: "$-0.5-$"}
+    - {kind: e, range: "b2-b3"}
+    - {kind: f, range: "$-1.0-$"}
+    - {kind: g, range: "^+1.0-00:02.000"}
+    - {kind: h, range: "30.0-31.0"}
+    - {kind: i, range: "^-00:00.500"}
+- path: b
+  files:
+    B:
+      replace:
+      - {kind: x, range: "00:05.000-00:06.000"}
+      - {kind: y, range: "00:00.500-00:01.000"}
+"#,
+        )?;
+
+        config.optimize()?;
+
+        let replace = config
+            .dirs
+            .iter()
+            .flat_map(|d| d.files.iter())
+            .map(|(_, replace, ..)| replace.iter().map(|r| r.word.as_str()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                vec!["i", "a", "c", "g", "b", "h", "e", "f", "d"],
+                vec!["y", "x"]
+            ],
+            replace
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_replace() -> Result<(), Error> {
+        let compact: Config = serde_yaml::from_str(
+            r#"
+dirs:
+- path: a
+  files:
+  - path: A
+    replace: "[a]{^-01.000} [b]{02.000-03.000}"
+- path: b
+  files:
+    B:
+      replace: "[c]{$-0.500-$}"
+"#,
+        )?;
+
+        let list: Config = serde_yaml::from_str(
+            r#"
+dirs:
+- path: a
+  files:
+  - path: A
+    replace:
+    - {kind: a, range: "^-01.000"}
+    - {kind: b, range: "02.000-03.000"}
+- path: b
+  files:
+    B:
+      replace:
+      - {kind: c, range: "$-0.500-$"}
+"#,
+        )?;
+
+        assert_eq!(list.dirs, compact.dirs);
+
+        let yaml = serde_yaml::to_string(&compact)?;
+        let config: Config = serde_yaml::from_str(&yaml)?;
+        assert_eq!(compact.dirs, config.dirs);
+
+        let e = serde_yaml::from_str::<ReplaceFile>("path: A\nreplace: \"[a]{^-$} [b]\"\n")
+            .expect_err("missing range");
+        assert!(e.to_string().contains("without a range: b"), "{}", e);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolved_files() -> Result<(), Error> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+file_extension: wav
+dirs:
+- path: a
+  file_prefix: X_
+  files:
+  - path: A$$
+    replace: "[a]{^-01.000}"
+  - path: B$@
+- path: b/c
+  suffix: _1
+  file_extension: flac
+  files:
+    C: "hello [world]{^-$}"
+"#,
+        )?;
+
+        let files = config
+            .resolved_files()
+            .map(|(dir, path, replace, transcript)| {
+                (
+                    dir.path.as_str(),
+                    path.as_str().to_string(),
+                    replace.len(),
+                    transcript.map(|t| t.text.as_str()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                ("a", String::from("a/X_A01.wav"), 1, None),
+                ("a", String::from("a/X_BAB.wav"), 0, None),
+                (
+                    "b/c",
+                    String::from("b/c/C_1.flac"),
+                    0,
+                    Some("hello [world]{^-$}")
+                ),
+            ],
+            files
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+//! Errors raised by the library.
+
+use crate::{Bound, Range, Replace};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// An error raised while reading files, or while parsing or resolving configurations,
+/// transcripts, positions, and ranges.
+///
+/// The displayed messages are the same as the ones the command line prints.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// An I/O error.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// A WAV file which couldn't be read or written.
+    #[error("{0}")]
+    Wav(#[from] hound::Error),
+    /// A configuration which couldn't be parsed or serialized.
+    #[error("{0}")]
+    Yaml(#[from] serde_yaml::Error),
+    /// A configuration file which couldn't be read.
+    #[error("could not open configuration: {}: {error}", path.display())]
+    OpenConfig {
+        /// The path to the configuration.
+        path: PathBuf,
+        /// The error raised while reading it.
+        #[source]
+        error: io::Error,
+    },
+    /// A configuration file which couldn't be parsed.
+    #[error("failed to parse: {}: {error}", path.display())]
+    ParseConfig {
+        /// The path to the configuration.
+        path: PathBuf,
+        /// The error raised while parsing it.
+        #[source]
+        error: serde_yaml::Error,
+    },
+    /// An included configuration which couldn't be loaded.
+    #[error("failed to include: {}: {error}", path.display())]
+    Include {
+        /// The path to the included configuration.
+        path: PathBuf,
+        /// The error raised while loading it.
+        #[source]
+        error: Box<Error>,
+    },
+    /// Configurations which include each other, starting and ending with the same configuration.
+    #[error("include cycle: {}", display_cycle(.0))]
+    IncludeCycle(Vec<PathBuf>),
+    /// A configuration without a parent directory.
+    #[error("config does not have a parent directory")]
+    ExpectedParent,
+    /// A position which couldn't be parsed.
+    #[error("bad position: {0}")]
+    ParsePos(String),
+    /// A range which couldn't be parsed.
+    #[error("bad range: {0}")]
+    ParseRange(String),
+    /// An occurrence of a marked word which couldn't be parsed, like `[word#0]`, or which is
+    /// past the number of times the word occurs.
+    #[error("bad occurrence: {0}")]
+    ParseOccurrence(String),
+    /// A marker in a transcript which is never closed.
+    #[error("unclosed '{open}' starting at position {position}: {snippet}")]
+    Unclosed {
+        /// The character which opened the marker.
+        open: char,
+        /// The position in characters of the marker in the transcript.
+        position: usize,
+        /// A short snippet of the transcript, starting at the marker.
+        snippet: String,
+    },
+    /// The timing of a subtitle cue which couldn't be parsed.
+    #[error("bad cue timing: {0}")]
+    ParseCue(String),
+    /// A subtitle cue whose text couldn't be parsed as a transcript.
+    #[error("cue {index} at {range}: {error}")]
+    Cue {
+        /// The number of the cue, starting at 1.
+        index: usize,
+        /// When the cue is shown.
+        range: Range,
+        /// The error raised by the text of the cue.
+        #[source]
+        error: Box<Error>,
+    },
+    /// A bound which doesn't fit in the file.
+    #[error(
+        "position `{bound}` overflows with a sample rate of {sample_rate} and {channels} channel(s)"
+    )]
+    OutOfRange {
+        bound: Bound,
+        sample_rate: u32,
+        channels: u16,
+    },
+    /// A duration whose number of interleaved samples overflows.
+    #[error("duration of {duration} frames overflows with {channels} channel(s)")]
+    DurationOverflow { duration: u32, channels: u16 },
+    /// A range whose start comes after its end, in interleaved samples.
+    #[error("{start} (start) is not before {end} (end)")]
+    StartAfterEnd { start: usize, end: usize },
+    /// A bar and beat bound, resolved without a tempo.
+    #[error("bar and beat position `{0}` requires --bpm")]
+    MissingTempo(Bound),
+    /// An audible bound, resolved without the decoded audio.
+    #[error("audible position `{0}` requires the decoded audio")]
+    MissingAudio(Bound),
+    /// A buffer of samples which doesn't match the duration it's censored with.
+    #[error("expected {duration} frames of {channels} channel(s), but buffer has {len} samples")]
+    BufferLength {
+        duration: u32,
+        channels: u16,
+        len: usize,
+    },
+    /// A replacement which couldn't be applied.
+    #[error("{replace}: {error}")]
+    Replace {
+        /// The replacement.
+        replace: Box<Replace>,
+        /// The error raised by the replacement.
+        #[source]
+        error: Box<Error>,
+    },
+    /// A tone frequency which isn't positive and finite.
+    #[error("bad frequency: {0}")]
+    BadFrequency(f32),
+    /// A tone amplitude outside of 0..1.
+    #[error("amplitude must be in 0..1: {0}")]
+    BadAmplitude(f32),
+    /// A sound sample without any audio.
+    #[error("sample has no audio")]
+    EmptySample,
+    /// A file which doesn't have the extension of its directory.
+    #[error("extension does not match in file: {0}")]
+    ExtensionMismatch(String),
+    /// A file which doesn't have the prefix of its directory.
+    #[error("bad prefix in file: {0}")]
+    PrefixMismatch(String),
+    /// A file which doesn't have the suffix of its directory.
+    #[error("bad suffix in file: {0}")]
+    SuffixMismatch(String),
+    /// A modified directory which isn't an audio file in an archive, like `<rpf>/<awc>`.
+    #[error("expected directory to be an audio file in an archive, like `<rpf>/<awc>`: {0}")]
+    ExpectedArchive(String),
+    /// A path without a file name.
+    #[error("expected file name")]
+    ExpectedFileName,
+    /// A file which is listed more than once in the same directory, with different content.
+    #[error("conflicting entries for file: {0}")]
+    ConflictingFile(String),
+    /// Two configurations with different values for the same top-level setting.
+    #[error("conflicting `{name}` settings: `{a}` and `{b}`")]
+    ConflictingSetting {
+        name: &'static str,
+        a: String,
+        b: String,
+    },
+    /// A top-level setting which is only set in some of the configurations being merged.
+    #[error("`{0}` is only set in some configurations")]
+    PartialSetting(&'static str),
+}
+
+fn display_cycle(cycle: &[PathBuf]) -> String {
+    cycle
+        .iter()
+        .map(|p| Path::display(p).to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
@@ -0,0 +1,134 @@
+//! Writing FLAC, using the [flacenc] encoder.
+
+use flacenc::{component::BitRepr, error::Verify};
+use std::io;
+
+/// Encode interleaved 16-bit samples with the given spec as FLAC.
+///
+/// The spec must describe 16-bit integer samples, since that is what `data` holds.
+pub fn write<W>(mut w: W, spec: hound::WavSpec, data: &[i16]) -> io::Result<()>
+where
+    W: io::Write,
+{
+    if spec.sample_format != hound::SampleFormat::Int || spec.bits_per_sample != 16 {
+        return Err(invalid(format!(
+            "flac can only be written from 16-bit integer samples, not {} bit(s)",
+            spec.bits_per_sample
+        )));
+    }
+
+    if spec.channels == 0 || spec.channels > 8 {
+        return Err(invalid("flac supports between 1 and 8 channels"));
+    }
+
+    if spec.sample_rate == 0 || spec.sample_rate >= 1 << 20 {
+        return Err(invalid("unsupported sample rate for flac"));
+    }
+
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| invalid(e.to_string()))?;
+
+    let samples = data.iter().map(|s| i32::from(*s)).collect::<Vec<_>>();
+
+    let source = flacenc::source::MemSource::from_samples(
+        &samples,
+        usize::from(spec.channels),
+        16,
+        spec.sample_rate as usize,
+    );
+
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| match e {
+            flacenc::error::EncodeError::Source(e) => invalid(e.to_string()),
+            flacenc::error::EncodeError::Config(e) => invalid(e.to_string()),
+            e => invalid(format!("{:?}", e)),
+        })?;
+
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|e| invalid(e.to_string()))?;
+    w.write_all(sink.as_slice())
+}
+
+/// Construct an error for input which can't be encoded.
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write;
+    use md5::{Digest, Md5};
+
+    fn spec(channels: u16, bits_per_sample: u16) -> hound::WavSpec {
+        hound::WavSpec {
+            channels,
+            sample_rate: 44100,
+            bits_per_sample,
+            sample_format: hound::SampleFormat::Int,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<(), failure::Error> {
+        // a block and a half of a tone in the left channel, with full-scale samples and noise in
+        // the right channel, and silence after it.
+        let mut data = Vec::new();
+
+        for i in 0..6000 {
+            let tone = ((i as f32 * 0.05).sin() * 10000f32) as i16;
+            let noise = ((i * 7919) % 65536) as u16 as i16;
+            data.push(tone);
+            data.push(if i % 1000 == 0 { i16::MIN } else { noise });
+        }
+
+        data.extend(std::iter::repeat_n(0i16, 2 * 5000));
+
+        let mut out = Vec::new();
+        write(&mut out, spec(2, 16), &data)?;
+
+        // NB: decoded with claxon, which is independent of the encoder.
+        let mut reader = claxon::FlacReader::new(&out[..])?;
+        let info = reader.streaminfo();
+        assert_eq!(44100, info.sample_rate);
+        assert_eq!(2, info.channels);
+        assert_eq!(16, info.bits_per_sample);
+        assert_eq!(Some(11000), info.samples);
+
+        let bytes = data
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(&Md5::digest(&bytes)[..], &info.md5sum[..]);
+
+        let decoded = reader
+            .samples()
+            .map(|s| s.map(|s| s as i16))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(data, decoded);
+
+        // the tone and silence, at least, should compress.
+        assert!(out.len() < data.len() * 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_specs() {
+        let data = [0i16; 16];
+
+        for spec in &[spec(1, 8), spec(1, 24), spec(0, 16), spec(9, 16)] {
+            let e = write(Vec::new(), *spec, &data).expect_err("unsupported spec");
+            assert_eq!(std::io::ErrorKind::InvalidInput, e.kind());
+        }
+
+        let float = hound::WavSpec {
+            sample_format: hound::SampleFormat::Float,
+            ..spec(1, 16)
+        };
+
+        let e = write(Vec::new(), float, &data).expect_err("float samples");
+        assert_eq!(std::io::ErrorKind::InvalidInput, e.kind());
+    }
+}
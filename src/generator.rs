@@ -1,30 +1,640 @@
-use std::ops;
+use crate::{process, utils, Error};
+use std::{
+    collections::HashMap,
+    ops,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
 /// Noise generator
 pub trait Generator: Sync + Send {
+    /// Name of the generator, used in reports.
+    fn name(&self) -> &str;
+
     fn generate(&self, range: ops::Range<usize>, sample_rate: u32) -> Vec<i16>;
+
+    /// Produce the replacement for the given original, interleaved samples.
+    ///
+    /// By default this ignores the original and generates the same number of samples.
+    fn process(&self, original: &[i16], sample_rate: u32, _channels: u16) -> Vec<i16> {
+        self.generate(0..original.len(), sample_rate)
+    }
+
+    /// Produce the replacement for `range` of the interleaved samples in `data`, which holds the
+    /// whole file so that the generator can look at the audio surrounding the range.
+    ///
+    /// Regions are applied one after another, so `data` includes the ones already applied.
+    /// By default this processes the range on its own.
+    fn process_in_context(
+        &self,
+        data: &[i16],
+        range: ops::Range<usize>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Vec<i16> {
+        self.process(&data[range], sample_rate, channels)
+    }
+
+    /// Construct a copy of this generator with the given frequency and amplitude, where
+    /// specified.
+    ///
+    /// Returns `None` if the generator doesn't produce a tone.
+    fn with_tone(
+        &self,
+        _frequency: Option<f32>,
+        _amplitude: Option<f32>,
+    ) -> Option<Box<dyn Generator>> {
+        None
+    }
+
+    /// Construct a copy of this generator which uses the given seed for randomness.
+    ///
+    /// Returns `None` if the generator is not random.
+    fn with_seed(&self, _seed: u64) -> Option<Box<dyn Generator>> {
+        None
+    }
 }
 
-pub struct Silence(());
+impl<G: ?Sized + Generator> Generator for &G {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn generate(&self, range: ops::Range<usize>, sample_rate: u32) -> Vec<i16> {
+        (**self).generate(range, sample_rate)
+    }
+
+    fn process(&self, original: &[i16], sample_rate: u32, channels: u16) -> Vec<i16> {
+        (**self).process(original, sample_rate, channels)
+    }
+
+    fn process_in_context(
+        &self,
+        data: &[i16],
+        range: ops::Range<usize>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Vec<i16> {
+        (**self).process_in_context(data, range, sample_rate, channels)
+    }
+
+    fn with_tone(
+        &self,
+        frequency: Option<f32>,
+        amplitude: Option<f32>,
+    ) -> Option<Box<dyn Generator>> {
+        (**self).with_tone(frequency, amplitude)
+    }
+
+    fn with_seed(&self, seed: u64) -> Option<Box<dyn Generator>> {
+        (**self).with_seed(seed)
+    }
+}
+
+/// Constructs the generator to use for each file.
+///
+/// Files are processed in parallel, so generators which carry state, like the seed of a random
+/// generator, are constructed for each file instead of being shared between them.
+pub trait GeneratorFactory: Sync {
+    /// Construct the generator for the file written to `dest`.
+    fn for_file(&self, dest: &Path) -> Box<dyn Generator + '_>;
+}
+
+/// A factory which seeds random generators for each file from a seed for the whole run, so that
+/// the output doesn't depend on the order in which files are processed.
+///
+/// Generators which aren't random are shared as they are.
+pub struct Seeded<'a> {
+    generator: &'a dyn Generator,
+    seed: u64,
+}
+
+impl<'a> Seeded<'a> {
+    /// Construct a factory for the given generator and seed.
+    pub fn new(generator: &'a dyn Generator, seed: u64) -> Self {
+        Seeded { generator, seed }
+    }
+}
+
+impl GeneratorFactory for Seeded<'_> {
+    fn for_file(&self, dest: &Path) -> Box<dyn Generator + '_> {
+        match self.generator.with_seed(utils::path_seed(self.seed, dest)) {
+            Some(generator) => generator,
+            None => Box::new(self.generator),
+        }
+    }
+}
+
+/// Replaces censored sections with silence, optionally with a low-level noise floor.
+pub struct Silence {
+    /// Noise to fill the silence with instead of exact zeros, if any.
+    floor: Option<Noise>,
+}
 
 impl Silence {
     /// Construct a new generator that generates silence.
     pub fn new() -> Self {
-        Silence(())
+        Silence { floor: None }
+    }
+
+    /// Fill the silence with white noise of the given amplitude, from 0 to 1, instead of exact
+    /// zeros.
+    pub fn with_floor(self, amplitude: f32, seed: u64) -> Self {
+        Silence {
+            floor: Some(Noise::new(seed).with_amplitude(amplitude)),
+        }
+    }
+}
+
+impl Default for Silence {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Generator for Silence {
+    fn name(&self) -> &str {
+        "silence"
+    }
+
+    fn with_seed(&self, seed: u64) -> Option<Box<dyn Generator>> {
+        let floor = self.floor.as_ref()?;
+        Some(Box::new(Silence::new().with_floor(floor.amplitude, seed)))
+    }
+
+    fn generate(&self, range: ops::Range<usize>, sample_rate: u32) -> Vec<i16> {
+        match self.floor {
+            Some(ref floor) => floor.generate(range, sample_rate),
+            None => range.map(|_| i16::default()).collect::<Vec<_>>(),
+        }
+    }
+}
+
+/// Silence which is faded in and out from the surrounding audio, instead of cutting it off.
+///
+/// Each channel ramps from the sample right before the range down to silence, and back up to the
+/// sample right after it, which avoids clicks at the edges.
+pub struct CrossfadeSilence {
+    /// Length of each ramp, in milliseconds.
+    fade: u32,
+}
+
+impl CrossfadeSilence {
+    /// Construct a new generator which fades over 5 milliseconds on each side.
+    pub fn new() -> Self {
+        CrossfadeSilence { fade: 5 }
+    }
+
+    /// Modify the length of each ramp, in milliseconds.
+    pub fn with_fade(self, fade: u32) -> Self {
+        CrossfadeSilence { fade }
+    }
+}
+
+impl Default for CrossfadeSilence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Generator for CrossfadeSilence {
+    fn name(&self) -> &str {
+        "crossfade"
+    }
+
+    fn generate(&self, range: ops::Range<usize>, _: u32) -> Vec<i16> {
+        range.map(|_| i16::default()).collect::<Vec<_>>()
+    }
+
+    fn process_in_context(
+        &self,
+        data: &[i16],
+        range: ops::Range<usize>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Vec<i16> {
+        let channels = usize::from(channels.max(1));
+        let mut out = vec![0i16; range.len()];
+
+        let frames = range.len() / channels;
+        let fade = (u64::from(self.fade) * u64::from(sample_rate) / 1000) as usize;
+        let fade = usize::min(fade, frames / 2);
+
+        for c in 0..channels {
+            let before = range
+                .start
+                .checked_sub(channels)
+                .map(|i| f32::from(data[i + c]))
+                .unwrap_or_default();
+
+            let after = data
+                .get(range.end + c)
+                .map(|s| f32::from(*s))
+                .unwrap_or_default();
+
+            for i in 0..fade {
+                let level = 1f32 - (i + 1) as f32 / (fade + 1) as f32;
+                out[i * channels + c] = (before * level).round() as i16;
+                out[(frames - 1 - i) * channels + c] = (after * level).round() as i16;
+            }
+        }
+
+        out
+    }
+}
+
+/// Plays the original audio backwards.
+pub struct Reverse(());
+
+impl Reverse {
+    /// Construct a new generator that reverses the original audio.
+    pub fn new() -> Self {
+        Reverse(())
+    }
+}
+
+impl Default for Reverse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Generator for Reverse {
+    fn name(&self) -> &str {
+        "reverse"
+    }
+
     fn generate(&self, range: ops::Range<usize>, _: u32) -> Vec<i16> {
+        // NB: without the original there is nothing to reverse.
         range.map(|_| i16::default()).collect::<Vec<_>>()
     }
+
+    fn process(&self, original: &[i16], _: u32, channels: u16) -> Vec<i16> {
+        let frames = original.chunks_exact(usize::from(channels.max(1)));
+        let rest = frames.remainder();
+
+        // NB: frames are reversed as a whole, so every sample stays in its channel.
+        frames
+            .rev()
+            .flatten()
+            .chain(rest)
+            .cloned()
+            .collect::<Vec<_>>()
+    }
+}
+
+/// Band-limits the original audio, which makes it sound like it's coming over a radio.
+///
+/// This is a one-pole high-pass filter cascaded with a one-pole low-pass filter, applied to each
+/// channel separately. The result is scaled to have the same loudness as the original.
+pub struct Radio {
+    /// Cutoff of the high-pass filter, in Hz.
+    low: f32,
+    /// Cutoff of the low-pass filter, in Hz.
+    high: f32,
+}
+
+impl Radio {
+    /// Construct a new generator passing frequencies between 300Hz and 3000Hz.
+    pub fn new() -> Self {
+        Radio {
+            low: 300f32,
+            high: 3000f32,
+        }
+    }
+
+    /// Modify the band of frequencies which are let through.
+    pub fn with_band(self, low: f32, high: f32) -> Self {
+        Radio { low, high }
+    }
+}
+
+impl Default for Radio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Generator for Radio {
+    fn name(&self) -> &str {
+        "radio"
+    }
+
+    fn generate(&self, range: ops::Range<usize>, _: u32) -> Vec<i16> {
+        // NB: without the original there is nothing to filter.
+        range.map(|_| i16::default()).collect::<Vec<_>>()
+    }
+
+    fn process(&self, original: &[i16], sample_rate: u32, channels: u16) -> Vec<i16> {
+        use std::f32::consts::PI;
+
+        let channels = usize::from(channels.max(1));
+        let dt = 1f32 / sample_rate as f32;
+        let rc = 1f32 / (2f32 * PI * self.low);
+        let high_pass = rc / (rc + dt);
+        let low_pass = 1f32 - (-2f32 * PI * self.high * dt).exp();
+
+        let mut filtered = vec![0f32; original.len()];
+
+        for c in 0..channels {
+            let mut last_input = 0f32;
+            let mut high = 0f32;
+            let mut low = 0f32;
+
+            for i in (c..original.len()).step_by(channels) {
+                let input = f32::from(original[i]);
+                high = high_pass * (high + input - last_input);
+                low += low_pass * (high - low);
+                last_input = input;
+                filtered[i] = low;
+            }
+        }
+
+        let rms = |s: &mut dyn Iterator<Item = f32>| s.map(|s| s * s).sum::<f32>().sqrt();
+        let input = rms(&mut original.iter().map(|s| f32::from(*s)));
+        let output = rms(&mut filtered.iter().cloned());
+
+        let gain = if output > 0f32 { input / output } else { 0f32 };
+
+        filtered
+            .into_iter()
+            .map(|s| {
+                (s * gain)
+                    .round()
+                    .max(f32::from(i16::MIN))
+                    .min(f32::from(i16::MAX)) as i16
+            })
+            .collect::<Vec<_>>()
+    }
+}
+
+/// White noise.
+///
+/// The noise is fully determined by the seed, and by the number of sections generated so far.
+pub struct Noise {
+    /// Amplitude from 0..1
+    amplitude: f32,
+    seed: u64,
+    /// The number of sections generated, so that consecutive sections sound different.
+    sections: AtomicU64,
+}
+
+impl Noise {
+    /// Construct a new white noise generator with the given seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            amplitude: 0.3f32,
+            seed,
+            sections: AtomicU64::new(0),
+        }
+    }
+
+    /// Use the given amplitude, from 0 to 1.
+    pub fn with_amplitude(self, amplitude: f32) -> Self {
+        Self { amplitude, ..self }
+    }
 }
 
+impl Generator for Noise {
+    fn name(&self) -> &str {
+        "noise"
+    }
+
+    fn with_seed(&self, seed: u64) -> Option<Box<dyn Generator>> {
+        Some(Box::new(Noise {
+            amplitude: self.amplitude,
+            ..Noise::new(seed)
+        }))
+    }
+
+    fn generate(&self, range: ops::Range<usize>, _: u32) -> Vec<i16> {
+        let section = self.sections.fetch_add(1, Ordering::SeqCst);
+        let mut state = self.seed ^ section.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+
+        range
+            .map(|_| {
+                // uniform in -1..1, from the 24 most significant bits.
+                let value =
+                    (utils::splitmix64(&mut state) >> 40) as f32 / (1u64 << 23) as f32 - 1f32;
+                (value * self.amplitude * (i16::MAX as f32)) as i16
+            })
+            .collect()
+    }
+}
+
+/// Replaces censored sections with a user-provided sound, like a bleep, repeated as needed.
+pub struct Sample {
+    /// Mono samples of the sound.
+    samples: Arc<Vec<i16>>,
+    /// Sample rate of the sound.
+    sample_rate: u32,
+    /// Resample the sound to the sample rate of each file, instead of using it as-is.
+    resample: bool,
+    /// Milliseconds over which the end of the sound is faded into its start when it's repeated.
+    crossfade: u32,
+    /// Sound resampled to other sample rates.
+    resampled: Mutex<HashMap<u32, Arc<Vec<i16>>>>,
+}
+
+impl Sample {
+    /// Construct a new generator from the given mono samples.
+    pub fn new(samples: Vec<i16>, sample_rate: u32) -> Self {
+        Self {
+            samples: Arc::new(samples),
+            sample_rate,
+            resample: false,
+            crossfade: 0,
+            resampled: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Open the sound in the given WAV file, downmixing it to mono.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let r = hound::WavReader::open(path)?;
+        let spec = r.spec();
+        let data = r.into_samples::<i16>().collect::<Result<Vec<_>, _>>()?;
+
+        if data.is_empty() {
+            return Err(Error::EmptySample);
+        }
+
+        Ok(Self::new(
+            process::downmix_mono(&data, spec.channels),
+            spec.sample_rate,
+        ))
+    }
+
+    /// Resample the sound to the sample rate of each file.
+    pub fn with_resample(self, resample: bool) -> Self {
+        Self { resample, ..self }
+    }
+
+    /// Crossfade the end of the sound into its start over the given number of milliseconds each
+    /// time it's repeated, which avoids a click at every repetition.
+    pub fn with_crossfade(self, crossfade: u32) -> Self {
+        Self { crossfade, ..self }
+    }
+
+    /// The sample rate of the sound.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Get the samples of the sound to use at the given sample rate.
+    fn samples_for(&self, sample_rate: u32) -> Arc<Vec<i16>> {
+        if !self.resample || sample_rate == self.sample_rate {
+            return self.samples.clone();
+        }
+
+        let mut resampled = self.resampled.lock().expect("poisoned lock");
+
+        resampled
+            .entry(sample_rate)
+            .or_insert_with(|| {
+                let samples = process::resample(&self.samples, 1, self.sample_rate, sample_rate);
+                Arc::new(samples)
+            })
+            .clone()
+    }
+}
+
+impl Generator for Sample {
+    fn name(&self) -> &str {
+        "sample"
+    }
+
+    fn generate(&self, range: ops::Range<usize>, sample_rate: u32) -> Vec<i16> {
+        self.process(&vec![0; range.len()], sample_rate, 1)
+    }
+
+    fn process(&self, original: &[i16], sample_rate: u32, channels: u16) -> Vec<i16> {
+        let samples = self.samples_for(sample_rate);
+        let channels = usize::from(channels.max(1));
+
+        if samples.is_empty() {
+            return vec![0; original.len()];
+        }
+
+        let frames = original.len() / channels;
+        let fade = (u64::from(self.crossfade) * u64::from(sample_rate) / 1000) as usize;
+        let fade = usize::min(fade, samples.len() / 2);
+
+        // NB: when repeated, each repetition starts while the previous one fades out, so the sound
+        // is repeated with a shorter period, and the start of the period is faded in from the end.
+        let (period, repeated) = if fade > 0 && frames > samples.len() {
+            let period = samples.len() - fade;
+            let mut repeated = samples[..period].to_vec();
+
+            for (j, s) in repeated.iter_mut().take(fade).enumerate() {
+                let level = j as f32 / fade as f32;
+                let tail = f32::from(samples[period + j]);
+                *s = (f32::from(*s) * level + tail * (1f32 - level)).round() as i16;
+            }
+
+            (period, repeated)
+        } else {
+            (samples.len(), samples.to_vec())
+        };
+
+        // NB: the sound is repeated in every channel.
+        (0..original.len())
+            .map(|i| {
+                let frame = i / channels;
+
+                // NB: the first repetition plays from the unfaded start of the sound.
+                if frame < period {
+                    samples[frame]
+                } else {
+                    repeated[frame % period]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Construct a generator from its name, as used in configuration files.
+///
+/// This is either `silence`, `crossfade`, `reverse`, `radio`, `noise`, `tone`, or a tone with a
+/// specific waveform like `tone:square`.
+pub fn from_name(name: &str) -> Option<Box<dyn Generator>> {
+    match name {
+        "silence" => Some(Box::new(Silence::new())),
+        "noise" => Some(Box::new(Noise::new(0))),
+        "reverse" => Some(Box::new(Reverse::new())),
+        "crossfade" => Some(Box::new(CrossfadeSilence::new())),
+        "radio" => Some(Box::new(Radio::new())),
+        "tone" => Some(Box::new(Tone::new())),
+        other => {
+            let waveform = Waveform::parse(other.strip_prefix("tone:")?)?;
+            Some(Box::new(Tone::new().with_waveform(waveform)))
+        }
+    }
+}
+
+/// Calculate the phase, in radians, of the given sample for a tone at the given frequency.
+fn phase(i: usize, frequency: f32, sample_rate: f32) -> f32 {
+    use std::f32::consts::PI;
+    (i as f32) * frequency * 2f32 * PI / sample_rate
+}
+
+/// The shape of the waveform generated by a tone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Waveform {
+    #[default]
+    Sine,
+    Square,
+    Triangle,
+    Saw,
+}
+
+impl Waveform {
+    /// Parse a waveform from its name.
+    pub fn parse(s: &str) -> Option<Waveform> {
+        match s {
+            "sine" => Some(Waveform::Sine),
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "saw" => Some(Waveform::Saw),
+            _ => None,
+        }
+    }
+
+    /// Calculate the value of the waveform in the range -1..1 at the given phase, in radians.
+    fn sample(self, mag: f32) -> f32 {
+        use std::f32::consts::PI;
+
+        // position within the current period, from 0..1.
+        let t = (mag / (2f32 * PI)).fract();
+
+        let value = match self {
+            Waveform::Sine => mag.sin(),
+            Waveform::Square => {
+                if t < 0.5 {
+                    1f32
+                } else {
+                    -1f32
+                }
+            }
+            Waveform::Triangle => 1f32 - 4f32 * (t - 0.5).abs(),
+            Waveform::Saw => 2f32 * t - 1f32,
+        };
+
+        value.clamp(-1f32, 1f32)
+    }
+}
+
+/// A tone, which starts at phase 0 in every region it generates.
+#[derive(Debug, Clone, Copy)]
 pub struct Tone {
     /// Frequency of the tone.
     frequency: f32,
     /// Amplitude from 0..1
     amplitude: f32,
+    /// Shape of the waveform.
+    waveform: Waveform,
 }
 
 impl Tone {
@@ -33,23 +643,411 @@ impl Tone {
         Self {
             frequency: 1000f32,
             amplitude: 0.3f32,
+            waveform: Waveform::default(),
         }
     }
+
+    /// Use the given waveform for the tone.
+    pub fn with_waveform(self, waveform: Waveform) -> Self {
+        Self { waveform, ..self }
+    }
+
+    /// Use the given amplitude, from 0 to 1.
+    pub fn with_amplitude(self, amplitude: f32) -> Self {
+        Self { amplitude, ..self }
+    }
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Generator for Tone {
+    fn name(&self) -> &str {
+        "tone"
+    }
+
+    fn with_tone(
+        &self,
+        frequency: Option<f32>,
+        amplitude: Option<f32>,
+    ) -> Option<Box<dyn Generator>> {
+        Some(Box::new(Tone {
+            frequency: frequency.unwrap_or(self.frequency),
+            amplitude: amplitude.unwrap_or(self.amplitude),
+            ..*self
+        }))
+    }
+
     fn generate(&self, range: ops::Range<usize>, sample_rate: u32) -> Vec<i16> {
-        use std::f32::consts::PI;
+        let sample_rate = sample_rate as f32;
 
+        range
+            .into_iter()
+            .enumerate()
+            .map(|(i, _)| {
+                let mag = phase(i, self.frequency, sample_rate);
+                (self.waveform.sample(mag) * self.amplitude * (i16::MAX as f32)) as i16
+            })
+            .collect()
+    }
+}
+
+/// A tone made up of two sine waves at different frequencies.
+pub struct DualTone {
+    /// Frequency of the first tone.
+    f1: f32,
+    /// Frequency of the second tone.
+    f2: f32,
+    /// Amplitude from 0..1
+    amplitude: f32,
+}
+
+impl DualTone {
+    /// Construct a new dual tone generator with the given frequencies.
+    pub fn new(f1: f32, f2: f32) -> Self {
+        Self {
+            f1,
+            f2,
+            amplitude: 0.3f32,
+        }
+    }
+
+    /// Use the given amplitude, from 0 to 1.
+    pub fn with_amplitude(self, amplitude: f32) -> Self {
+        Self { amplitude, ..self }
+    }
+}
+
+impl Generator for DualTone {
+    fn name(&self) -> &str {
+        "dual-tone"
+    }
+
+    fn generate(&self, range: ops::Range<usize>, sample_rate: u32) -> Vec<i16> {
         let sample_rate = sample_rate as f32;
 
         range
             .into_iter()
             .enumerate()
             .map(|(i, _)| {
-                let mag = (i as f32) * self.frequency * 2f32 * PI / sample_rate;
-                (mag.sin() * self.amplitude * (std::i16::MAX as f32)) as i16
+                let a = phase(i, self.f1, sample_rate).sin();
+                let b = phase(i, self.f2, sample_rate).sin();
+                // NB: normalize the sum so that it doesn't clip.
+                let value = ((a + b) / 2f32).clamp(-1f32, 1f32);
+                (value * self.amplitude * (i16::MAX as f32)) as i16
             })
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        from_name, CrossfadeSilence, DualTone, Generator, GeneratorFactory, Noise, Radio, Reverse,
+        Sample, Seeded, Silence, Tone, Waveform,
+    };
+    use crate::Error;
+
+    #[test]
+    fn test_square() {
+        let tone = Tone::new().with_waveform(Waveform::Square);
+        let mut samples = tone.generate(0..8000, 8000);
+        samples.sort();
+        samples.dedup();
+
+        let peak = (0.3f32 * (i16::MAX as f32)) as i16;
+        assert_eq!(vec![-peak, peak], samples);
+    }
+
+    #[test]
+    fn test_triangle() {
+        let tone = Tone::new().with_waveform(Waveform::Triangle);
+        let samples = tone.generate(0..8000, 8000);
+
+        // the slope of a 1000Hz triangle at 8000Hz covers the full peak-to-peak range in 4
+        // samples, so no step should exceed a quarter of it (with some room for rounding).
+        let peak_to_peak = 2f32 * 0.3f32 * (i16::MAX as f32);
+        let max_step = (peak_to_peak / 4f32 * 1.01f32) as i32;
+
+        for w in samples.windows(2) {
+            let step = (i32::from(w[1]) - i32::from(w[0])).abs();
+            assert!(step <= max_step, "step {} exceeds {}", step, max_step);
+        }
+
+        let peak = (0.3f32 * (i16::MAX as f32)) as i16;
+        assert!(samples.iter().all(|s| s.abs() <= peak));
+    }
+
+    #[test]
+    fn test_dual_tone() {
+        let dual = DualTone::new(1000f32, 1500f32).generate(0..8000, 8000);
+        let single = Tone::new().generate(0..8000, 8000);
+
+        let peak = (0.3f32 * (i16::MAX as f32)) as i16;
+        assert!(dual.iter().all(|s| s.abs() <= peak));
+        assert!(dual.iter().any(|s| s.abs() > peak / 2));
+        assert_ne!(single, dual);
+
+        // 1000Hz and 1500Hz repeat together at 500Hz, every 16 samples at 8000Hz.
+        assert_eq!(&dual[..16], &dual[16..32]);
+        assert_ne!(&dual[..8], &dual[8..16]);
+    }
+
+    #[test]
+    fn test_from_name() {
+        let name = |s| from_name(s).map(|g| g.name().to_string());
+
+        assert_eq!(Some(String::from("silence")), name("silence"));
+        assert_eq!(Some(String::from("tone")), name("tone"));
+        assert_eq!(Some(String::from("tone")), name("tone:square"));
+        assert_eq!(None, name("tone:"));
+        assert_eq!(Some(String::from("noise")), name("noise"));
+        assert_eq!(Some(String::from("radio")), name("radio"));
+        assert_eq!(Some(String::from("crossfade")), name("crossfade"));
+        assert_eq!(None, name("pink"));
+
+        let square = from_name("tone:square").expect("valid generator");
+        let expected = Tone::new().with_waveform(Waveform::Square);
+        assert_eq!(
+            expected.generate(0..100, 8000),
+            square.generate(0..100, 8000)
+        );
+    }
+
+    #[test]
+    fn test_reverse() {
+        // stereo, with the left channel counting up and the right channel counting down.
+        let original = vec![1, -1, 2, -2, 3, -3];
+        let reversed = Reverse::new().process(&original, 1000, 2);
+        assert_eq!(vec![3, -3, 2, -2, 1, -1], reversed);
+
+        let mono = Reverse::new().process(&original, 1000, 1);
+        assert_eq!(vec![-3, 3, -2, 2, -1, 1], mono);
+
+        // the default implementation generates as usual.
+        let tone = Tone::new();
+        assert_eq!(tone.generate(0..6, 8000), tone.process(&original, 8000, 2));
+    }
+
+    #[test]
+    fn test_crossfade_silence() {
+        // stereo at 1000Hz, with a constant level on each side of the range.
+        let mut data = vec![1000i16; 40];
+        data.extend(vec![-2000i16; 40]);
+        let data = data
+            .chunks(2)
+            .flat_map(|f| vec![f[0], -f[0]])
+            .collect::<Vec<_>>();
+
+        let out = CrossfadeSilence::new().process_in_context(&data, 20..60, 1000, 2);
+        assert_eq!(40, out.len());
+
+        let left = out.iter().step_by(2).cloned().collect::<Vec<_>>();
+        let right = out.iter().skip(1).step_by(2).cloned().collect::<Vec<_>>();
+
+        // 5ms at 1000Hz is a ramp of 5 frames on each side.
+        assert_eq!(vec![833, 667, 500, 333, 167], &left[..5]);
+        assert_eq!(vec![-333, -667, -1000, -1333, -1667], &left[15..]);
+        assert!(left[5..15].iter().all(|s| *s == 0));
+        assert_eq!(left.iter().map(|s| -s).collect::<Vec<_>>(), right);
+
+        // ramps are limited to half the range, and fade to silence at the edges of the file.
+        let out = CrossfadeSilence::new().process_in_context(&data, 0..4, 1000, 2);
+        assert_eq!(vec![0, 0, 500, -500], out);
+
+        // the default implementation only sees the range.
+        let reverse = Reverse::new();
+        assert_eq!(
+            reverse.process(&data[20..26], 1000, 2),
+            reverse.process_in_context(&data, 20..26, 1000, 2)
+        );
+    }
+
+    #[test]
+    fn test_radio() {
+        let sample_rate = 16000;
+        let mut impulse = vec![0i16; 16000];
+        impulse[0] = 16000;
+
+        let response = Radio::new().process(&impulse, sample_rate, 1);
+        assert_eq!(impulse.len(), response.len());
+
+        // magnitude of the response at the given frequency.
+        let magnitude = |frequency: f32| {
+            let (re, im) = response
+                .iter()
+                .enumerate()
+                .fold((0f32, 0f32), |(re, im), (i, s)| {
+                    let phase = super::phase(i, frequency, sample_rate as f32);
+                    let s = f32::from(*s);
+                    (re + s * phase.cos(), im - s * phase.sin())
+                });
+
+            (re * re + im * im).sqrt()
+        };
+
+        let low = magnitude(50f32);
+        let pass = magnitude(1000f32);
+        let high = magnitude(7500f32);
+
+        assert!(pass > low * 3f32, "{} > {} * 3", pass, low);
+        assert!(pass > high * 1.5f32, "{} > {} * 1.5", pass, high);
+
+        // silence stays silent.
+        assert_eq!(
+            vec![0i16; 8],
+            Radio::new().process(&[0i16; 8], sample_rate, 2)
+        );
+    }
+
+    #[test]
+    fn test_noise() {
+        let a = Noise::new(42);
+        let b = Noise::new(42);
+
+        let first = a.generate(0..1000, 8000);
+        assert_eq!(first, b.generate(0..1000, 8000));
+        // consecutive sections differ, but in the same way for the same seed.
+        let second = a.generate(0..1000, 8000);
+        assert_ne!(first, second);
+        assert_eq!(second, b.generate(0..1000, 8000));
+
+        assert_ne!(first, Noise::new(43).generate(0..1000, 8000));
+
+        let peak = (0.3f32 * (i16::MAX as f32)) as i16;
+        assert!(first.iter().all(|s| s.abs() <= peak));
+        assert!(first.iter().any(|s| *s < -peak / 2));
+        assert!(first.iter().any(|s| *s > peak / 2));
+    }
+
+    #[test]
+    fn test_sample_crossfade() {
+        // a ramp, which has a large jump when repeated as-is.
+        let ramp = (0..100).map(|i| i * 10).collect::<Vec<i16>>();
+        let max_jump = |data: &[i16]| {
+            data.windows(2)
+                .map(|w| (i32::from(w[1]) - i32::from(w[0])).abs())
+                .max()
+                .unwrap_or_default()
+        };
+
+        let plain = Sample::new(ramp.clone(), 1000).generate(0..500, 1000);
+        assert_eq!(990, max_jump(&plain));
+
+        let faded = Sample::new(ramp.clone(), 1000)
+            .with_crossfade(10)
+            .generate(0..500, 1000);
+        assert_eq!(500, faded.len());
+        assert!(max_jump(&faded) <= 100, "{}", max_jump(&faded));
+        assert_eq!(&ramp[..90], &faded[..90]);
+
+        // a single repetition is played as-is.
+        let sample = Sample::new(ramp.clone(), 1000).with_crossfade(10);
+        assert_eq!(ramp, sample.generate(0..100, 1000));
+    }
+
+    #[test]
+    fn test_sample() {
+        let sample = Sample::new(vec![1, 2, 3], 1000);
+
+        // the sound is repeated, and duplicated across channels.
+        assert_eq!(vec![1, 2, 3, 1, 2], sample.generate(0..5, 1000));
+        assert_eq!(
+            vec![1, 1, 2, 2, 3, 3, 1, 1],
+            sample.process(&[0; 8], 1000, 2)
+        );
+        // sample rates are ignored unless resampling.
+        assert_eq!(vec![1, 2, 3, 1], sample.process(&[0; 4], 2000, 1));
+
+        let sample = sample.with_resample(true);
+        let resampled = sample.process(&[0; 6], 2000, 1);
+        assert_eq!(6, resampled.len());
+        assert_eq!(resampled, sample.process(&[0; 6], 2000, 1));
+        assert_eq!(1, sample.resampled.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_silence_floor() {
+        assert!(Silence::new()
+            .generate(0..100, 1000)
+            .iter()
+            .all(|s| *s == 0));
+        assert!(Silence::new().with_seed(1).is_none());
+
+        // -60 dBFS
+        let amplitude = 0.001f32;
+        let floor = Silence::new().with_floor(amplitude, 0);
+        let out = floor.generate(0..1000, 1000);
+
+        assert!(out.iter().any(|s| *s != 0));
+        assert!(out
+            .iter()
+            .all(|s| f32::from(s.abs()) <= amplitude * f32::from(i16::MAX)));
+
+        // the floor is deterministic for a given seed.
+        let a = floor.with_seed(42).expect("seeded").generate(0..100, 1000);
+        let b = floor.with_seed(42).expect("seeded").generate(0..100, 1000);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_seeded_factory() {
+        use rayon::prelude::*;
+        use std::path::PathBuf;
+
+        let noise = Noise::new(0);
+        let factory = Seeded::new(&noise, 42);
+
+        let paths = (0..64)
+            .map(|i| PathBuf::from(format!("out/{}.wav", i)))
+            .collect::<Vec<_>>();
+
+        let generate = |path: &PathBuf| factory.for_file(path).generate(0..100, 1000);
+
+        // NB: noise generated in parallel is the same as when it's generated in order, since
+        // every file gets its own generator.
+        let parallel = paths.par_iter().map(generate).collect::<Vec<_>>();
+        let sequential = paths.iter().map(generate).collect::<Vec<_>>();
+        assert_eq!(sequential, parallel);
+        assert_ne!(parallel[0], parallel[1]);
+
+        // generators which aren't random are shared.
+        let silence = Silence::new();
+        let factory = Seeded::new(&silence, 42);
+        assert_eq!("silence", factory.for_file(&paths[0]).name());
+        assert_eq!(
+            vec![0i16; 10],
+            factory.for_file(&paths[0]).generate(0..10, 1000)
+        );
+    }
+
+    #[test]
+    fn test_sample_open_errors() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!(
+            "batchcensor-test-sample-open-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("empty.wav");
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        hound::WavWriter::create(&path, spec)?.finalize()?;
+        assert!(matches!(Sample::open(&path), Err(Error::EmptySample)));
+        assert!(matches!(
+            Sample::open(&dir.join("missing.wav")),
+            Err(Error::Wav(hound::Error::IoError(..)))
+        ));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
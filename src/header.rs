@@ -0,0 +1,179 @@
+use crate::Error;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Header information of a WAV file, available without decoding any samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// The spec of the file.
+    pub spec: hound::WavSpec,
+    /// The duration of the file in frames, that is samples per channel.
+    pub duration: u32,
+    /// The total number of interleaved samples in the file.
+    pub len: u32,
+}
+
+impl Header {
+    /// Read the header of the file at the given path.
+    pub fn read(path: &Path) -> Result<Header, Error> {
+        let r = hound::WavReader::new(io::BufReader::new(File::open(path)?))?;
+        Ok(Header::from_reader(&r))
+    }
+
+    /// Construct a header from an already open reader.
+    pub fn from_reader<R: io::Read>(r: &hound::WavReader<R>) -> Header {
+        Header {
+            spec: r.spec(),
+            duration: r.duration(),
+            len: r.len(),
+        }
+    }
+
+    /// The number of bytes the samples of the file take up once decoded.
+    pub fn decoded_size(&self) -> u64 {
+        u64::from(self.len) * std::mem::size_of::<i16>() as u64
+    }
+}
+
+/// A cache of WAV headers, keyed by path.
+///
+/// This makes sure that each header is only read once, even if it is needed by multiple stages.
+#[derive(Debug, Default)]
+pub struct HeaderCache {
+    headers: Mutex<HashMap<PathBuf, Header>>,
+}
+
+impl HeaderCache {
+    /// Construct a new, empty header cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the header for the given path, reading it if it isn't cached.
+    pub fn get(&self, path: &Path) -> Result<Header, Error> {
+        if let Some(header) = self.lock().get(path) {
+            return Ok(*header);
+        }
+
+        let header = Header::read(path)?;
+        self.insert(path.to_owned(), header);
+        Ok(header)
+    }
+
+    /// Insert a header which has already been read, like when decoding the file.
+    pub fn insert(&self, path: PathBuf, header: Header) {
+        self.lock().insert(path, header);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Header>> {
+        self.headers.lock().expect("poisoned lock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Header, HeaderCache};
+    use crate::Error;
+
+    #[test]
+    fn test_read_header() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!(
+            "batchcensor-test-read-header-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("file.wav");
+
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut w = hound::WavWriter::create(&path, spec)?;
+
+        for _ in 0..200 {
+            w.write_sample(0i16)?;
+        }
+
+        w.finalize()?;
+
+        let header = Header::read(&path)?;
+        assert_eq!(spec, header.spec);
+        assert_eq!(100, header.duration);
+        assert_eq!(200, header.len);
+        assert_eq!(400, header.decoded_size());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_cache() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!(
+            "batchcensor-test-header-cache-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("file.wav");
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut w = hound::WavWriter::create(&path, spec)?;
+
+        for _ in 0..100 {
+            w.write_sample(0i16)?;
+        }
+
+        w.finalize()?;
+
+        let cache = HeaderCache::new();
+        let header = cache.get(&path)?;
+        assert_eq!(100, header.len);
+
+        // headers inserted when decoding are served without opening the file.
+        let inserted = Header {
+            duration: 50,
+            len: 50,
+            ..header
+        };
+        cache.insert(dir.join("inserted.wav"), inserted);
+
+        // the header is served from the cache once it has been read.
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(header, cache.get(&path)?);
+        assert_eq!(inserted, cache.get(&dir.join("inserted.wav"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_header_errors() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!(
+            "batchcensor-test-read-header-errors-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir)?;
+
+        let e = Header::read(&dir.join("missing.wav")).expect_err("missing file");
+        assert!(matches!(e, Error::Io(ref e) if e.kind() == std::io::ErrorKind::NotFound));
+
+        let path = dir.join("file.wav");
+        std::fs::write(&path, "not a wav file")?;
+        let e = Header::read(&path).expect_err("bad file");
+        assert!(matches!(e, Error::Wav(..)));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,14 +1,32 @@
+pub mod censor;
 pub mod config;
+mod error;
+#[cfg(feature = "flac")]
+pub mod flac;
 pub mod generator;
+mod header;
+mod loader;
+pub mod oiv;
 mod pos;
+pub mod process;
 mod range;
 mod replace;
+pub mod subtitles;
+pub mod task;
+mod tempo;
 mod transcript;
 pub mod utils;
+mod wordlist;
 
-pub use self::config::{Config, ReplaceDir, ReplaceFile};
-pub use self::generator::Generator;
+pub use self::config::{Config, FileValue, ReplaceDir, ReplaceFile};
+pub use self::error::Error;
+pub use self::generator::{Generator, GeneratorFactory};
+pub use self::header::{Header, HeaderCache};
+pub use self::loader::Loader;
 pub use self::pos::Pos;
-pub use self::range::Range;
+pub use self::range::{Bound, Range};
 pub use self::replace::Replace;
+pub use self::subtitles::Subtitles;
+pub use self::tempo::Tempo;
 pub use self::transcript::Transcript;
+pub use self::wordlist::Wordlist;
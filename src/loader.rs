@@ -0,0 +1,202 @@
+use crate::{Config, Error};
+use std::path::{Path, PathBuf};
+
+/// Loads configurations, resolving any includes.
+#[derive(Debug, Default)]
+pub struct Loader {
+    /// Configurations currently being loaded, used to detect include cycles.
+    stack: Vec<PathBuf>,
+    /// All configuration files which have been loaded, including included ones.
+    loaded: Vec<PathBuf>,
+}
+
+impl Loader {
+    /// All configuration files which have been loaded, including included ones.
+    pub fn loaded(&self) -> &[PathBuf] {
+        &self.loaded
+    }
+
+    /// Load the configuration at the given path.
+    ///
+    /// The paths to included configurations are resolved relative to the including file, and the
+    /// included configurations are merged into it. The directories of an included configuration
+    /// are not rebased on the directory it's in, so they resolve against the same root as the
+    /// directories of the including configuration.
+    pub fn load(&mut self, path: &Path) -> Result<Config, Error> {
+        let content = std::fs::read_to_string(path).map_err(|error| Error::OpenConfig {
+            path: path.to_owned(),
+            error,
+        })?;
+
+        // NB: some editors on Windows prefix files with a byte order mark.
+        let content = content.strip_prefix('\u{feff}').unwrap_or(&content);
+
+        let mut config: Config =
+            serde_yaml::from_str(content).map_err(|error| Error::ParseConfig {
+                path: path.to_owned(),
+                error,
+            })?;
+
+        self.loaded.push(path.to_owned());
+
+        if config.include.is_empty() {
+            return Ok(config);
+        }
+
+        let canonical = std::fs::canonicalize(path)?;
+
+        if let Some(i) = self.stack.iter().position(|p| *p == canonical) {
+            let mut cycle = self.stack[i..].to_vec();
+            cycle.push(canonical);
+            return Err(Error::IncludeCycle(cycle));
+        }
+
+        let parent = path.parent().ok_or(Error::ExpectedParent)?;
+
+        self.stack.push(canonical);
+
+        for include in std::mem::take(&mut config.include) {
+            let include = include.to_path(parent);
+
+            let included = self.load(&include).map_err(|error| Error::Include {
+                path: include.clone(),
+                error: Box::new(error),
+            })?;
+
+            config.include(included);
+        }
+
+        self.stack.pop();
+        config.optimize()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Loader;
+    use crate::Error;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> Result<PathBuf, Error> {
+        let dir = std::env::temp_dir().join(format!(
+            "batchcensor-test-loader-{}-{}",
+            name,
+            std::process::id()
+        ));
+
+        if dir.is_dir() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+
+        std::fs::create_dir_all(dir.join("base"))?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_include() -> Result<(), Error> {
+        let dir = temp_dir("include")?;
+
+        std::fs::write(
+            dir.join("config.yml"),
+            "include: [base/base.yml]\ndirs: [{path: a}]",
+        )?;
+        std::fs::write(
+            dir.join("base/base.yml"),
+            "file_extension: wav\ndirs: [{path: a}, {path: b}]",
+        )?;
+
+        let mut loader = Loader::default();
+        let config = loader.load(&dir.join("config.yml"))?;
+        assert_eq!(Some("wav"), config.file_extension.as_deref());
+        assert_eq!(
+            &[dir.join("config.yml"), dir.join("base/base.yml")][..],
+            loader.loaded()
+        );
+
+        // NB: `b` is not rebased on `base`, since included directories resolve against the root
+        // of the including configuration.
+        let dirs = config
+            .dirs
+            .iter()
+            .map(|d| (d.path.as_str(), d.file_extension.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![("a", None), ("a", Some("wav")), ("b", Some("wav"))],
+            dirs
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_errors() -> Result<(), Error> {
+        let dir = temp_dir("include-errors")?;
+        let config = dir.join("config.yml");
+        let base = dir.join("base/base.yml");
+
+        let e = Loader::default().load(&config).expect_err("missing config");
+        assert!(matches!(e, Error::OpenConfig { ref path, .. } if *path == config));
+
+        std::fs::write(&config, "include: [base/base.yml]")?;
+        std::fs::write(&base, "dirs: [")?;
+
+        let e = Loader::default().load(&config).expect_err("bad include");
+
+        match e {
+            Error::Include { path, error } => {
+                assert_eq!(base, path);
+                assert!(matches!(*error, Error::ParseConfig { ref path, .. } if *path == base));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        std::fs::write(&base, "include: [../config.yml]")?;
+        let e = Loader::default().load(&config).expect_err("include cycle");
+
+        let cycle = match e {
+            Error::Include { error, .. } => match *error {
+                Error::Include { error, .. } => *error,
+                e => panic!("unexpected error: {}", e),
+            },
+            e => panic!("unexpected error: {}", e),
+        };
+
+        match cycle {
+            Error::IncludeCycle(cycle) => {
+                let config = std::fs::canonicalize(&config)?;
+                let base = std::fs::canonicalize(&base)?;
+                assert_eq!(vec![config.clone(), base, config], cycle);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_bom() -> Result<(), Error> {
+        let dir = temp_dir("bom")?;
+
+        std::fs::write(
+            dir.join("config.yml"),
+            "\u{feff}file_extension: wav\r\ndirs:\r\n- path: a\r\n  files:\r\n  - A: \"hello [world]{01.000-02.000}\"\r\n",
+        )?;
+
+        let config = Loader::default().load(&dir.join("config.yml"))?;
+        assert_eq!(Some("wav"), config.file_extension.as_deref());
+
+        let (path, replace, transcript, _) = config.dirs[0].files.iter().next().expect("file");
+        assert_eq!("A", path.as_str());
+        assert!(replace.is_empty());
+
+        let transcript = transcript.expect("transcript");
+        assert_eq!("hello [world]{01.000-02.000}", transcript.text);
+        assert_eq!("world", transcript.replace[0].word);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,65 +1,180 @@
-use batchcensor::{generator, utils, Config, Generator, Pos, Replace, Transcript};
+use batchcensor::{
+    censor, generator, oiv, process,
+    task::{self, Task},
+    utils, Config, Generator, GeneratorFactory as _, Header, HeaderCache, Loader, Pos, Replace,
+    Subtitles, Tempo, Transcript, Wordlist,
+};
 use failure::ResultExt;
 use relative_path::{RelativePath, RelativePathBuf};
 use std::{
-    borrow::Cow,
-    collections::{BTreeMap, BTreeSet, HashMap},
-    fmt,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs::File,
     io,
     path::{Path, PathBuf},
+    sync::{mpsc, Condvar, Mutex},
+    time::Duration,
 };
 
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Mixed into the seed of each file for dithering, so that it is independent from the seed of
+/// random generators.
+const DITHER_SEED: u64 = 0x6469_7468_6572;
+
+/// Extensions of files treated as configurations in a configuration directory, unless
+/// `--config-ext` is specified.
+const CONFIG_EXTENSIONS: &[&str] = &["yml", "yaml"];
+
+/// Number of consecutive full-scale samples in a channel which are considered clipping.
+const CLIPPING_RUN: usize = 3;
+
+/// A file which is missing configuration, or which is silenced because of marked words without a
+/// range.
+struct Missing<'a> {
+    /// The configuration the file belongs to.
+    config_path: &'a Path,
+    /// The output directory of the directory the file is in.
+    dest_root: &'a Path,
+    /// The directory the file is in, relative to the root of the configuration.
+    dir_path: &'a RelativePath,
+    /// Extension to use for the output file, if it differs from the input.
+    output_extension: Option<&'a str>,
+    /// Marked words which are missing a range.
+    words: &'a [String],
+}
+
+/// Construct a task which copies an audio file without censoring it.
+///
+/// Audio is converted instead if it is written in a different format.
+fn copy_audio(path: PathBuf, dest: PathBuf, format: OutputFormat) -> Task<'static> {
+    match format {
+        OutputFormat::Wav => Task::Copy(path, dest),
+        OutputFormat::Flac => Task::Process(path, dest, vec![], None),
+    }
+}
+
+/// Run a single task.
+fn run_task(task: &Task<'_>, cx: &Context<'_>) -> Result<(), failure::Error> {
+    match *task {
+        Task::Copy(ref path, ref dest) => {
+            process_copy(path, dest)?;
+        }
+        Task::Process(ref path, ref dest, ref replace, generator) => {
+            let factory = generator::Seeded::new(generator.unwrap_or(cx.generator), cx.seed);
+            let generator = factory.for_file(dest);
+            process_single(path, dest, replace, &*generator, cx)?;
+        }
+        Task::Silence(ref path, ref dest) => {
+            process_silent(path, dest, cx)?;
+        }
+    }
 
-struct Missing<'a>(&'a Path, &'a Path, &'a RelativePath);
+    Ok(())
+}
 
-/// A single task that can be executed.
-pub enum Task<'a> {
-    /// Copy a single file.
-    Copy(PathBuf, PathBuf),
-    /// Regular processing with replacements.
-    Process(PathBuf, PathBuf, Vec<&'a Replace>),
-    // Silent processing.
-    Silence(PathBuf, PathBuf),
+/// Where to store the original audio of censored sections, for `--retain-originals`.
+struct Originals {
+    /// Directory to store clips in.
+    dir: PathBuf,
+    /// Output directories. Clips mirror the path of their output file relative to these.
+    outputs: Vec<PathBuf>,
 }
 
-impl<'a> Task<'a> {
-    fn run(&self, generator: &dyn Generator) -> Result<(), failure::Error> {
-        match *self {
-            Task::Copy(ref path, ref dest) => {
-                process_copy(path, dest)?;
-            }
-            Task::Process(ref path, ref dest, ref replace) => {
-                process_single(&path, &dest, replace, generator)?;
-            }
-            Task::Silence(ref path, ref dest) => {
-                process_silent(&path, &dest)?;
-            }
+/// Shared context used when running tasks.
+struct Context<'a> {
+    /// Generator used for censored sections.
+    generator: &'a dyn Generator,
+    /// Records of applied replacements, if a report was requested.
+    report: Option<Mutex<Vec<Record>>>,
+    /// Where to store the original audio of censored sections.
+    retain_originals: Option<Originals>,
+    /// Downmix all processed files to mono.
+    downmix_mono: bool,
+    /// Write only the given channel of all processed files, as mono.
+    pick_channel: Option<u16>,
+    /// Resample all processed files to the given sample rate.
+    resample: Option<u32>,
+    /// Headers of all files which have been opened.
+    headers: HeaderCache,
+    /// Tempo used to resolve positions expressed in bars and beats.
+    tempo: Option<Tempo>,
+    /// Warn about sources which appear to be clipped.
+    warn_clipping: bool,
+    /// The format to write processed audio in.
+    output_format: OutputFormat,
+    /// Error if a file has a larger fraction than this censored.
+    max_censor_ratio: Option<f32>,
+    /// Seed for random generators, from which a seed for each file is derived.
+    seed: u64,
+    /// Milliseconds to pad each censored range with on both sides.
+    pad: u32,
+    /// Keep only the marked ranges, and silence everything else.
+    invert: bool,
+    /// Bridge gaps between censored ranges which are shorter than this many milliseconds.
+    min_silence: u32,
+    /// Samples at or below this amplitude are considered silent when resolving `^^` and `$$`.
+    silence_threshold: i16,
+    /// Amplitude of the noise which silence is filled with, if any.
+    silence_floor: Option<f32>,
+    /// Limit on the decoded audio held at once by all running tasks.
+    memory: Option<MemoryLimit>,
+    /// Dither samples which are requantized when downmixing or resampling.
+    dither: bool,
+}
+
+/// A limit on the number of bytes reserved at once, shared between threads.
+struct MemoryLimit {
+    limit: u64,
+    reserved: Mutex<u64>,
+    released: Condvar,
+}
+
+impl MemoryLimit {
+    fn new(limit: u64) -> Self {
+        MemoryLimit {
+            limit,
+            reserved: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Reserve the given number of bytes, blocking until they fit within the limit.
+    ///
+    /// Reservations larger than the limit are granted once nothing else is reserved, so that
+    /// every task eventually runs.
+    fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let mut reserved = self.reserved.lock().expect("poisoned lock");
+
+        while *reserved > 0 && *reserved + bytes > self.limit {
+            reserved = self.released.wait(reserved).expect("poisoned lock");
         }
 
-        Ok(())
+        *reserved += bytes;
+        Reservation { limit: self, bytes }
     }
 }
 
-impl<'a> fmt::Display for Task<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Task::Copy(ref path, ref dest) => {
-                write!(fmt, "copy {} -> {}", path.display(), dest.display())?;
-            }
-            Task::Process(ref path, ref dest, ..) => {
-                write!(fmt, "process {} -> {}", path.display(), dest.display())?;
-            }
-            Task::Silence(ref path, ref dest) => {
-                write!(fmt, "silence {} -> {}", path.display(), dest.display())?;
-            }
-        }
+/// Bytes reserved from a [MemoryLimit], which are released when dropped.
+struct Reservation<'a> {
+    limit: &'a MemoryLimit,
+    bytes: u64,
+}
 
-        Ok(())
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        *self.limit.reserved.lock().expect("poisoned lock") -= self.bytes;
+        self.limit.released.notify_all();
     }
 }
 
+/// A record of all replacements applied to a single file.
+#[derive(Debug)]
+struct Record {
+    source: PathBuf,
+    dest: PathBuf,
+    replacements: Vec<censor::Applied>,
+}
+
 /// CLI options.
 fn opts() -> clap::App<'static, 'static> {
     clap::App::new("Batch Censor")
@@ -80,9 +195,24 @@ fn opts() -> clap::App<'static, 'static> {
                 .short("d")
                 .long("config-dir")
                 .value_name("dir")
-                .help("Configuration directory to use.")
+                .help(
+                    "Configuration directory to use. Configurations in it are loaded in sorted \
+                     order of their paths.",
+                )
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("config-ext")
+                .long("config-ext")
+                .value_name("ext")
+                .help(
+                    "Extension of files in --config-dir to load as configurations, like \
+                     censor.yml. Can be repeated (default: yml, yaml).",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
         .arg(
             clap::Arg::with_name("root")
                 .short("r")
@@ -96,7 +226,10 @@ fn opts() -> clap::App<'static, 'static> {
                 .short("o")
                 .long("output")
                 .value_name("dir")
-                .help("Where to build output.")
+                .help(
+                    "Where to build output. A `{config}` in the path is replaced with the name of \
+                     each configuration file, without its extension.",
+                )
                 .takes_value(true),
         )
         .arg(
@@ -104,17 +237,98 @@ fn opts() -> clap::App<'static, 'static> {
                 .long("list")
                 .help("List files which will be muted since they don't have a configuration."),
         )
+        .arg(
+            clap::Arg::with_name("list-format")
+                .long("list-format")
+                .value_name("format")
+                .help("Format of the --list output: text (default) to stderr, or json to stdout.")
+                .takes_value(true)
+                .requires("list"),
+        )
+        .arg(
+            clap::Arg::with_name("reencode-clean")
+                .long("reencode-clean")
+                .help(
+                    "Re-encode files with a transcript but nothing to censor, instead of copying \
+                     them, so that options like --downmix-mono and --resample apply to them too.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("limit")
+                .long("limit")
+                .value_name("n")
+                .help(
+                    "Only run the first <n> tasks, in order of their destination files. Useful to \
+                     quickly check the output of a configuration on a large tree.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("strict-ranges")
+                .long("strict-ranges")
+                .help(
+                    "Fail on ranges relative to the start or end of a file (`^` or `$`), so that \
+                     every range has an explicit start and end.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("deny-missing")
+                .long("deny-missing")
+                .help(
+                    "Fail instead of muting files which don't have a configuration, listing them \
+                     like --list does.",
+                ),
+        )
         .arg(
             clap::Arg::with_name("stats")
                 .long("stats")
                 .help("Show statistics about all configurations loaded."),
         )
+        .arg(clap::Arg::with_name("dry-run").long("dry-run").help(
+            "Print the tasks which would run and an estimate of the size of their output, \
+                     without running them.",
+        ))
         .arg(
             clap::Arg::with_name("init")
                 .long("init")
                 .help("Initialize an existing configuration, complete with missing files.")
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("init-inplace")
+                .long("init-inplace")
+                .help(
+                    "Like --init, but write each configuration with missing files back to where \
+                     it was loaded from.",
+                )
+                .conflicts_with("init"),
+        )
+        .arg(
+            clap::Arg::with_name("init-stub")
+                .long("init-stub")
+                .value_name("text")
+                .help(
+                    "Transcript to use for files added by --init or --init-inplace (default: \
+                     [missing]).",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("merge")
+                .long("merge")
+                .value_name("file")
+                .help("Merge all configurations into a single one, and write it to the given file.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("print-config")
+                .long("print-config")
+                .help(
+                    "Print the effective configurations, with includes and defaults resolved, \
+                     and exit without processing anything.",
+                )
+                .conflicts_with("merge"),
+        )
         .arg(
             clap::Arg::with_name("oiv-manifest")
                 .long("oiv-manifest")
@@ -127,30 +341,448 @@ fn opts() -> clap::App<'static, 'static> {
                 .long("tone")
                 .help("Replace censored sections with a 1000Hz tone instead of blank audio."),
         )
+        .arg(
+            clap::Arg::with_name("tone-waveform")
+                .long("tone-waveform")
+                .value_name("shape")
+                .help(
+                    "Waveform of the tone used by --tone: sine (default), square, triangle, or \
+                     saw. Implies --tone.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("dual-tone")
+                .long("dual-tone")
+                .value_name("f1,f2")
+                .help("Replace censored sections with a tone made up of two frequencies.")
+                .conflicts_with_all(&["tone", "tone-waveform"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("reverse")
+                .long("reverse")
+                .help("Replace censored sections with the original audio played backwards.")
+                .conflicts_with_all(&["tone", "tone-waveform", "dual-tone"]),
+        )
+        .arg(
+            clap::Arg::with_name("noise")
+                .long("noise")
+                .help("Replace censored sections with white noise.")
+                .conflicts_with_all(&["tone", "tone-waveform", "dual-tone", "reverse"]),
+        )
+        .arg(
+            clap::Arg::with_name("tone-amplitude")
+                .long("tone-amplitude")
+                .value_name("amplitude")
+                .help("Amplitude of --tone, --dual-tone, and --noise, from 0 to 1 (default: 0.3).")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("tone-amplitude-db")
+                .long("tone-amplitude-db")
+                .value_name("dBFS")
+                .help(
+                    "Amplitude of --tone, --dual-tone, and --noise in decibels relative to full \
+                     scale, like -6. Levels above 0 are clamped to avoid clipping.",
+                )
+                .conflicts_with("tone-amplitude")
+                .allow_hyphen_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("silence-floor")
+                .long("silence-floor")
+                .value_name("dBFS")
+                .help(
+                    "Fill silence with white noise at the given level in decibels relative to \
+                     full scale, like -60, instead of exact zeros.",
+                )
+                .conflicts_with_all(&[
+                    "tone",
+                    "tone-waveform",
+                    "dual-tone",
+                    "reverse",
+                    "noise",
+                    "crossfade",
+                    "radio",
+                    "radio-low",
+                    "radio-high",
+                    "sample",
+                ])
+                .allow_hyphen_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("crossfade")
+                .long("crossfade")
+                .help(
+                    "Fade censored sections in and out of silence over 5ms, instead of cutting \
+                     the audio off.",
+                )
+                .conflicts_with_all(&["tone", "tone-waveform", "dual-tone", "reverse", "noise"]),
+        )
+        .arg(
+            clap::Arg::with_name("radio")
+                .long("radio")
+                .help(
+                    "Replace censored sections with the original audio band-limited, like it's \
+                     coming over a radio.",
+                )
+                .conflicts_with_all(&["tone", "tone-waveform", "dual-tone", "reverse", "noise"]),
+        )
+        .arg(
+            clap::Arg::with_name("radio-low")
+                .long("radio-low")
+                .value_name("hz")
+                .help("Lowest frequency let through by --radio (default: 300). Implies --radio.")
+                .conflicts_with_all(&["tone", "tone-waveform", "dual-tone", "reverse", "noise"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("radio-high")
+                .long("radio-high")
+                .value_name("hz")
+                .help("Highest frequency let through by --radio (default: 3000). Implies --radio.")
+                .conflicts_with_all(&["tone", "tone-waveform", "dual-tone", "reverse", "noise"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("sample")
+                .long("sample")
+                .value_name("file")
+                .help(
+                    "Replace censored sections with the sound in the given WAV file, repeated as \
+                     needed. Every processed file must have the same sample rate as the sound.",
+                )
+                .conflicts_with_all(&[
+                    "tone",
+                    "tone-waveform",
+                    "dual-tone",
+                    "reverse",
+                    "noise",
+                    "crossfade",
+                    "radio",
+                    "radio-low",
+                    "radio-high",
+                ])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("sample-resample")
+                .long("sample-resample")
+                .help("Resample the sound from --sample to the sample rate of each file.")
+                .requires("sample"),
+        )
+        .arg(
+            clap::Arg::with_name("sample-crossfade")
+                .long("sample-crossfade")
+                .value_name("ms")
+                .help(
+                    "Crossfade the end of the sound from --sample into its start over the given \
+                     number of milliseconds each time it's repeated.",
+                )
+                .requires("sample")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("seed")
+                .long("seed")
+                .value_name("n")
+                .help(
+                    "Seed used for noise, which makes the output of every file the same across \
+                     runs. By default a random seed is used.",
+                )
+                .takes_value(true),
+        )
+        .arg(clap::Arg::with_name("no-ignore").long("no-ignore").help(
+            "Don't respect ignore files (.gitignore, .ignore, ...) when looking for files \
+                     to process, and include hidden files. By default files excluded by ignore \
+                     files and hidden files are skipped entirely.",
+        ))
+        .arg(
+            clap::Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("glob")
+                .help(
+                    "Skip files matching the given glob, relative to each directory. Excluded \
+                     files are neither processed nor copied.",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("mirror-all")
+                .long("mirror-all")
+                .help(
+                    "Also copy every file under the root of each configuration which isn't in a \
+                     configured directory, so that the output is a complete copy of the tree.",
+                )
+                .conflicts_with("jobs-from"),
+        )
+        .arg(
+            clap::Arg::with_name("jobs-from")
+                .long("jobs-from")
+                .value_name("file")
+                .help(
+                    "Only process the files listed in the given file, one per line. \
+                     Paths are relative to the current directory.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("report")
+                .long("report")
+                .value_name("file")
+                .help("Write a JSON report of every replacement applied to the given file.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("retain-originals")
+                .long("retain-originals")
+                .value_name("dir")
+                .help(
+                    "Keep the original audio of every censored section as clips in the given \
+                     directory, laid out like the output directory.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("downmix-mono")
+                .long("downmix-mono")
+                .help("Downmix processed and silenced files to mono by averaging their channels."),
+        )
+        .arg(
+            clap::Arg::with_name("pick-channel")
+                .long("pick-channel")
+                .value_name("index")
+                .help(
+                    "Write only the channel with the given index, starting at 0, of processed and \
+                     silenced files as mono.",
+                )
+                .conflicts_with("downmix-mono")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("resample")
+                .long("resample")
+                .value_name("hz")
+                .help("Resample processed and silenced files to the given sample rate.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("wordlist")
+                .long("wordlist")
+                .value_name("file")
+                .help(
+                    "Censor every word in the given file, one per line, wherever it has a \
+                     timestamp in a transcript, like `word{01.000-01.500}`.",
+                )
+                .takes_value(true),
+        )
+        .arg(clap::Arg::with_name("subtitles").long("subtitles").help(
+            "Also censor cues in .srt or .vtt subtitles next to each configured file, \
+                     like A.srt for A.wav, which have a marked word like [word] or a word from \
+                     --wordlist.",
+        ))
+        .arg(
+            clap::Arg::with_name("on-missing")
+                .long("on-missing")
+                .value_name("policy")
+                .help(
+                    "What to do with files that have marked words without a range: \
+                     silence (default), skip, or error.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("bpm")
+                .long("bpm")
+                .value_name("n")
+                .help("Tempo used to resolve positions expressed in bars and beats, like `b3.2`.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("time-signature")
+                .long("time-signature")
+                .value_name("n/d")
+                .help("Time signature used with --bpm (default: 4/4).")
+                .requires("bpm")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("warn-clipping")
+                .long("warn-clipping")
+                .help("Warn about processed files whose source appears to be clipped."),
+        )
+        .arg(
+            clap::Arg::with_name("invert")
+                .long("invert")
+                .help(
+                    "Keep only the marked ranges and silence everything else. Can't be combined \
+                     with other generators than silence.",
+                )
+                .conflicts_with_all(&[
+                    "tone",
+                    "tone-waveform",
+                    "dual-tone",
+                    "reverse",
+                    "noise",
+                    "crossfade",
+                    "radio",
+                    "radio-low",
+                    "radio-high",
+                    "sample",
+                ]),
+        )
+        .arg(clap::Arg::with_name("dither").long("dither").help(
+            "Use triangular dither instead of truncating or rounding samples when they \
+                     are requantized by --downmix-mono or --resample. Seeded by --seed.",
+        ))
+        .arg(
+            clap::Arg::with_name("pad")
+                .long("pad")
+                .value_name("ms")
+                .help(
+                    "Extend every censored range by the given number of milliseconds on each \
+                     side, without overlapping other ranges.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("min-silence")
+                .long("min-silence")
+                .value_name("ms")
+                .help(
+                    "Censor gaps between censored ranges which are shorter than the given number \
+                     of milliseconds, to avoid stuttering.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("silence-threshold")
+                .long("silence-threshold")
+                .value_name("dBFS")
+                .help(
+                    "Level in decibels relative to full scale at or below which audio is \
+                     considered silent, when resolving `^^` and `$$` in ranges (default: -60).",
+                )
+                .allow_hyphen_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("play")
+                .long("play")
+                .value_name("command")
+                .help(
+                    "Run the given command on the output once done, if exactly one file was \
+                     processed. `{}` in the command is replaced with the path of the output, \
+                     which is otherwise passed as the last argument. Arguments with spaces can be \
+                     quoted with `\"` or `'`.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("max-memory")
+                .long("max-memory")
+                .value_name("MB")
+                .help(
+                    "Limit the decoded audio held in memory at once to <MB> megabytes. Files are \
+                     queued until enough memory is available, and files larger than the limit \
+                     are processed one at a time.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("max-censor-ratio")
+                .long("max-censor-ratio")
+                .value_name("ratio")
+                .help(
+                    "Error if more than the given fraction of a file, from 0 to 1, is censored. \
+                     This catches mistakes like swapped minutes and seconds in a range.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("output-format")
+                .long("output-format")
+                .value_name("format")
+                .help(
+                    "Format to write audio files in: wav (default) or flac, which requires the \
+                     `flac` feature. Audio files which don't need censoring are converted as well.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("companion-ext")
+                .long("companion-ext")
+                .value_name("ext,...")
+                .help(
+                    "Extensions of companion files to copy along with each directory and audio \
+                     file, like `--companion-ext oac,xml` (default: oac).",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("no-companions")
+                .long("no-companions")
+                .alias("no-oac")
+                .help("Don't copy any companion files.")
+                .conflicts_with("companion-ext"),
+        )
+        .arg(
+            clap::Arg::with_name("no-progress")
+                .long("no-progress")
+                .help(
+                    "Don't show a progress bar. The progress bar is also hidden if stderr is \
+                     not a terminal.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("watch")
+                .long("watch")
+                .help("Watch configurations and source directories, and re-run when they change."),
+        )
 }
 
 /// Copy a single file.
+///
+/// The copy is skipped if the destination already has the same size and modification time as the
+/// source, which is preserved when copying.
 fn process_copy(path: &Path, dest: &Path) -> Result<(), failure::Error> {
-    let dest_parent = dest
-        .parent()
-        .ok_or_else(|| failure::format_err!("expected destination to have parent dir"))?;
+    let source = std::fs::metadata(path)?;
+    let modified = source.modified().ok();
 
-    if !dest_parent.is_dir() {
-        std::fs::create_dir_all(dest_parent)?;
+    if let (Ok(existing), Some(modified)) = (std::fs::metadata(dest), modified) {
+        if existing.len() == source.len() && existing.modified().ok() == Some(modified) {
+            return Ok(());
+        }
     }
 
-    std::fs::copy(path, dest)?;
-    Ok(())
+    write_atomic(dest, |temp| {
+        std::fs::copy(path, temp)?;
+
+        if let Some(modified) = modified {
+            File::options()
+                .write(true)
+                .open(temp)?
+                .set_modified(modified)?;
+        }
+
+        Ok(())
+    })
 }
 
-/// Process a single file and apply all the specified replacements.
-fn process_single(
-    path: &Path,
-    dest_path: &Path,
-    replaces: &[&Replace],
-    generator: &dyn Generator,
+/// Write the file at `dest` atomically.
+///
+/// The file is written to a temporary sibling of `dest` using `write`, and only moved into place
+/// once that succeeds, so an interrupted write never leaves a partial destination behind.
+fn write_atomic(
+    dest: &Path,
+    write: impl FnOnce(&Path) -> Result<(), failure::Error>,
 ) -> Result<(), failure::Error> {
-    let dest_parent = dest_path
+    let dest_parent = dest
         .parent()
         .ok_or_else(|| failure::format_err!("expected destination to have parent dir"))?;
 
@@ -158,49 +790,29 @@ fn process_single(
         std::fs::create_dir_all(dest_parent)?;
     }
 
-    if dest_path.is_file() {
-        std::fs::remove_file(dest_path)?;
-    }
-
-    std::fs::copy(path, dest_path)?;
-
-    let r = File::open(path)?;
-    let r = hound::WavReader::new(r)
-        .with_context(|_| failure::format_err!("failed to open file: {}", path.display()))?;
-    let s = r.spec();
-    let duration = r.duration();
-
-    let mut data = r.into_samples::<i16>().collect::<Result<Vec<i16>, _>>()?;
+    let name = dest
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| failure::format_err!("expected file name"))?;
 
-    for replace in replaces {
-        let range = &replace.range;
-        let start = pos(range.start.as_ref(), s, duration, 0) as usize;
-        let end = pos(range.end.as_ref(), s, duration, duration) as usize;
-
-        if start == end {
-            continue;
-        }
-
-        let generated = generator.generate(start..end, s.sample_rate);
-
-        if start >= end {
-            failure::bail!("{}: {} (start) is not before {} (end)", replace, start, end);
-        }
-
-        if start > data.len() || end > data.len() {
-            failure::bail!(
-                "{}: {}-{} out of range 0-{}",
-                replace,
-                start,
-                end,
-                data.len()
-            );
-        }
+    let temp = dest_parent.join(format!(".{}.tmp", name));
 
-        (&mut data[start..end]).copy_from_slice(&generated);
+    if let Err(e) = write(&temp) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
     }
 
-    let d = File::create(&dest_path)?;
+    std::fs::rename(&temp, dest)?;
+    Ok(())
+}
+
+/// Write the given samples to a new WAV file.
+fn write_wav(
+    path: &Path,
+    s: hound::WavSpec,
+    data: impl ExactSizeIterator<Item = i16>,
+) -> Result<(), failure::Error> {
+    let d = File::create(path)?;
     let mut w = hound::WavWriter::new(d, s)?;
 
     let mut writer = w.get_i16_writer(data.len() as u32);
@@ -210,519 +822,3564 @@ fn process_single(
     }
 
     writer.flush()?;
-    return Ok(());
-
-    fn pos(pos: Option<&Pos>, s: hound::WavSpec, duration: u32, default: u32) -> u32 {
-        match pos.as_ref() {
-            Some(pos) => {
-                let pos = pos
-                    .as_samples(s.sample_rate)
-                    .expect("samples overflow with sample rate")
-                    .checked_mul(s.channels as u32)
-                    .expect("overflow");
+    w.finalize()?;
+    Ok(())
+}
 
-                u32::min(pos, duration)
-            }
-            None => default,
+/// Write the given samples to a new file in the given format.
+fn write_audio(
+    path: &Path,
+    s: hound::WavSpec,
+    data: impl ExactSizeIterator<Item = i16>,
+    format: OutputFormat,
+) -> Result<(), failure::Error> {
+    match format {
+        OutputFormat::Wav => write_wav(path, s, data),
+        #[cfg(feature = "flac")]
+        OutputFormat::Flac => {
+            use std::io::Write;
+
+            let data = data.collect::<Vec<_>>();
+            let mut f = io::BufWriter::new(File::create(path)?);
+            batchcensor::flac::write(&mut f, s, &data)?;
+            f.flush()?;
+            Ok(())
         }
+        #[cfg(not(feature = "flac"))]
+        OutputFormat::Flac => failure::bail!("not built with flac support"),
     }
 }
 
-/// Replace the given file with silence.
-fn process_silent(path: &Path, dest_path: &Path) -> Result<(), failure::Error> {
-    if dest_path.is_file() {
-        // Ignore files that already exist.
-        return Ok(());
-    }
-
-    let dest_parent = dest_path
-        .parent()
-        .ok_or_else(|| failure::format_err!("expected destination to have parent dir"))?;
-
-    if !dest_parent.is_dir() {
-        std::fs::create_dir_all(dest_parent)?;
+/// Open the given WAV file for reading.
+///
+/// Returns `None` and emits a warning if the file doesn't contain any samples, in which case it
+/// should be copied as-is.
+fn open_wav(
+    path: &Path,
+    headers: &HeaderCache,
+) -> Result<Option<hound::WavReader<File>>, failure::Error> {
+    if std::fs::metadata(path)?.len() == 0 {
+        eprintln!("warning: {}: empty file, copying as-is", path.display());
+        return Ok(None);
     }
 
     let r = File::open(path)?;
     let r = hound::WavReader::new(r)
         .with_context(|_| failure::format_err!("failed to open file: {}", path.display()))?;
-    let s = r.spec();
-
-    let d = File::create(&dest_path)?;
-    let mut w = hound::WavWriter::new(d, s)?;
 
-    let mut writer = w.get_i16_writer(r.duration());
+    headers.insert(path.to_owned(), Header::from_reader(&r));
 
-    for _ in 0..(r.duration() * s.channels as u32) {
-        writer.write_sample(0i16);
+    if r.duration() == 0 {
+        eprintln!("warning: {}: no samples, copying as-is", path.display());
+        return Ok(None);
     }
 
-    writer.flush()?;
-    Ok(())
+    Ok(Some(r))
 }
 
-/// Write out the .oiv manifest for GTA V.
-fn write_oiv_manifest(
-    modified: &BTreeSet<RelativePathBuf>,
-    output: Option<&Path>,
-) -> Result<(), failure::Error> {
-    use std::{collections::btree_map::Entry, io::Write};
+/// Decode all samples in the given reader.
+///
+/// Decoding errors are reported with the path and the index of the sample that failed.
+fn read_samples(path: &Path, r: hound::WavReader<File>) -> Result<Vec<i16>, failure::Error> {
+    let mut data = Vec::with_capacity(r.len() as usize);
+
+    for (index, sample) in r.into_samples::<i16>().enumerate() {
+        let sample = sample.with_context(|_| {
+            failure::format_err!("failed decoding {} at sample {}", path.display(), index)
+        })?;
 
-    let mut archives = BTreeMap::new();
+        data.push(sample);
+    }
 
-    for m in modified {
-        let mut c = m.components();
-        let rpf = c.next().expect("expected root").as_str();
+    Ok(data)
+}
 
-        let archive = match archives.entry(rpf.clone()) {
-            Entry::Vacant(e) => e.insert(Archive {
-                path: format!("x64/audio/sfx/{}.rpf", rpf),
-                create_if_not_exists: "True",
-                ty: String::from("RPF7"),
-                add: Vec::new(),
-            }),
-            Entry::Occupied(e) => e.into_mut(),
-        };
+/// Read the header of the given WAV file, without decoding any samples.
+///
+/// Like `open_wav`, returns `None` if the file should be copied as-is.
+fn read_header(path: &Path, headers: &HeaderCache) -> Result<Option<Header>, failure::Error> {
+    if std::fs::metadata(path)?.len() == 0 {
+        eprintln!("warning: {}: empty file, copying as-is", path.display());
+        return Ok(None);
+    }
 
-        let audio_file = c.next().expect("expected audio file").as_str();
+    let header = headers
+        .get(path)
+        .with_context(|_| failure::format_err!("failed to open file: {}", path.display()))?;
 
-        archive.add.push(Add {
-            source: format!("{}.awc", m.display()),
-            value: format!("{}.awc", audio_file),
-        });
+    if header.duration == 0 {
+        eprintln!("warning: {}: no samples, copying as-is", path.display());
+        return Ok(None);
     }
 
-    let mut content = Content::default();
-    content.archives.extend(archives.into_iter().map(|v| v.1));
+    Ok(Some(header))
+}
 
-    match output {
-        Some(output) => {
-            let mut f = File::create(output)?;
-            write!(f, "{}", content)?;
+/// Process a single file and apply all the specified replacements.
+fn process_single(
+    path: &Path,
+    dest_path: &Path,
+    replaces: &[&Replace],
+    generator: &dyn Generator,
+    cx: &Context<'_>,
+) -> Result<(), failure::Error> {
+    let r = match open_wav(path, &cx.headers)? {
+        Some(r) => r,
+        None => return process_copy(path, dest_path),
+    };
+
+    // NB: the header was cached when the file was opened, so it's not read again to reserve
+    // memory for the decoded samples.
+    let _reservation = match cx.memory {
+        Some(ref memory) => Some(memory.reserve(cx.headers.get(path)?.decoded_size())),
+        None => None,
+    };
+
+    let s = r.spec();
+    let duration = r.duration();
+    let mut data = read_samples(path, r)?;
+
+    if cx.warn_clipping {
+        let runs = process::clipped_runs(&data, s.channels, CLIPPING_RUN);
+
+        if let Some(first) = runs.first() {
+            eprintln!(
+                "warning: {}: source is clipped in {} place(s), first at {}",
+                path.display(),
+                runs.len(),
+                Pos::from_samples(*first as u32, s.sample_rate)
+            );
+        }
+    }
+
+    // NB: when inverting, the replaced ranges are restored and everything else is silenced.
+    let silence = generator::Silence::new();
+    let generator = if cx.invert { &silence } else { generator };
+
+    let mut applied = censor::apply_replacements(
+        &mut data,
+        s,
+        duration,
+        replaces,
+        generator,
+        &censor::Options {
+            tempo: cx.tempo.as_ref(),
+            pad: cx.pad,
+            min_silence: cx.min_silence,
+            silence_threshold: cx.silence_threshold,
+        },
+    )?;
+
+    for warning in empty_range_warnings(path, replaces, &applied) {
+        eprintln!("{}", warning);
+    }
+
+    // NB: files without replacements are only re-encoded, and are kept as they are.
+    if cx.invert && !replaces.is_empty() {
+        censor::invert(&mut data, &applied);
+    }
+
+    if let Some(max_censor_ratio) = cx.max_censor_ratio {
+        let ratio = censor::censored_ratio(&applied, data.len());
+        let ratio = if cx.invert && !replaces.is_empty() {
+            1f32 - ratio
+        } else {
+            ratio
+        };
+
+        if ratio > max_censor_ratio {
+            let channels = u32::from(s.channels);
+
+            let ranges = applied
+                .iter()
+                .map(|a| {
+                    let start = Pos::from_samples(a.range.start as u32 / channels, s.sample_rate);
+                    let end = Pos::from_samples(a.range.end as u32 / channels, s.sample_rate);
+                    format!("[{}]{{{}-{}}}", a.word, start, end)
+                })
+                .collect::<Vec<_>>();
+
+            failure::bail!(
+                "{}: {:.1}% of the file is censored, which is more than --max-censor-ratio {}: {}",
+                path.display(),
+                ratio * 100f32,
+                max_censor_ratio,
+                ranges.join(", ")
+            );
+        }
+    }
+
+    if let Some(retain_originals) = cx.retain_originals.as_ref() {
+        for a in &applied {
+            retain_original(retain_originals, dest_path, s, a.range.clone(), &a.original)?;
+        }
+    }
+
+    if let Some(report) = cx.report.as_ref() {
+        // NB: the original samples are not part of the report.
+        for a in &mut applied {
+            a.original = Vec::new();
+        }
+
+        report.lock().expect("poisoned lock").push(Record {
+            source: path.to_owned(),
+            dest: dest_path.to_owned(),
+            replacements: applied,
+        });
+    }
+
+    // NB: seeded by the destination, like random generators, so that the output doesn't depend
+    // on the order in which files are processed.
+    let mut dither = process::Dither::new(utils::path_seed(cx.seed ^ DITHER_SEED, dest_path));
+    let mut quantize = |sample: f64, round: bool| {
+        if cx.dither {
+            dither.quantize(sample)
+        } else if round {
+            sample.round() as i16
+        } else {
+            sample as i16
+        }
+    };
+
+    // NB: downmixing happens after replacements, which are resolved in the original channels.
+    let (s, data) = if cx.downmix_mono {
+        let data = process::downmix_mono_with(&data, s.channels, |s| quantize(s, false));
+        (hound::WavSpec { channels: 1, ..s }, data)
+    } else if let Some(channel) = cx.pick_channel {
+        let data = match process::pick_channel(&data, s.channels, channel) {
+            Some(data) => data,
+            None => failure::bail!(bad_channel(path, channel, s.channels)),
+        };
+
+        (hound::WavSpec { channels: 1, ..s }, data)
+    } else {
+        (s, data)
+    };
+
+    let (s, data) = match cx.resample {
+        Some(sample_rate) => {
+            let data = process::resample_with(&data, s.channels, s.sample_rate, sample_rate, |s| {
+                quantize(s, true)
+            });
+            (hound::WavSpec { sample_rate, ..s }, data)
+        }
+        None => (s, data),
+    };
+
+    write_atomic(dest_path, |temp| {
+        write_audio(temp, s, data.into_iter(), cx.output_format)
+    })
+}
+
+/// Warnings for replacements which weren't applied since their range is empty, so that a timestamp
+/// which censors nothing doesn't go unnoticed.
+fn empty_range_warnings(
+    path: &Path,
+    replaces: &[&Replace],
+    applied: &[censor::Applied],
+) -> Vec<String> {
+    censor::skipped(replaces, applied)
+        .into_iter()
+        .map(|r| {
+            format!(
+                "warning: {}: {} is empty, so nothing is censored",
+                path.display(),
+                r
+            )
+        })
+        .collect()
+}
+
+/// Remove replacements which are exact duplicates of an earlier one, like the ones repeated in
+/// merged configurations, keeping the first of each.
+fn dedup_replace(replace: &mut Vec<&Replace>) {
+    let mut seen = BTreeSet::new();
+    replace.retain(|r| seen.insert(*r));
+}
+
+/// Write an estimate of the size of the output of all tasks, returning the estimated total in
+/// bytes.
+fn write_estimate(
+    out: &mut dyn io::Write,
+    tasks: &[Task<'_>],
+    headers: &HeaderCache,
+) -> io::Result<u64> {
+    let mut kinds = BTreeMap::<_, (u64, u64)>::new();
+
+    for task in tasks {
+        let (files, bytes) = kinds.entry(task.kind()).or_default();
+        *files += 1;
+        *bytes += task.estimated_size(headers);
+    }
+
+    writeln!(out, "# Estimated output (--dry-run)")?;
+
+    for (kind, (files, bytes)) in &kinds {
+        writeln!(
+            out,
+            "{} - {} file(s), {}",
+            kind,
+            files,
+            indicatif::HumanBytes(*bytes)
+        )?;
+    }
+
+    let total = kinds.values().map(|(_, bytes)| bytes).sum();
+    writeln!(out, "total - {}", indicatif::HumanBytes(total))?;
+    Ok(total)
+}
+
+/// Run the `--play` command on the given output.
+fn play(command: &str, path: &Path) -> Result<(), failure::Error> {
+    let args = split_command(command)?;
+    let mut args = args.iter();
+
+    let program = match args.next() {
+        Some(program) => program,
+        None => failure::bail!("empty --play command"),
+    };
+
+    let mut command = std::process::Command::new(program);
+    let mut substituted = false;
+
+    for arg in args {
+        if arg.contains("{}") {
+            command.arg(arg.replace("{}", &path.to_string_lossy()));
+            substituted = true;
+        } else {
+            command.arg(arg);
+        }
+    }
+
+    if !substituted {
+        command.arg(path);
+    }
+
+    let status = command
+        .status()
+        .with_context(|_| failure::format_err!("failed to run --play command: {}", program))?;
+
+    if !status.success() {
+        failure::bail!("--play command failed: {}: {}", program, status);
+    }
+
+    Ok(())
+}
+
+/// Split a command into its arguments on whitespace.
+///
+/// Arguments can be quoted with `"` or `'` to include whitespace. Backslashes are kept as-is, so
+/// that Windows paths don't have to be escaped.
+fn split_command(command: &str) -> Result<Vec<String>, failure::Error> {
+    let mut args = Vec::new();
+    let mut arg = None::<String>;
+    let mut quote = None;
+
+    for c in command.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(..) => arg.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => args.extend(arg.take()),
+            None => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        failure::bail!("unterminated quote in command: {}", command);
+    }
+
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Error message for a `--pick-channel` which doesn't exist in a file.
+fn bad_channel(path: &Path, channel: u16, channels: u16) -> String {
+    format!(
+        "{}: can't pick channel {} from audio with {} channel(s)",
+        path.display(),
+        channel,
+        channels
+    )
+}
+
+/// Write the original samples of a censored section as a clip.
+///
+/// Clips are named after the destination file and the range of samples they cover, in the same
+/// directory relative to the originals directory as the destination file is relative to its
+/// output directory.
+fn retain_original(
+    originals: &Originals,
+    dest_path: &Path,
+    s: hound::WavSpec,
+    range: std::ops::Range<usize>,
+    original: &[i16],
+) -> Result<(), failure::Error> {
+    let stem = dest_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| failure::format_err!("expected file name"))?;
+
+    // NB: the longest output is the one the file is written to, in case outputs are nested.
+    let relative = originals
+        .outputs
+        .iter()
+        .filter_map(|o| dest_path.strip_prefix(o).ok())
+        .min_by_key(|r| r.components().count())
+        .and_then(|r| r.parent())
+        .unwrap_or_else(|| Path::new(""));
+
+    let dir = originals.dir.join(relative);
+
+    if !dir.is_dir() {
+        std::fs::create_dir_all(&dir)?;
+    }
+
+    let clip = dir.join(format!("{}_{}-{}.wav", stem, range.start, range.end));
+
+    write_wav(&clip, s, original.iter().cloned())
+        .with_context(|_| failure::format_err!("failed to write clip: {}", clip.display()))?;
+    Ok(())
+}
+
+/// Replace the given file with silence.
+fn process_silent(path: &Path, dest_path: &Path, cx: &Context<'_>) -> Result<(), failure::Error> {
+    let dest_parent = dest_path
+        .parent()
+        .ok_or_else(|| failure::format_err!("expected destination to have parent dir"))?;
+
+    if !dest_parent.is_dir() {
+        std::fs::create_dir_all(dest_parent)?;
+    }
+
+    let header = match read_header(path, &cx.headers)? {
+        Some(header) => header,
+        None => return process_copy(path, dest_path),
+    };
+
+    let mut s = header.spec;
+    let mut duration = header.duration;
+
+    if let Some(channel) = cx.pick_channel {
+        if channel >= s.channels {
+            failure::bail!(bad_channel(path, channel, s.channels));
+        }
+    }
+
+    if cx.downmix_mono || cx.pick_channel.is_some() {
+        s.channels = 1;
+    }
+
+    if let Some(sample_rate) = cx.resample {
+        duration = process::resampled_frames(duration, s.sample_rate, sample_rate);
+        s.sample_rate = sample_rate;
+    }
+
+    let samples = duration as usize * usize::from(s.channels);
+
+    // NB: seeded the same way as the generator used for replacements, so that silenced files
+    // have the same noise floor.
+    let silence = silence_generator(cx.silence_floor, cx.seed);
+    let factory = generator::Seeded::new(&silence, cx.seed);
+    let generator = factory.for_file(dest_path);
+    let data = generator.generate(0..samples, s.sample_rate);
+
+    write_atomic(dest_path, |temp| {
+        write_audio(temp, s, data.iter().copied(), cx.output_format)
+    })
+}
+
+/// Write a JSON report of all replacements applied.
+///
+/// The report is an array with one object per censored file, sorted by destination. Each object
+/// has the `source` and `dest` paths of the file, and its `replacements` with the censored `word`,
+/// the `start_sample` and `end_sample` of the interleaved samples replaced, and the `generator`.
+fn write_report(path: &Path, mut records: Vec<Record>) -> Result<(), failure::Error> {
+    use std::io::Write;
+
+    #[derive(serde::Serialize)]
+    struct Replacement<'a> {
+        word: &'a str,
+        start_sample: usize,
+        end_sample: usize,
+        generator: &'a str,
+    }
+
+    #[derive(serde::Serialize)]
+    struct Entry<'a> {
+        source: String,
+        dest: String,
+        replacements: Vec<Replacement<'a>>,
+    }
+
+    records.sort_by(|a, b| a.dest.cmp(&b.dest));
+
+    let entries = records
+        .iter()
+        .map(|r| Entry {
+            source: r.source.display().to_string(),
+            dest: r.dest.display().to_string(),
+            replacements: r
+                .replacements
+                .iter()
+                .map(|a| Replacement {
+                    word: &a.word,
+                    start_sample: a.range.start,
+                    end_sample: a.range.end,
+                    generator: &a.generator,
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut f = File::create(path)
+        .with_context(|_| failure::format_err!("failed to create report: {}", path.display()))?;
+
+    serde_json::to_writer_pretty(&mut f, &entries)?;
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Write a JSON array of the files which are missing configuration or are silenced, for `--list`.
+fn write_list_json(
+    out: &mut dyn io::Write,
+    missing: &BTreeMap<PathBuf, Missing<'_>>,
+    silenced: &BTreeMap<PathBuf, Missing<'_>>,
+) -> Result<(), failure::Error> {
+    #[derive(serde::Serialize)]
+    struct Entry<'a> {
+        path: String,
+        config: String,
+        status: &'static str,
+        missing_words: &'a [String],
+    }
+
+    let entries = missing
+        .iter()
+        .map(|e| ("missing", e))
+        .chain(silenced.iter().map(|e| ("silenced", e)))
+        .map(|(status, (path, m))| Entry {
+            path: path.display().to_string(),
+            config: m.config_path.display().to_string(),
+            status,
+            missing_words: m.words,
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_writer_pretty(&mut *out, &entries)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Write out the .oiv manifest for GTA V.
+fn write_oiv_manifest(
+    modified: &BTreeSet<RelativePathBuf>,
+    output: Option<&Path>,
+) -> Result<(), failure::Error> {
+    use std::io::Write;
+
+    let content = oiv::manifest(modified)?;
+
+    match output {
+        Some(output) => {
+            let mut f = File::create(output)?;
+            write!(f, "{}", content)?;
         }
         None => {
             println!("{}", content);
         }
     }
 
-    return Ok(());
+    Ok(())
+}
 
-    #[derive(Debug)]
-    struct Add {
-        source: String,
-        value: String,
+/// Initialize missing files into the current set of configurations.
+///
+/// Only files which are missing configuration are added, using `stub` as their transcript.
+/// Existing files are never modified.
+fn do_init<'a>(
+    out: &mut dyn io::Write,
+    missing: BTreeMap<PathBuf, Missing<'a>>,
+    mut configs: Vec<(&'a Path, &'a Path, Config)>,
+    stub: &str,
+) -> Result<(), failure::Error> {
+    insert_missing(missing, &mut configs, stub)?;
+
+    for (_, _, config) in &configs {
+        serde_yaml::to_writer(&mut *out, &config)?;
+    }
+
+    Ok(())
+}
+
+/// Initialize configurations like [do_init], but write each configuration with missing files back
+/// to where it was loaded from, returning the paths of the written configurations.
+///
+/// Configurations which include others can't be written back, since their includes have been
+/// merged into them.
+fn do_init_inplace<'a>(
+    missing: BTreeMap<PathBuf, Missing<'a>>,
+    mut configs: Vec<(&'a Path, &'a Path, Config)>,
+    stub: &str,
+    including: &HashSet<PathBuf>,
+) -> Result<Vec<PathBuf>, failure::Error> {
+    let changed = missing
+        .values()
+        .map(|m| m.config_path)
+        .collect::<BTreeSet<_>>();
+
+    if let Some(path) = changed.iter().find(|p| including.contains(**p)) {
+        failure::bail!(
+            "can't initialize a configuration with includes in place: {}",
+            path.display()
+        );
+    }
+
+    insert_missing(missing, &mut configs, stub)?;
+
+    let mut written = Vec::new();
+
+    for (_, config_path, config) in &configs {
+        if !changed.contains(config_path) {
+            continue;
+        }
+
+        write_atomic(config_path, |temp| {
+            serde_yaml::to_writer(File::create(temp)?, &config)?;
+            Ok(())
+        })?;
+
+        written.push(config_path.to_path_buf());
+    }
+
+    Ok(written)
+}
+
+/// Insert all missing files into the configuration they belong to, with the given stub as
+/// their transcript, and optimize every configuration.
+fn insert_missing<'a>(
+    missing: BTreeMap<PathBuf, Missing<'a>>,
+    configs: &mut [(&'a Path, &'a Path, Config)],
+    stub: &str,
+) -> Result<(), failure::Error> {
+    let stub = Transcript::parse(stub)
+        .with_context(|_| failure::format_err!("bad init stub: {}", stub))?;
+
+    for (path, m) in missing {
+        for (root, config_path, config) in configs.iter_mut() {
+            if *config_path != m.config_path {
+                continue;
+            }
+
+            let path = path.strip_prefix(&root)?;
+
+            let mut c = path.components();
+            for _ in (&mut c).take(m.dir_path.components().count()) {}
+            let path = RelativePath::from_path(c.as_path())?;
+
+            config.insert_file(m.dir_path, path.to_owned(), stub.clone())?;
+            break;
+        }
+    }
+
+    // optimize all configurations.
+    for (_, _, config) in configs.iter_mut() {
+        config.optimize()?;
+    }
+
+    Ok(())
+}
+
+/// Check that no configuration uses positions in bars and beats without a tempo.
+///
+/// This is checked up front, instead of failing for every file when it is processed.
+fn check_tempo(
+    configs: &[(&Path, &Path, Config)],
+    tempo: Option<&Tempo>,
+) -> Result<(), failure::Error> {
+    if tempo.is_some() {
+        return Ok(());
+    }
+
+    for (_, config_path, config) in configs {
+        for (_, path, replace, transcript) in config.resolved_files() {
+            let transcript = transcript.into_iter().flat_map(|t| &t.replace);
+
+            for r in replace.into_iter().chain(transcript) {
+                let bound = if r.range.start.uses_tempo() {
+                    &r.range.start
+                } else if r.range.end.uses_tempo() {
+                    &r.range.end
+                } else {
+                    continue;
+                };
+
+                failure::bail!(
+                    "{}: {}: {}: {}",
+                    config_path.display(),
+                    path.as_str(),
+                    r,
+                    batchcensor::Error::MissingTempo(bound.clone())
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge all loaded configurations into one.
+fn merge_configs(configs: &[(&Path, &Path, Config)]) -> Result<Config, failure::Error> {
+    let mut it = configs.iter();
+
+    let (first_root, first_path, merged) = match it.next() {
+        Some(first) => first,
+        None => failure::bail!("no configurations to merge"),
+    };
+
+    let mut merged = merged.clone();
+
+    for (root, path, config) in it {
+        if root != first_root {
+            failure::bail!(
+                "cannot merge {} and {} since they have different roots (use --root)",
+                first_path.display(),
+                path.display()
+            );
+        }
+
+        merged
+            .merge(config.clone())
+            .with_context(|_| failure::format_err!("failed to merge: {}", path.display()))?;
+    }
+
+    merged.optimize()?;
+    Ok(merged)
+}
+
+/// Test if `path` is `root` or a descendant of it, resolving both to absolute paths.
+///
+/// `path` doesn't need to exist.
+fn is_within(path: &Path, root: &Path) -> Result<bool, failure::Error> {
+    let root = root.canonicalize()?;
+    Ok(canonicalize_existing(path)?.starts_with(root))
+}
+
+/// Resolve `path` into an absolute path, canonicalizing the part of it which exists.
+fn canonicalize_existing(path: &Path) -> Result<PathBuf, failure::Error> {
+    let mut path = std::path::absolute(path)?;
+    let mut rest = Vec::new();
+
+    // NB: only the part of the path which exists can be canonicalized.
+    let path = loop {
+        if let Ok(path) = path.canonicalize() {
+            break path;
+        }
+
+        match path.file_name() {
+            Some(name) => rest.push(name.to_owned()),
+            None => break path,
+        }
+
+        if !path.pop() {
+            break path;
+        }
+    };
+
+    Ok(rest.iter().rev().fold(path, |path, c| path.join(c)))
+}
+
+/// Expand the `{config}` placeholder in an output directory to the stem of the configuration file.
+///
+/// Outputs without a placeholder are used as-is.
+fn output_template(output: &Path, config_path: &Path) -> PathBuf {
+    let (output, stem) = match (output.to_str(), config_path.file_stem()) {
+        (Some(output), Some(stem)) if output.contains("{config}") => (output, stem),
+        _ => return output.to_owned(),
+    };
+
+    let mut expanded = std::ffi::OsString::new();
+    let mut parts = output.split("{config}");
+
+    if let Some(first) = parts.next() {
+        expanded.push(first);
+    }
+
+    for part in parts {
+        expanded.push(stem);
+        expanded.push(part);
+    }
+
+    PathBuf::from(expanded)
+}
+
+/// Describe why none of the given files has the configured extension, if that's the case.
+///
+/// Returns `None` if any file matches, or if there are no files at all.
+fn extension_mismatch(files: &[PathBuf], file_extension: &str) -> Option<String> {
+    let extensions = files
+        .iter()
+        .filter_map(|path| path.extension().and_then(|s| s.to_str()))
+        .collect::<BTreeSet<_>>();
+
+    if files.is_empty() || extensions.contains(file_extension) {
+        return None;
     }
 
-    impl Add {
-        pub fn to_xml(&self, fmt: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
-            let prefix = std::iter::repeat(' ').take(depth).collect::<String>();
+    let similar = extensions
+        .iter()
+        .find(|ext| ext.eq_ignore_ascii_case(file_extension));
+
+    Some(match similar {
+        Some(similar) => format!(
+            "no files with the configured extension `{}`, but found `{}` (extensions are case \
+             sensitive)",
+            file_extension, similar
+        ),
+        None => format!(
+            "no files with the configured extension `{}`, found: {}",
+            file_extension,
+            extensions.into_iter().collect::<Vec<_>>().join(", ")
+        ),
+    })
+}
+
+/// Compute the destination of an audio file, remapping its extension if requested.
+fn audio_dest(
+    dest_root: &Path,
+    path: &Path,
+    output_extension: Option<&str>,
+) -> Result<PathBuf, failure::Error> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| failure::format_err!("expected file name"))?;
+
+    let dest = dest_root.join(name);
+
+    Ok(match output_extension {
+        Some(output_extension) => dest.with_extension(output_extension),
+        None => dest,
+    })
+}
+
+/// Find all configuration files in the given directory, recursively.
+///
+/// Configurations are sorted by their path relative to the directory, which is the order they are
+/// loaded in regardless of how the filesystem lists them. Where configurations overlap, this is
+/// the order which decides precedence.
+fn find_configs(config_dir: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>, failure::Error> {
+    let mut configs = Vec::new();
+
+    for path in Walker::default().walk(config_dir)? {
+        let name = match path.file_name().and_then(|s| s.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        // NB: extensions might be compound, like `censor.yml`.
+        let matches = extensions.iter().any(|ext| {
+            name.strip_suffix(ext)
+                .and_then(|stem| stem.strip_suffix('.'))
+                .is_some_and(|stem| !stem.is_empty())
+        });
+
+        if matches {
+            configs.push(path);
+        }
+    }
+
+    configs.sort_by(|a, b| {
+        let a = a.strip_prefix(config_dir).unwrap_or(a);
+        let b = b.strip_prefix(config_dir).unwrap_or(b);
+        a.cmp(b)
+    });
+
+    Ok(configs)
+}
+
+/// Settings for walking directories.
+#[derive(Debug, Clone, Default)]
+struct Walker {
+    /// Don't respect ignore files (`.gitignore`, `.ignore`, ...) and include hidden files.
+    no_ignore: bool,
+    /// Globs of files to exclude, relative to the directory being walked.
+    exclude: Vec<String>,
+}
+
+impl Walker {
+    /// Walk the given directory and collect all files in it.
+    ///
+    /// By default this respects ignore files and skips hidden files, like ripgrep does.
+    fn walk(&self, root: &Path) -> Result<Vec<PathBuf>, failure::Error> {
+        let mut files = Vec::new();
+
+        let mut overrides = ignore::overrides::OverrideBuilder::new(root);
+
+        for glob in &self.exclude {
+            overrides
+                .add(&format!("!{}", glob))
+                .with_context(|_| failure::format_err!("bad exclude glob: {}", glob))?;
+        }
+
+        let walk = ignore::WalkBuilder::new(root)
+            .standard_filters(!self.no_ignore)
+            .overrides(overrides.build()?)
+            .build();
+
+        for result in walk {
+            let result = result?;
+            let path = result.path();
+
+            if !path.is_file() {
+                continue;
+            }
+
+            files.push(path.to_owned());
+        }
+
+        Ok(files)
+    }
+}
+
+/// Read a list of newline-separated files to process.
+///
+/// Relative paths are relative to the current directory. Every path is resolved into an absolute
+/// path, so that it can be matched against the directories it belongs to.
+fn read_jobs(path: &Path) -> Result<Vec<PathBuf>, failure::Error> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|_| failure::format_err!("failed to read jobs: {}", path.display()))?;
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| canonicalize_existing(Path::new(line)))
+        .collect()
+}
+
+/// Group listed files by the directory they belong to, spelled the same way as the directory.
+///
+/// A file which is in several nested directories belongs to the deepest one, so that it is only
+/// processed once. Files outside of every directory are ignored.
+fn group_jobs<V>(
+    jobs: Vec<PathBuf>,
+    roots: &HashMap<PathBuf, V>,
+) -> HashMap<&PathBuf, BTreeSet<PathBuf>> {
+    // NB: directories which don't exist have no files, and are reported when they are visited.
+    let canonical = roots
+        .keys()
+        .filter_map(|root| Some((root.canonicalize().ok()?, root)))
+        .collect::<Vec<_>>();
+
+    let mut grouped = HashMap::<_, BTreeSet<_>>::new();
+
+    for job in jobs {
+        let found = canonical
+            .iter()
+            .filter_map(|(c, root)| Some((job.strip_prefix(c).ok()?, *root)))
+            .min_by_key(|(rest, _)| rest.components().count());
+
+        if let Some((rest, root)) = found {
+            let path = root.join(rest);
+            grouped.entry(root).or_default().insert(path);
+        }
+    }
+
+    grouped
+}
+
+/// Statistics printed with `--stats`.
+#[derive(Debug, Default)]
+struct Stats {
+    /// Number of times each word is censored, in lowercase.
+    words: BTreeMap<String, u64>,
+    /// Number of files which have been verified to not need any censoring.
+    clean: u64,
+    /// Files which are silenced whole, and the number of words without a range in them.
+    silenced: (u64, u64),
+    /// Statistics for each configured directory.
+    dirs: BTreeMap<RelativePathBuf, DirStats>,
+}
+
+/// Statistics for a single configured directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct DirStats {
+    /// Files which are censored.
+    processed: u64,
+    /// Files which are silenced whole, because of words without a range.
+    silenced: u64,
+    /// Files which have been verified to not need any censoring.
+    clean: u64,
+    /// Number of replacements in the censored files.
+    replacements: u64,
+}
+
+impl Stats {
+    /// Record a file in `dir` which is censored with the given replacements.
+    fn processed(&mut self, dir: &RelativePath, replace: &[&Replace]) {
+        for r in replace {
+            *self.words.entry(r.word.to_lowercase()).or_default() += 1;
+        }
+
+        let dir = self.dir(dir);
+        dir.processed += 1;
+        dir.replacements += replace.len() as u64;
+    }
+
+    /// Record a file in `dir` which is silenced whole, because of words without a range.
+    fn silenced(&mut self, dir: &RelativePath, words: usize) {
+        self.silenced.0 += 1;
+        self.silenced.1 += words as u64;
+        self.dir(dir).silenced += 1;
+    }
+
+    /// Record a file in `dir` which is verified to not need any censoring.
+    fn clean(&mut self, dir: &RelativePath) {
+        self.clean += 1;
+        self.dir(dir).clean += 1;
+    }
+
+    fn dir(&mut self, dir: &RelativePath) -> &mut DirStats {
+        self.dirs.entry(dir.to_owned()).or_default()
+    }
+
+    /// Write the statistics in a human readable format.
+    fn write(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(out, "# Statistics (--stats)")?;
+
+        for (word, count) in &self.words {
+            writeln!(out, "{} - {}", word, count)?;
+        }
+
+        if self.clean > 0 {
+            writeln!(out, "clean files - {}", self.clean)?;
+        }
+
+        let (files, words) = self.silenced;
+
+        if files > 0 {
+            writeln!(
+                out,
+                "silenced files - {} ({} word(s) without a range)",
+                files, words
+            )?;
+        }
+
+        if !self.dirs.is_empty() {
+            writeln!(out, "# Directories")?;
+        }
+
+        for (dir, s) in &self.dirs {
+            writeln!(
+                out,
+                "{} - {} processed, {} silenced, {} clean, {} replacement(s)",
+                dir.as_str(),
+                s.processed,
+                s.silenced,
+                s.clean,
+                s.replacements
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Paths consulted by a single run, used to determine what to watch.
+#[derive(Debug, Default)]
+struct Sources {
+    /// Configuration files that were loaded.
+    configs: Vec<PathBuf>,
+    /// Source directories that were walked.
+    roots: Vec<PathBuf>,
+    /// Output directories, which are never watched.
+    outputs: Vec<PathBuf>,
+    /// How source directories are walked.
+    walker: Walker,
+}
+
+impl Sources {
+    /// Start watching the configurations and source directories.
+    fn watch(&self) -> Result<Watch, failure::Error> {
+        use notify::Watcher as _;
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::watcher(tx, WATCH_DEBOUNCE)?;
+        let cwd = std::env::current_dir()?;
+
+        let mut configs = Vec::new();
+        let mut roots = Vec::new();
+        let mut parents = HashSet::new();
+
+        // NB: parent directories are watched, since editors commonly save by replacing the file.
+        for config in &self.configs {
+            let absolute = cwd.join(config);
+
+            if let Some(parent) = absolute.parent() {
+                if parents.insert(parent.to_owned()) {
+                    watcher.watch(parent, notify::RecursiveMode::NonRecursive)?;
+                }
+            }
+
+            configs.push((absolute, config.clone()));
+        }
+
+        for root in &self.roots {
+            let absolute = cwd.join(root);
+            watcher.watch(&absolute, notify::RecursiveMode::Recursive)?;
+            roots.push((absolute, root.clone()));
+        }
+
+        Ok(Watch {
+            rx,
+            _watcher: watcher,
+            configs,
+            roots,
+            outputs: self.outputs.iter().map(|o| cwd.join(o)).collect(),
+        })
+    }
+}
+
+/// How long events are debounced for, so that a burst of saves results in a single re-run.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// A watcher over the configurations and source directories of a run.
+struct Watch {
+    rx: mpsc::Receiver<notify::DebouncedEvent>,
+    /// Kept alive for as long as events should be received.
+    _watcher: notify::RecommendedWatcher,
+    /// Watched configurations, as reported by the watcher and as loaded by the run.
+    configs: Vec<(PathBuf, PathBuf)>,
+    /// Watched source directories, as reported by the watcher and as walked by the run.
+    roots: Vec<(PathBuf, PathBuf)>,
+    /// Output directories, whose changes are ignored.
+    outputs: Vec<PathBuf>,
+}
+
+impl Watch {
+    /// Wait until some watched files change, and return the changed files.
+    ///
+    /// Paths are returned the way the run spelled them. If the watcher might have missed
+    /// events, every configuration is returned so that everything is re-run.
+    fn wait(&self) -> Result<BTreeSet<PathBuf>, failure::Error> {
+        let mut changed = BTreeSet::new();
+        let mut event = self.rx.recv()?;
+
+        loop {
+            match event {
+                notify::DebouncedEvent::Create(path)
+                | notify::DebouncedEvent::Write(path)
+                | notify::DebouncedEvent::Remove(path) => {
+                    changed.extend(self.resolve(&path));
+                }
+                notify::DebouncedEvent::Rename(from, to) => {
+                    changed.extend(self.resolve(&from));
+                    changed.extend(self.resolve(&to));
+                }
+                notify::DebouncedEvent::Rescan => {
+                    changed.extend(self.configs.iter().map(|(_, c)| c.clone()));
+                }
+                notify::DebouncedEvent::Error(e, path) => match path {
+                    Some(path) => failure::bail!("failed to watch {}: {}", path.display(), e),
+                    None => failure::bail!("failed to watch: {}", e),
+                },
+                notify::DebouncedEvent::NoticeWrite(..)
+                | notify::DebouncedEvent::NoticeRemove(..)
+                | notify::DebouncedEvent::Chmod(..) => {}
+            }
+
+            // NB: keep collecting as long as events for other paths keep arriving.
+            event = match self.rx.recv_timeout(WATCH_DEBOUNCE) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) if !changed.is_empty() => {
+                    return Ok(changed);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => self.rx.recv()?,
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    failure::bail!("watcher stopped unexpectedly")
+                }
+            };
+        }
+    }
+
+    /// Translate a path reported by the watcher into the path used by the run, if it is watched.
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        if let Some((_, config)) = self.configs.iter().find(|(c, _)| c == path) {
+            return Some(config.clone());
+        }
+
+        if self.outputs.iter().any(|o| path.starts_with(o)) {
+            return None;
+        }
+
+        self.roots.iter().find_map(|(absolute, root)| {
+            path.strip_prefix(absolute).ok().map(|rest| root.join(rest))
+        })
+    }
+}
+
+/// Wait for changes to a previous run, and run again.
+///
+/// A changed configuration could affect any file, so then everything is re-run. Otherwise only
+/// the changed files are.
+fn rerun(
+    m: &clap::ArgMatches<'_>,
+    sources: &Sources,
+    watch: &Watch,
+    out: &mut dyn io::Write,
+) -> Result<Sources, failure::Error> {
+    let changed = watch.wait()?;
+
+    for path in &changed {
+        writeln!(out, "changed: {}", path.display())?;
+    }
+
+    let filter = if changed.iter().any(|c| sources.configs.contains(c)) {
+        None
+    } else {
+        Some(changed.into_iter().collect::<HashSet<_>>())
+    };
+
+    run(m, filter.as_ref(), out)
+}
+
+fn main() -> Result<(), failure::Error> {
+    let m = opts().get_matches();
+    let mut sources = run(&m, None, &mut io::stdout())?;
+
+    if !m.is_present("watch") {
+        return Ok(());
+    }
+
+    let mut watch = sources.watch()?;
+
+    loop {
+        println!("watching for changes...");
+
+        match rerun(&m, &sources, &watch, &mut io::stdout()) {
+            Ok(s) => {
+                // NB: the same watch is kept if possible, so that changes made during the run
+                // are picked up.
+                if s.configs != sources.configs || s.roots != sources.roots {
+                    watch = s.watch()?;
+                }
+
+                sources = s;
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+
+                for cause in e.iter_causes() {
+                    eprintln!("caused by: {}", cause);
+                }
+            }
+        }
+    }
+}
+
+/// Convert a level in decibels relative to full scale into a linear amplitude.
+///
+/// Levels above 0 dBFS are clamped to full scale.
+fn amplitude_from_db(db: f32) -> f32 {
+    10f32.powf(db.min(0f32) / 20f32)
+}
+
+/// Construct the generator for silence, with a noise floor of the given amplitude if any.
+fn silence_generator(floor: Option<f32>, seed: u64) -> generator::Silence {
+    match floor {
+        Some(amplitude) => generator::Silence::new().with_floor(amplitude, seed),
+        None => generator::Silence::new(),
+    }
+}
+
+/// Parse the frequencies of a dual tone, like `1000,1500`.
+fn parse_dual_tone(s: &str) -> Result<(f32, f32), failure::Error> {
+    let mut it = s.split(',').map(|f| str::parse::<f32>(f.trim()));
+
+    match (it.next(), it.next(), it.next()) {
+        (Some(Ok(f1)), Some(Ok(f2)), None) if f1 > 0f32 && f2 > 0f32 => Ok((f1, f2)),
+        _ => failure::bail!("bad dual tone, expected <f1>,<f2>: {}", s),
+    }
+}
+
+/// The format to write processed audio in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Wav,
+    Flac,
+}
+
+impl OutputFormat {
+    /// Parse the output format from a string.
+    fn parse(s: &str) -> Option<OutputFormat> {
+        match s {
+            "wav" => Some(OutputFormat::Wav),
+            "flac" => Some(OutputFormat::Flac),
+            _ => None,
+        }
+    }
+
+    /// The extension that outputs must have, if it differs from the input.
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Wav => None,
+            OutputFormat::Flac => Some("flac"),
+        }
+    }
+}
+
+/// What to do with files that have marked words without a range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnMissing {
+    /// Silence the whole file.
+    Silence,
+    /// Leave the file uncensored.
+    Skip,
+    /// Abort with an error.
+    Error,
+}
+
+impl OnMissing {
+    /// Parse the policy from a string.
+    fn parse(s: &str) -> Option<OnMissing> {
+        match s {
+            "silence" => Some(OnMissing::Silence),
+            "skip" => Some(OnMissing::Skip),
+            "error" => Some(OnMissing::Error),
+            _ => None,
+        }
+    }
+}
+
+/// How to format the output of `--list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListFormat {
+    /// Free-form text to stderr.
+    Text,
+    /// A JSON array to stdout.
+    Json,
+}
+
+impl ListFormat {
+    /// Parse the format from a string.
+    fn parse(s: &str) -> Option<ListFormat> {
+        match s {
+            "text" => Some(ListFormat::Text),
+            "json" => Some(ListFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Add tasks to copy every file under the given configuration roots to their outputs, for
+/// `--mirror-all`.
+///
+/// Files in configured directories, outputs, and configurations are left out, as are files which
+/// some other task already handles.
+fn mirror_tasks<V>(
+    tasks: &mut Vec<Task<'_>>,
+    mirrors: &[(PathBuf, PathBuf)],
+    roots: &HashMap<PathBuf, V>,
+    sources: &Sources,
+) -> Result<(), failure::Error> {
+    let handled = tasks
+        .iter()
+        .map(|t| t.source().to_owned())
+        .collect::<HashSet<_>>();
+    let mut copied = tasks
+        .iter()
+        .map(|t| t.dest().to_owned())
+        .collect::<HashSet<_>>();
+
+    for (root, output) in mirrors {
+        for path in sources.walker.walk(root)? {
+            let skip = handled.contains(&path)
+                || sources.configs.contains(&path)
+                || roots.keys().any(|r| path.starts_with(r))
+                || sources.outputs.iter().any(|o| path.starts_with(o));
+
+            if skip {
+                continue;
+            }
+
+            let dest = output.join(path.strip_prefix(root)?);
+
+            if copied.insert(dest.clone()) {
+                tasks.push(Task::Copy(path, dest));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that every file which is processed has the given sample rate.
+///
+/// Files which can't be read are left to fail when they are processed.
+fn check_sample_rates(
+    tasks: &[Task<'_>],
+    sample_rate: u32,
+    headers: &HeaderCache,
+) -> Result<(), failure::Error> {
+    let mut mismatched = Vec::new();
+
+    for task in tasks {
+        let path = match *task {
+            Task::Process(ref path, _, ref replace, _) if !replace.is_empty() => path,
+            _ => continue,
+        };
+
+        if std::fs::metadata(path)
+            .map(|m| m.len() == 0)
+            .unwrap_or(true)
+        {
+            continue;
+        }
+
+        if let Ok(header) = headers.get(path) {
+            if header.spec.sample_rate != sample_rate {
+                mismatched.push(format!(
+                    "{} ({} Hz)",
+                    path.display(),
+                    header.spec.sample_rate
+                ));
+            }
+        }
+    }
+
+    if !mismatched.is_empty() {
+        failure::bail!(
+            "files don't match the sample rate of --sample ({} Hz), use --sample-resample to \
+             resample it:\n{}",
+            sample_rate,
+            mismatched.join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+/// Test if the progress bar should be shown.
+///
+/// It is hidden with `--no-progress`, or when stderr isn't a terminal, since redrawing it would
+/// fill logs with escape codes.
+fn show_progress(no_progress: bool, stderr_is_tty: bool) -> bool {
+    !no_progress && stderr_is_tty
+}
+
+/// Discover and run all tasks.
+///
+/// If `changed` is specified, only tasks whose source file is in the set will be run. Listings,
+/// statistics, and other reports which would go to stdout are written to `out`.
+fn run(
+    m: &clap::ArgMatches<'_>,
+    changed: Option<&HashSet<PathBuf>>,
+    out: &mut dyn io::Write,
+) -> Result<Sources, failure::Error> {
+    use rayon::prelude::*;
+
+    let list = m.is_present("list");
+    let deny_missing = m.is_present("deny-missing");
+    let reencode_clean = m.is_present("reencode-clean");
+    let pick_channel = match m.value_of("pick-channel") {
+        Some(channel) => match str::parse::<u16>(channel) {
+            Ok(channel) => Some(channel),
+            Err(..) => failure::bail!("bad --pick-channel: {}", channel),
+        },
+        None => None,
+    };
+    let limit = match m.value_of("limit") {
+        Some(limit) => match str::parse::<usize>(limit) {
+            Ok(limit) => Some(limit),
+            Err(..) => failure::bail!("bad --limit: {}", limit),
+        },
+        None => None,
+    };
+    let strict_ranges = m.is_present("strict-ranges");
+    let stats = m.is_present("stats");
+    let tone = m.is_present("tone") || m.is_present("tone-waveform");
+    let waveform = match m.value_of("tone-waveform") {
+        Some(shape) => match generator::Waveform::parse(shape) {
+            Some(waveform) => waveform,
+            None => failure::bail!("bad tone waveform: {}", shape),
+        },
+        None => generator::Waveform::default(),
+    };
+    let dual_tone = match m.value_of("dual-tone") {
+        Some(frequencies) => Some(parse_dual_tone(frequencies)?),
+        None => None,
+    };
+    let radio = {
+        let band = |name: &str, default: f32| match m.value_of(name) {
+            Some(hz) => match str::parse::<f32>(hz) {
+                Ok(hz) if hz > 0f32 => Ok(hz),
+                _ => failure::bail!("bad --{}: {}", name, hz),
+            },
+            None => Ok(default),
+        };
+
+        let low = band("radio-low", 300f32)?;
+        let high = band("radio-high", 3000f32)?;
+
+        if low >= high {
+            failure::bail!("--radio-low must be below --radio-high");
+        }
+
+        if m.is_present("radio") || m.is_present("radio-low") || m.is_present("radio-high") {
+            Some((low, high))
+        } else {
+            None
+        }
+    };
+    let amplitude = match (
+        m.value_of("tone-amplitude"),
+        m.value_of("tone-amplitude-db"),
+    ) {
+        (Some(amplitude), _) => match str::parse::<f32>(amplitude) {
+            Ok(a) if (0f32..=1f32).contains(&a) => Some(a),
+            _ => failure::bail!("bad --tone-amplitude, expected 0 to 1: {}", amplitude),
+        },
+        (None, Some(db)) => match str::parse::<f32>(db) {
+            Ok(db) if !db.is_nan() => Some(amplitude_from_db(db)),
+            _ => failure::bail!("bad --tone-amplitude-db: {}", db),
+        },
+        (None, None) => None,
+    };
+    let silence_floor = match m.value_of("silence-floor") {
+        Some(db) => match str::parse::<f32>(db) {
+            Ok(db) if !db.is_nan() => Some(amplitude_from_db(db)),
+            _ => failure::bail!("bad --silence-floor: {}", db),
+        },
+        None => None,
+    };
+    let output = m.value_of("output").map(PathBuf::from);
+    let init = m.value_of("init");
+    let init_inplace = m.is_present("init-inplace");
+    let init_stub = m.value_of("init-stub").unwrap_or("[missing]");
+    let resample = match m.value_of("resample") {
+        Some(hz) => match str::parse::<u32>(hz) {
+            Ok(hz) if hz > 0 => Some(hz),
+            _ => failure::bail!("bad sample rate: {}", hz),
+        },
+        None => None,
+    };
+    let tempo = match m.value_of("bpm") {
+        Some(bpm) => {
+            let tempo = match str::parse::<f32>(bpm) {
+                Ok(bpm) if bpm > 0f32 && bpm.is_finite() => Tempo::new(bpm),
+                _ => failure::bail!("bad --bpm: {}", bpm),
+            };
+
+            match m.value_of("time-signature") {
+                Some(s) => match tempo.with_time_signature(s) {
+                    Some(tempo) => Some(tempo),
+                    None => failure::bail!("bad --time-signature: {}", s),
+                },
+                None => Some(tempo),
+            }
+        }
+        None => None,
+    };
+    let max_censor_ratio = match m.value_of("max-censor-ratio") {
+        Some(ratio) => match str::parse::<f32>(ratio) {
+            Ok(ratio) if (0f32..=1f32).contains(&ratio) => Some(ratio),
+            _ => failure::bail!("bad --max-censor-ratio, expected 0 to 1: {}", ratio),
+        },
+        None => None,
+    };
+    let output_format = match m.value_of("output-format") {
+        Some(format) => match OutputFormat::parse(format) {
+            Some(format) => format,
+            None => failure::bail!("bad --output-format: {}", format),
+        },
+        None => OutputFormat::Wav,
+    };
+
+    if cfg!(not(feature = "flac")) && output_format == OutputFormat::Flac {
+        failure::bail!("--output-format flac requires building with the `flac` feature");
+    }
+
+    let wordlist = match m.value_of("wordlist") {
+        Some(path) => Some(
+            Wordlist::open(Path::new(path))
+                .with_context(|_| failure::format_err!("failed to open --wordlist: {}", path))?,
+        ),
+        None => None,
+    };
+    let on_missing = match m.value_of("on-missing") {
+        Some(policy) => match OnMissing::parse(policy) {
+            Some(policy) => policy,
+            None => failure::bail!("bad --on-missing policy: {}", policy),
+        },
+        None => OnMissing::Silence,
+    };
+    let list_format = match m.value_of("list-format") {
+        Some(format) => match ListFormat::parse(format) {
+            Some(format) => format,
+            None => failure::bail!("bad --list-format: {}", format),
+        },
+        None => ListFormat::Text,
+    };
+    let sample = match m.value_of("sample") {
+        Some(path) => {
+            let sample = generator::Sample::open(Path::new(path))
+                .with_context(|_| failure::format_err!("failed to open --sample: {}", path))?;
+            let crossfade = match m.value_of("sample-crossfade") {
+                Some(ms) => match str::parse::<u32>(ms) {
+                    Ok(ms) => ms,
+                    Err(..) => failure::bail!("bad --sample-crossfade: {}", ms),
+                },
+                None => 0,
+            };
+
+            Some(
+                sample
+                    .with_resample(m.is_present("sample-resample"))
+                    .with_crossfade(crossfade),
+            )
+        }
+        None => None,
+    };
+    // sample rate which all processed files must have, unless the sample is resampled.
+    let sample_rate = match sample {
+        Some(ref sample) if !m.is_present("sample-resample") => Some(sample.sample_rate()),
+        _ => None,
+    };
+    let pad = match m.value_of("pad") {
+        Some(pad) => match str::parse::<u32>(pad) {
+            Ok(pad) => pad,
+            Err(..) => failure::bail!("bad --pad: {}", pad),
+        },
+        None => 0,
+    };
+    let min_silence = match m.value_of("min-silence") {
+        Some(ms) => match str::parse::<u32>(ms) {
+            Ok(ms) => ms,
+            Err(..) => failure::bail!("bad --min-silence: {}", ms),
+        },
+        None => 0,
+    };
+    let silence_threshold = match m.value_of("silence-threshold") {
+        Some(db) => match str::parse::<f32>(db) {
+            Ok(db) if !db.is_nan() => db,
+            _ => failure::bail!("bad --silence-threshold: {}", db),
+        },
+        None => -60f32,
+    };
+    let silence_threshold = (amplitude_from_db(silence_threshold) * f32::from(i16::MAX)) as i16;
+    let seed = match m.value_of("seed") {
+        Some(seed) => match str::parse::<u64>(seed) {
+            Ok(seed) => seed,
+            Err(..) => failure::bail!("bad --seed: {}", seed),
+        },
+        None => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    };
+    let max_memory = match m.value_of("max-memory") {
+        Some(mb) => match str::parse::<u64>(mb) {
+            Ok(mb) => Some(mb.saturating_mul(1024 * 1024)),
+            Err(..) => failure::bail!("bad --max-memory: {}", mb),
+        },
+        None => None,
+    };
+    let companions = m
+        .value_of("companion-ext")
+        .unwrap_or("oac")
+        .split(',')
+        .map(|ext| ext.trim().trim_start_matches('.'))
+        .filter(|ext| !ext.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let no_companions = m.is_present("no-companions");
+    let jobs = match m.value_of("jobs-from") {
+        Some(jobs) => Some(read_jobs(Path::new(jobs))?),
+        None => None,
+    };
+
+    let mut statistics = Stats::default();
+
+    let mut configs = Vec::new();
+    configs.extend(
+        m.values_of("config")
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from),
+    );
+
+    let config_extensions = match m.values_of("config-ext") {
+        Some(values) => values
+            .flat_map(|ext| ext.split(','))
+            .map(|ext| ext.trim().trim_start_matches('.'))
+            .filter(|ext| !ext.is_empty())
+            .collect::<Vec<_>>(),
+        None => CONFIG_EXTENSIONS.to_vec(),
+    };
+
+    if let Some(config_dir) = m.value_of("config-dir") {
+        configs.extend(find_configs(Path::new(config_dir), &config_extensions)?);
+    }
+
+    if configs.is_empty() {
+        let mut searched = Vec::new();
+        searched.extend(
+            m.values_of("config")
+                .into_iter()
+                .flatten()
+                .map(|c| format!("--config {}", c)),
+        );
+        searched.extend(m.value_of("config-dir").map(|d| {
+            format!(
+                "--config-dir {} for *.{}",
+                d,
+                config_extensions.join(", *.")
+            )
+        }));
+
+        if searched.is_empty() {
+            failure::bail!(
+                "no configuration files found: use --config or --config-dir to specify some"
+            );
+        }
+
+        failure::bail!(
+            "no configuration files found (searched: {})",
+            searched.join(", ")
+        );
+    }
+
+    let default_root = m.value_of("root").map(Path::new);
+
+    // NB: configurations are loaded in parallel, but collected in order so that the reported
+    // error is always the first one by path.
+    let loaded = configs
+        .par_iter()
+        .map(|path| {
+            let mut loader = Loader::default();
+            let config = loader.load(path)?;
+
+            let root = match default_root {
+                Some(root) => root,
+                None => path.parent().ok_or_else(|| {
+                    failure::format_err!("config does not have a parent directory")
+                })?,
+            };
+
+            Ok(((root, path.as_path(), config), loader.loaded().to_vec()))
+        })
+        .collect::<Vec<Result<_, failure::Error>>>()
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut config_paths = Vec::new();
+    let mut configs = Vec::new();
+    // Configurations which include others.
+    let mut including = HashSet::new();
+
+    for (config, loaded) in loaded {
+        if loaded.len() > 1 {
+            including.insert(config.1.to_path_buf());
+        }
+
+        configs.push(config);
+        config_paths.extend(loaded);
+    }
+
+    if let Some(merge) = m.value_of("merge") {
+        let config = merge_configs(&configs)?;
+
+        match merge {
+            "-" => {
+                serde_yaml::to_writer(&mut *out, &config)?;
+            }
+            other => {
+                let other = Path::new(other);
+
+                let f = File::create(other).with_context(|_| {
+                    failure::format_err!(
+                        "failed to open merge file for writing: {}",
+                        other.display()
+                    )
+                })?;
+
+                serde_yaml::to_writer(f, &config)?;
+            }
+        }
+
+        return Ok(Sources {
+            configs: config_paths,
+            ..Sources::default()
+        });
+    }
+
+    if m.is_present("print-config") {
+        for (_, path, config) in &configs {
+            let mut config = config.clone();
+            config.apply_defaults();
+            config.optimize()?;
+
+            writeln!(out, "# {}", path.display())?;
+            serde_yaml::to_writer(&mut *out, &config)?;
+            writeln!(out)?;
+        }
+
+        return Ok(Sources {
+            configs: config_paths,
+            ..Sources::default()
+        });
+    }
+
+    check_tempo(&configs, tempo.as_ref())?;
+
+    let mut tasks = Vec::new();
+
+    // keep track if we are processing any files, which will determine what goes into the manifest.
+    let mut modified = BTreeSet::new();
+
+    let mut missing = BTreeMap::<PathBuf, Missing>::new();
+    // audio files in configured directories, whose companions are copied along with them.
+    let mut audio_files = HashSet::new();
+    let mut silenced = BTreeMap::<PathBuf, Missing>::new();
+    let mut unranged = Vec::new();
+    let mut open_ranges = Vec::new();
+    let mut roots = HashMap::new();
+    let mut dirs = HashMap::<PathBuf, Vec<_>>::new();
+    // roots of configurations and their outputs, for --mirror-all.
+    let mut mirrors = Vec::new();
+
+    let walker = Walker {
+        no_ignore: m.is_present("no-ignore"),
+        exclude: m
+            .values_of("exclude")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect(),
+    };
+
+    let mut sources = Sources {
+        walker: walker.clone(),
+        ..Sources::default()
+    };
+    sources.configs.extend(config_paths);
+
+    // NB: generators specified on the command line take precedence over config defaults.
+    let cli_generator = if let Some((f1, f2)) = dual_tone {
+        let dual_tone = generator::DualTone::new(f1, f2);
+        let dual_tone = match amplitude {
+            Some(amplitude) => dual_tone.with_amplitude(amplitude),
+            None => dual_tone,
+        };
+        Some(Box::new(dual_tone) as Box<dyn Generator>)
+    } else if tone {
+        let tone = generator::Tone::new().with_waveform(waveform);
+        let tone = match amplitude {
+            Some(amplitude) => tone.with_amplitude(amplitude),
+            None => tone,
+        };
+        Some(Box::new(tone) as Box<dyn Generator>)
+    } else if amplitude.is_some() && !m.is_present("noise") {
+        failure::bail!("--tone-amplitude only applies to --tone, --dual-tone, and --noise");
+    } else if m.is_present("reverse") {
+        Some(Box::new(generator::Reverse::new()) as Box<dyn Generator>)
+    } else if m.is_present("noise") {
+        let noise = generator::Noise::new(seed);
+        let noise = match amplitude {
+            Some(amplitude) => noise.with_amplitude(amplitude),
+            None => noise,
+        };
+        Some(Box::new(noise) as Box<dyn Generator>)
+    } else if m.is_present("crossfade") {
+        Some(Box::new(generator::CrossfadeSilence::new()) as Box<dyn Generator>)
+    } else if let Some((low, high)) = radio {
+        Some(Box::new(generator::Radio::new().with_band(low, high)) as Box<dyn Generator>)
+    } else {
+        sample.map(|sample| Box::new(sample) as Box<dyn Generator>)
+    };
+
+    let default_generators = configs
+        .iter()
+        .map(|(_, config_path, config)| {
+            let name = match config.default_generator.as_deref() {
+                Some(name) => name,
+                None => return Ok(None),
+            };
+
+            match generator::from_name(name) {
+                Some(generator) => Ok(Some(generator)),
+                None => failure::bail!(
+                    "{}: unknown default_generator `{}`",
+                    config_path.display(),
+                    name
+                ),
+            }
+        })
+        .collect::<Result<Vec<_>, failure::Error>>()?;
+
+    // NB: subtitles are loaded up front, since tasks borrow the replacements from them.
+    let mut subtitles = HashMap::<PathBuf, Vec<Replace>>::new();
+
+    if m.is_present("subtitles") {
+        for (root, _, config) in &configs {
+            for (_, path, ..) in config.resolved_files() {
+                let path = path.to_path(root);
+
+                for ext in batchcensor::subtitles::EXTENSIONS {
+                    let subtitles_path = path.with_extension(ext);
+
+                    if !subtitles_path.is_file() {
+                        continue;
+                    }
+
+                    let replaces = Subtitles::open(&subtitles_path)
+                        .and_then(|s| s.replaces(wordlist.as_ref()))
+                        .with_context(|_| {
+                            failure::format_err!(
+                                "failed to load subtitles: {}",
+                                subtitles_path.display()
+                            )
+                        })?;
+
+                    subtitles.entry(path.clone()).or_default().extend(replaces);
+                }
+            }
+        }
+    }
+
+    // Go through all configurations and construct root directories.
+    for ((root, config_path, config), default_generator) in configs.iter().zip(&default_generators)
+    {
+        let default_generator = match cli_generator {
+            Some(..) => None,
+            None => default_generator.as_deref(),
+        };
+
+        let output = output
+            .as_deref()
+            .map(|output| output_template(output, config_path))
+            .unwrap_or_else(|| root.join("output"));
+
+        sources.outputs.push(output.clone());
+        mirrors.push((root.to_path_buf(), output.clone()));
+
+        // NB: configured companions are relative to the configuration, even if `--root` points
+        // elsewhere.
+        let config_dir = config_path
+            .parent()
+            .ok_or_else(|| failure::format_err!("config does not have a parent directory"))?;
+
+        for dir in &config.dirs {
+            let root = dir.path.to_path(root);
+
+            if !root.is_dir() {
+                failure::bail!("no such directory: {}", root.display());
+            }
+
+            // NB: outputs in a processed directory would be picked up as sources on the next run.
+            if is_within(&output, &root)? {
+                failure::bail!(
+                    "output directory {} is inside the processed directory {}, use --output to \
+                     write to a separate location",
+                    output.display(),
+                    root.display()
+                );
+            }
+
+            let output_extension = output_format.extension().or(dir
+                .output_extension
+                .as_deref()
+                .or(config.output_extension.as_deref()));
+
+            dirs.entry(root.clone())
+                .or_default()
+                .push((dir, output_extension));
+
+            let mut dest_root = output.to_owned();
+
+            for c in dir.path.components() {
+                dest_root.push(c.as_str());
+            }
+
+            let companion = dir.companion.as_ref().map(|c| c.to_path(config_dir));
+
+            roots.insert(
+                root,
+                (
+                    dest_root,
+                    *config_path,
+                    config,
+                    dir,
+                    output_extension,
+                    companion,
+                    default_generator,
+                ),
+            );
+        }
+    }
+
+    sources.roots.extend(roots.keys().cloned());
+    sources.roots.sort();
+
+    let jobs = jobs.map(|jobs| group_jobs(jobs, &roots));
+
+    // NB: walk all roots up front and in parallel, since it's the slowest part of discovery. Like
+    // configurations, the results are collected in order so that the reported error is always the
+    // first one by path.
+    let mut walked = match jobs {
+        Some(..) => HashMap::new(),
+        None => sources
+            .roots
+            .par_iter()
+            .map(|root| Ok((root.clone(), walker.walk(root)?)))
+            .collect::<Vec<Result<_, failure::Error>>>()
+            .into_iter()
+            .collect::<Result<HashMap<_, _>, _>>()?,
+    };
+
+    for (
+        root,
+        (dest_root, config_path, config, dir, output_extension, companion, default_generator),
+    ) in &roots
+    {
+        if !root.is_dir() {
+            failure::bail!("no such directory: {}", root.display());
+        }
+
+        let dir_path = &dir.path;
+
+        let files = match jobs {
+            // only consider the listed files which belong to this directory.
+            Some(ref jobs) => jobs
+                .get(root)
+                .into_iter()
+                .flatten()
+                .filter(|path| path.is_file())
+                .cloned()
+                .collect(),
+            None => {
+                match companion {
+                    _ if no_companions => {}
+                    // copy the configured companion to where the one next to the directory goes.
+                    Some(companion) => {
+                        if !companion.is_file() {
+                            failure::bail!("no such companion file: {}", companion.display());
+                        }
+
+                        let ext = match companion.extension() {
+                            Some(ext) => ext,
+                            None => failure::bail!(
+                                "companion file has no extension: {}",
+                                companion.display()
+                            ),
+                        };
+
+                        tasks.push(Task::Copy(companion.clone(), dest_root.with_extension(ext)));
+                    }
+                    // copy companions of the directory, like `<dir>.oac`, if present.
+                    None => {
+                        for ext in &companions {
+                            let companion = root.with_extension(ext);
+
+                            if companion.is_file() {
+                                tasks.push(Task::Copy(companion, dest_root.with_extension(ext)));
+                            }
+                        }
+                    }
+                }
+
+                walked.remove(root).unwrap_or_default()
+            }
+        };
+
+        // the extensions of audio files in the directory, as configured.
+        let file_extensions = dirs
+            .get(root)
+            .into_iter()
+            .flatten()
+            .map(|(dir, _)| {
+                dir.file_extension
+                    .as_deref()
+                    .or(config.file_extension.as_deref())
+            })
+            .collect::<Vec<_>>();
+
+        // NB: a mismatched extension would otherwise only show up as unexpected or missing files.
+        if jobs.is_none() {
+            for ext in file_extensions.iter().flatten() {
+                if let Some(hint) = extension_mismatch(&files, ext) {
+                    eprintln!("warning: {}: {}", root.display(), hint);
+                }
+            }
+        }
+
+        // NB: directories without an extension have wav files.
+        let file_extensions = file_extensions
+            .into_iter()
+            .map(|ext| ext.unwrap_or("wav"))
+            .collect::<HashSet<_>>();
+
+        let audio = files
+            .iter()
+            .filter(|path| {
+                path.extension()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|ext| file_extensions.contains(ext))
+            })
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        for path in files {
+            match path.extension().and_then(|s| s.to_str()) {
+                _ if audio.contains(&path) => {}
+                // NB: companions of audio files are copied along with them.
+                Some(ext)
+                    if companions.iter().any(|c| c == ext)
+                        && file_extensions
+                            .iter()
+                            .any(|e| audio.contains(&path.with_extension(e))) =>
+                {
+                    continue
+                }
+                _ => {
+                    let dest = dest_root.join(path.strip_prefix(root)?);
+                    // NB: straight up copy other files.
+                    tasks.push(Task::Copy(path, dest));
+                    continue;
+                }
+            }
+
+            // Keep track of all files to produce a list of files missing configuration in the end.
+            missing.insert(
+                path,
+                Missing {
+                    config_path,
+                    dest_root,
+                    dir_path,
+                    output_extension: *output_extension,
+                    words: &[],
+                },
+            );
+        }
+
+        audio_files.extend(audio);
+
+        // Process all dirs.
+        for (dir, output_extension) in dirs.get(root).into_iter().flatten() {
+            for (i, (path, mut replace, transcript, clean)) in dir.files.iter().enumerate() {
+                let path = dir
+                    .resolve_file(config.file_extension.as_deref(), i, path)
+                    .to_path(root);
+
+                let dest = audio_dest(dest_root, &path, *output_extension)?;
+
+                let indexed = match missing.remove(&path) {
+                    Some(indexed) => indexed,
+                    // file was not listed in --jobs-from.
+                    None if jobs
+                        .as_ref()
+                        .is_some_and(|jobs| !jobs.get(root).is_some_and(|j| j.contains(&path))) =>
+                    {
+                        continue
+                    }
+                    None => {
+                        failure::bail!("did not expect to censor file: {}", path.display());
+                    }
+                };
+
+                // file has been verified to not need any censoring.
+                if clean {
+                    if !replace.is_empty() || transcript.is_some_and(|t| !t.replace.is_empty()) {
+                        failure::bail!("file marked as clean has replacements: {}", path.display());
+                    }
+
+                    statistics.clean(&dir.path);
+                    tasks.push(copy_audio(path, dest, output_format));
+                    continue;
+                }
+
+                if let Some(transcript) = transcript {
+                    // file has marked words which do not have a range.
+                    if !transcript.missing.is_empty() {
+                        match on_missing {
+                            OnMissing::Silence => {
+                                let words = &transcript.missing[..];
+
+                                statistics.silenced(&dir.path, words.len());
+
+                                // NB: the silence task is added with the other missing files below.
+                                silenced.insert(path, Missing { words, ..indexed });
+                            }
+                            OnMissing::Skip => tasks.push(copy_audio(path, dest, output_format)),
+                            OnMissing::Error => unranged.push(path),
+                        }
+
+                        continue;
+                    }
+
+                    replace.extend(transcript.replace.iter());
+
+                    if let Some(wordlist) = &wordlist {
+                        replace.extend(transcript.matching(wordlist));
+                    }
+                }
+
+                if let Some(subtitles) = subtitles.get(&path) {
+                    replace.extend(subtitles.iter());
+                }
+
+                dedup_replace(&mut replace);
+
+                if strict_ranges {
+                    for r in &replace {
+                        if r.range.start.is_open() || r.range.end.is_open() {
+                            open_ranges.push(format!("{}: {}", path.display(), r));
+                        }
+                    }
+                }
+
+                // audio file already clean.
+                if replace.is_empty() {
+                    if reencode_clean {
+                        tasks.push(Task::Process(path, dest, replace, None));
+                    } else {
+                        tasks.push(copy_audio(path, dest, output_format));
+                    }
+
+                    continue;
+                }
+
+                statistics.processed(&dir.path, &replace);
+                modified.insert(dir.path.to_owned());
+                tasks.push(Task::Process(path, dest, replace, *default_generator));
+            }
+        }
+    }
+
+    if m.is_present("mirror-all") {
+        mirror_tasks(&mut tasks, &mirrors, &roots, &sources)?;
+    }
+
+    if !unranged.is_empty() {
+        let files = unranged
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>();
+
+        failure::bail!(
+            "marked words without a range in {} file(s) (--on-missing=error): {}",
+            files.len(),
+            files.join(", ")
+        );
+    }
+
+    if !open_ranges.is_empty() {
+        failure::bail!(
+            "ranges without an explicit start or end in {} place(s) (--strict-ranges): {}",
+            open_ranges.len(),
+            open_ranges.join(", ")
+        );
+    }
+
+    if init.is_some() || init_inplace {
+        if missing.is_empty() {
+            writeln!(out, "nothing to initialize: there are no missing files!")?;
+            return Ok(sources);
+        }
+
+        if init_inplace {
+            for path in do_init_inplace(missing, configs.clone(), init_stub, &including)? {
+                writeln!(out, "initialized: {}", path.display())?;
+            }
+
+            return Ok(sources);
+        }
+
+        match init {
+            None | Some("-") => {
+                do_init(out, missing, configs.clone(), init_stub)?;
+                return Ok(sources);
+            }
+            Some(other) => {
+                let other = Path::new(other);
+
+                let mut f = File::create(other).with_context(|_| {
+                    failure::format_err!(
+                        "failed to open init file for writing: {}",
+                        other.display()
+                    )
+                })?;
+
+                do_init(&mut f, missing, configs.clone(), init_stub)?;
+                return Ok(sources);
+            }
+        }
+    }
+
+    if deny_missing && !missing.is_empty() {
+        for (path, m) in &missing {
+            eprintln!(
+                "{}: missing config for: {}",
+                m.config_path.display(),
+                path.display()
+            );
+        }
+
+        failure::bail!(
+            "missing censor configuration for {} file(s) (--deny-missing)",
+            missing.len()
+        );
+    }
+
+    if !missing.is_empty() || !silenced.is_empty() {
+        if !list {
+            if !missing.is_empty() {
+                eprintln!(
+                    "Missing censor configuration for {} file(s) (--list to see them)",
+                    missing.len()
+                );
+            }
+
+            if !silenced.is_empty() {
+                eprintln!(
+                    "Silenced censor configuration for {} file(s) (--list to see them)",
+                    silenced.len()
+                );
+            }
+        } else if list_format == ListFormat::Json {
+            write_list_json(out, &missing, &silenced)?;
+        } else {
+            for (path, m) in &missing {
+                eprintln!(
+                    "{}: missing config for: {}",
+                    m.config_path.display(),
+                    path.display()
+                );
+            }
+
+            for (path, m) in &silenced {
+                eprintln!(
+                    "{}: silenced config for: {} (missing ranges for: {})",
+                    m.config_path.display(),
+                    path.display(),
+                    m.words.join(", ")
+                );
+            }
+        }
+
+        for (path, m) in missing.into_iter().chain(silenced) {
+            let dest = audio_dest(m.dest_root, &path, m.output_extension)?;
+
+            modified.insert(m.dir_path.to_owned());
+            tasks.push(Task::Silence(path, dest));
+        }
+    }
+
+    if !no_companions {
+        task::add_companions(&mut tasks, &companions, &audio_files);
+    }
+    task::sort(&mut tasks);
+
+    if let Some(changed) = changed {
+        tasks.retain(|t| changed.contains(t.source()));
+
+        for t in &tasks {
+            writeln!(out, "re-running: {}", t)?;
+        }
+    }
+
+    if let Some(limit) = limit {
+        if let Some(len) = task::limit(&mut tasks, limit) {
+            writeln!(out, "processing {} of {} tasks (limited)", limit, len)?;
+        }
+    }
+
+    if m.is_present("dry-run") {
+        for t in &tasks {
+            writeln!(out, "{}", t)?;
+        }
+
+        write_estimate(out, &tasks, &HeaderCache::new())?;
+        return Ok(sources);
+    }
+
+    if stats {
+        statistics.write(out)?;
+    } else {
+        // NB: progress is weighted by the size of each file, so that a few large files don't
+        // make the bar jump.
+        let weights = tasks.iter().map(Task::weight).collect::<Vec<_>>();
+
+        let pb = if show_progress(m.is_present("no-progress"), atty::is(atty::Stream::Stderr)) {
+            indicatif::ProgressBar::new(weights.iter().sum())
+        } else {
+            indicatif::ProgressBar::hidden()
+        };
+
+        pb.set_style(indicatif::ProgressStyle::default_bar().template(
+            "{percent:>3}% [{bar:40}] {bytes}/{total_bytes} (elapsed: {elapsed}, eta: {eta})",
+        ));
+
+        let silence = silence_generator(silence_floor, seed);
+
+        let cx = Context {
+            generator: cli_generator.as_deref().unwrap_or(&silence),
+            report: m.value_of("report").map(|_| Mutex::new(Vec::new())),
+            retain_originals: m.value_of("retain-originals").map(|dir| Originals {
+                dir: PathBuf::from(dir),
+                outputs: sources.outputs.clone(),
+            }),
+            downmix_mono: m.is_present("downmix-mono"),
+            pick_channel,
+            resample,
+            headers: HeaderCache::new(),
+            tempo,
+            warn_clipping: m.is_present("warn-clipping"),
+            output_format,
+            max_censor_ratio,
+            seed,
+            pad,
+            invert: m.is_present("invert"),
+            min_silence,
+            silence_threshold,
+            silence_floor,
+            memory: max_memory.map(MemoryLimit::new),
+            dither: m.is_present("dither"),
+        };
+
+        if let Some(sample_rate) = sample_rate {
+            check_sample_rates(&tasks, sample_rate, &cx.headers)?;
+        }
+
+        let played = match m.value_of("play") {
+            Some(command) => {
+                let mut processed = tasks
+                    .iter()
+                    .filter_map(|t| match *t {
+                        Task::Process(_, ref dest, ..) => Some(dest.clone()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+
+                if processed.len() == 1 {
+                    Some((command, processed.remove(0)))
+                } else {
+                    eprintln!(
+                        "warning: not playing, since {} files are processed instead of one",
+                        processed.len()
+                    );
+                    None
+                }
+            }
+            None => None,
+        };
+
+        tasks
+            .into_par_iter()
+            .zip(weights)
+            .map(|(t, weight)| {
+                let r = run_task(&t, &cx)
+                    .with_context(|_| failure::format_err!("failed to run: {}", t));
+                pb.inc(weight);
+                r
+            })
+            .collect::<Result<(), _>>()?;
+
+        pb.finish();
+
+        if let Some((command, dest)) = played {
+            play(command, &dest)?;
+        }
+
+        if let (Some(path), Some(report)) = (m.value_of("report"), cx.report) {
+            write_report(Path::new(path), report.into_inner().expect("poisoned lock"))?;
+        }
+    }
+
+    if let Some(oiv_manifest) = m.value_of("oiv-manifest") {
+        let out = match oiv_manifest {
+            "-" => None,
+            other => Some(Path::new(other)),
+        };
+
+        write_oiv_manifest(&modified, out)?;
+    }
+
+    Ok(sources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        amplitude_from_db, dedup_replace, do_init, empty_range_warnings, extension_mismatch,
+        find_configs, opts, output_template, play, process_copy, process_silent, process_single,
+        rerun, run, run_task, show_progress, silence_generator, split_command, write_atomic,
+        write_estimate, write_list_json, write_report, Context, MemoryLimit, Missing, OutputFormat,
+        Record, Stats, CONFIG_EXTENSIONS,
+    };
+    use batchcensor::{censor, generator, task::Task, Config, HeaderCache, Range, Replace, Tempo};
+    use relative_path::RelativePath;
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    };
+
+    /// Construct a fresh temporary directory for the given test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("batchcensor-test-{}-{}", name, std::process::id()));
+
+        if dir.is_dir() {
+            std::fs::remove_dir_all(&dir).expect("failed to remove temp dir");
+        }
+
+        std::fs::create_dir_all(&dir).expect("failed to create temp dir");
+        dir
+    }
+
+    /// Construct a default context using the given generator.
+    fn context(generator: &dyn batchcensor::Generator) -> Context<'_> {
+        Context {
+            generator,
+            report: None,
+            retain_originals: None,
+            downmix_mono: false,
+            pick_channel: None,
+            resample: None,
+            headers: HeaderCache::new(),
+            tempo: None,
+            warn_clipping: false,
+            output_format: OutputFormat::Wav,
+            max_censor_ratio: None,
+            seed: 0,
+            pad: 0,
+            invert: false,
+            min_silence: 0,
+            silence_threshold: 0,
+            silence_floor: None,
+            memory: None,
+            dither: false,
+        }
+    }
+
+    /// Write a mono test file with the given samples.
+    fn write_test_wav(path: &Path, samples: &[i16]) -> Result<(), failure::Error> {
+        write_test_wav_at(path, samples, 1000)
+    }
+
+    /// Write a mono test file with the given samples and sample rate.
+    fn write_test_wav_at(
+        path: &Path,
+        samples: &[i16],
+        sample_rate: u32,
+    ) -> Result<(), failure::Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        std::fs::create_dir_all(path.parent().expect("no parent"))?;
+        let mut w = hound::WavWriter::create(path, spec)?;
+
+        for s in samples {
+            w.write_sample(*s)?;
+        }
+
+        w.finalize()?;
+        Ok(())
+    }
+
+    /// Read all samples from the given test file.
+    fn read_test_wav(path: &Path) -> Result<Vec<i16>, failure::Error> {
+        let mut r = hound::WavReader::open(path)?;
+        Ok(r.samples::<i16>().collect::<Result<Vec<_>, _>>()?)
+    }
+
+    #[test]
+    fn test_find_configs() -> Result<(), failure::Error> {
+        let dir = temp_dir("find-configs");
+        std::fs::write(dir.join("a.yml"), "dirs: []")?;
+        std::fs::write(dir.join("b.yaml"), "dirs: []")?;
+        std::fs::write(dir.join("notes.txt"), "not a config")?;
+        std::fs::write(dir.join(".DS_Store"), "")?;
+
+        assert_eq!(
+            vec![dir.join("a.yml"), dir.join("b.yaml")],
+            find_configs(&dir, CONFIG_EXTENSIONS)?
+        );
+
+        // nested configurations are sorted by their relative path, component by component.
+        for nested in &["z", "a", "a-b", "a/c"] {
+            std::fs::create_dir_all(dir.join(nested))?;
+            std::fs::write(dir.join(nested).join("c.yml"), "dirs: []")?;
+        }
+
+        std::fs::write(dir.join("a").join("b.yml"), "dirs: []")?;
+
+        assert_eq!(
+            vec![
+                dir.join("a").join("b.yml"),
+                dir.join("a").join("c").join("c.yml"),
+                dir.join("a").join("c.yml"),
+                dir.join("a-b").join("c.yml"),
+                dir.join("a.yml"),
+                dir.join("b.yaml"),
+                dir.join("z").join("c.yml"),
+            ],
+            find_configs(&dir, CONFIG_EXTENSIONS)?
+        );
+
+        // extensions can have several components, and hidden files are never configurations.
+        std::fs::write(dir.join("a.censor.yml"), "dirs: []")?;
+        std::fs::write(dir.join(".censor.yml"), "dirs: []")?;
+        assert_eq!(
+            vec![dir.join("a.censor.yml")],
+            find_configs(&dir, &["censor.yml"])?
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_dir_load_order() -> Result<(), failure::Error> {
+        let dir = temp_dir("config-dir-load-order");
+
+        // NB: created out of order, so that the order of the directory doesn't line up.
+        for name in &["c.yml", "a.yml", "b/a.yml", "b.yml"] {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().expect("no parent"))?;
+            std::fs::write(path, "dirs: []")?;
+        }
+
+        let m = opts().get_matches_from_safe(vec![
+            "batchcensor",
+            "-d",
+            dir.to_str().expect("bad path"),
+        ])?;
+
+        let sources = run(&m, None, &mut Vec::new())?;
+
+        assert_eq!(
+            vec![
+                dir.join("a.yml"),
+                dir.join("b").join("a.yml"),
+                dir.join("b.yml"),
+                dir.join("c.yml"),
+            ],
+            sources.configs
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_preserves_existing() -> Result<(), failure::Error> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+dirs:
+- path: ar2
+  file_extension: wav
+  files:
+  - AAAA_01: "hello [world]{01.000-02.000}"
+"#,
+        )?;
+
+        let root = Path::new("root");
+        let config_path = Path::new("root/config.yml");
+        let dest_root = Path::new("output/ar2");
+        let dir_path = RelativePath::new("ar2");
+
+        let mut missing = BTreeMap::new();
+        missing.insert(
+            PathBuf::from("root/ar2/AAAA_02.wav"),
+            Missing {
+                config_path,
+                dest_root,
+                dir_path,
+                output_extension: None,
+                words: &[],
+            },
+        );
+
+        let mut out = Vec::new();
+        do_init(
+            &mut out,
+            missing,
+            vec![(root, config_path, config)],
+            "[todo]",
+        )?;
+
+        let config: Config = serde_yaml::from_slice(&out)?;
+        let files = config.dirs[0]
+            .files
+            .iter()
+            .map(|(path, _, transcript, _)| {
+                (
+                    path.as_str().to_string(),
+                    transcript.map(|t| t.text.clone()).unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                (
+                    String::from("AAAA_01"),
+                    String::from("hello [world]{01.000-02.000}")
+                ),
+                (String::from("AAAA_02"), String::from("[todo]")),
+            ],
+            files
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_files() -> Result<(), failure::Error> {
+        let dir = temp_dir("empty-files");
+
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        hound::WavWriter::create(dir.join("zero.wav"), spec)?.finalize()?;
+        std::fs::write(dir.join("empty.wav"), "")?;
+
+        let generator = generator::Silence::new();
+        let cx = context(&generator);
+
+        let replace = Replace::new(
+            String::from("word"),
+            Range::parse("^-$").expect("valid range"),
+        );
+
+        for name in &["zero.wav", "empty.wav"] {
+            let source = dir.join(name);
+            let processed = dir.join("processed").join(name);
+            let silenced = dir.join("silenced").join(name);
 
-            writeln!(
-                fmt,
-                "{}<add source=\"{}\">{}</add>",
-                prefix, self.source, self.value
-            )?;
+            process_single(&source, &processed, &[&replace], cx.generator, &cx)?;
+            process_silent(&source, &silenced, &cx)?;
 
-            Ok(())
+            assert_eq!(std::fs::read(&source)?, std::fs::read(&processed)?);
+            assert_eq!(std::fs::read(&source)?, std::fs::read(&silenced)?);
         }
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 
-    #[derive(Debug)]
-    struct Archive {
-        path: String,
-        create_if_not_exists: &'static str,
-        ty: String,
-        add: Vec<Add>,
+    #[test]
+    fn test_process_silent_floor() -> Result<(), failure::Error> {
+        let dir = temp_dir("process-silent-floor");
+        let source = dir.join("a.wav");
+        write_test_wav(&source, &[1000i16; 1000])?;
+
+        let silenced = dir.join("silenced.wav");
+        let other = dir.join("other.wav");
+
+        let generator = generator::Silence::new();
+        let mut cx = context(&generator);
+
+        process_silent(&source, &silenced, &cx)?;
+        assert_eq!(vec![0i16; 1000], read_test_wav(&silenced)?);
+
+        let amplitude = amplitude_from_db(-40f32);
+        let generator = silence_generator(Some(amplitude), cx.seed);
+        cx.generator = &generator;
+        cx.silence_floor = Some(amplitude);
+
+        process_silent(&source, &silenced, &cx)?;
+        let data = read_test_wav(&silenced)?;
+        let limit = (amplitude * i16::MAX as f32) as i16;
+
+        assert_eq!(1000, data.len());
+        assert!(data.iter().any(|s| *s != 0));
+        assert!(data.iter().all(|s| s.abs() <= limit), "{:?}", data);
+
+        // silenced files have the same noise floor as a replacement of the whole file with the
+        // same destination.
+        let replace = Replace::new(
+            String::from("word"),
+            Range::parse("^-$").expect("valid range"),
+        );
+        run_task(
+            &Task::Process(source.clone(), silenced.clone(), vec![&replace], None),
+            &cx,
+        )?;
+        assert_eq!(data, read_test_wav(&silenced)?);
+
+        // the noise is seeded by the destination.
+        process_silent(&source, &other, &cx)?;
+        assert_ne!(data, read_test_wav(&other)?);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 
-    impl Archive {
-        pub fn to_xml(&self, fmt: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
-            let prefix = std::iter::repeat(' ').take(depth).collect::<String>();
+    #[test]
+    fn test_write_atomic() -> Result<(), failure::Error> {
+        let dir = temp_dir("write-atomic");
+        let dest = dir.join("out").join("file.wav");
 
-            writeln!(
-                fmt,
-                "{}<archive path=\"{}\" createIfNotExist=\"{}\" type=\"{}\">",
-                prefix, self.path, self.create_if_not_exists, self.ty
-            )?;
+        let result = write_atomic(&dest, |temp| {
+            std::fs::write(temp, "partial")?;
+            failure::bail!("write failed");
+        });
 
-            for a in &self.add {
-                a.to_xml(fmt, depth + 2)?;
-            }
+        assert!(result.is_err());
+        assert!(!dest.exists());
+        assert_eq!(0, std::fs::read_dir(dir.join("out"))?.count());
 
-            writeln!(fmt, "{}</archive>", prefix)?;
+        write_atomic(&dest, |temp| {
+            std::fs::write(temp, "complete")?;
             Ok(())
-        }
-    }
+        })?;
 
-    #[derive(Debug, Default)]
-    struct Content {
-        archives: Vec<Archive>,
-    }
+        let result = write_atomic(&dest, |temp| {
+            std::fs::write(temp, "partial")?;
+            failure::bail!("write failed");
+        });
 
-    impl Content {
-        pub fn to_xml(&self, fmt: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
-            let prefix = std::iter::repeat(' ').take(depth).collect::<String>();
+        assert!(result.is_err());
+        assert_eq!("complete", std::fs::read_to_string(&dest)?);
 
-            writeln!(fmt, "{}<content>", prefix)?;
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 
-            for a in &self.archives {
-                a.to_xml(fmt, depth + 2)?;
-            }
+    #[test]
+    fn test_watch_reruns() -> Result<(), failure::Error> {
+        let dir = temp_dir("watch-reruns");
+
+        write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+
+        let config = dir.join("config.yml");
+        let write_config = |transcript: &str| {
+            std::fs::write(
+                &config,
+                format!(
+                    "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"{}\"\n",
+                    transcript
+                ),
+            )
+        };
 
-            writeln!(fmt, "{}</content>", prefix)?;
-            Ok(())
-        }
+        let output = dir.join("output").join("ar2").join("A.wav");
+        write_config("[a]")?;
+
+        let m = opts().get_matches_from_safe(vec![
+            "batchcensor",
+            "-c",
+            config.to_str().expect("bad path"),
+        ])?;
+        let sources = run(&m, None, &mut Vec::new())?;
+        assert!(read_test_wav(&output)?.iter().all(|s| *s == 0));
+
+        // a changed configuration re-runs everything.
+        let watch = sources.watch()?;
+        write_config("[a]{^-.100}")?;
+        let sources = rerun(&m, &sources, &watch, &mut Vec::new())?;
+        let data = read_test_wav(&output)?;
+        assert!(data[..100].iter().all(|s| *s == 0));
+        assert!(data[100..].iter().all(|s| *s == 100));
+
+        // and so does a changed source file.
+        write_test_wav(&dir.join("ar2").join("A.wav"), &[200i16; 1000])?;
+        rerun(&m, &sources, &watch, &mut Vec::new())?;
+        let data = read_test_wav(&output)?;
+        assert!(data[..100].iter().all(|s| *s == 0));
+        assert!(data[100..].iter().all(|s| *s == 200));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 
-    impl fmt::Display for Content {
-        fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-            self.to_xml(fmt, 0)
-        }
-    }
-}
+    #[test]
+    fn test_seeded_noise() -> Result<(), failure::Error> {
+        let dir = temp_dir("seeded-noise");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
+        write_test_wav(&source, &[100i16; 1000])?;
 
-/// Initialize missing files into the current set of configurations.
-fn do_init<'a>(
-    out: &mut impl io::Write,
-    missing: BTreeMap<PathBuf, Missing<'a>>,
-    mut configs: Vec<(&'a Path, &'a Path, Config)>,
-) -> Result<(), failure::Error> {
-    for m in missing {
-        for (root, config_path, config) in &mut configs {
-            if *config_path != (m.1).0 {
-                continue;
-            }
+        let replace = Replace::new(
+            String::from("a"),
+            Range::parse("^-.500").expect("valid range"),
+        );
 
-            let (path, Missing(_, _, dir_path)) = m;
+        let generator = generator::Noise::new(0);
+        let mut cx = context(&generator);
+        cx.seed = 42;
 
-            let path = path.strip_prefix(&root)?;
+        let task = Task::Process(source, dest.clone(), vec![&replace], None);
 
-            let mut c = path.components();
-            for _ in (&mut c).take(dir_path.components().count()) {}
-            let path = RelativePath::from_path(c.as_path())?;
+        run_task(&task, &cx)?;
+        let first = read_test_wav(&dest)?;
+        run_task(&task, &cx)?;
+        assert_eq!(first, read_test_wav(&dest)?);
+        assert!(first[..500].iter().any(|s| *s != 0));
 
-            let transcript = Transcript::parse("[missing]")?;
-            config.insert_file(dir_path, path.to_owned(), transcript)?;
-            break;
-        }
-    }
+        cx.seed = 43;
+        run_task(&task, &cx)?;
+        assert_ne!(first, read_test_wav(&dest)?);
 
-    // optimize all configurations.
-    for (_, _, config) in &mut configs {
-        config.optimize()?;
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 
-    for (_, _, config) in &configs {
-        serde_yaml::to_writer(&mut *out, &config)?;
-    }
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_skips_identical() -> Result<(), failure::Error> {
+        use std::os::unix::fs::MetadataExt as _;
 
-    return Ok(());
-}
+        let dir = temp_dir("copy-skips-identical");
+        let source = dir.join("source.bin");
+        let dest = dir.join("output").join("dest.bin");
+        std::fs::write(&source, "hello")?;
 
-fn main() -> Result<(), failure::Error> {
-    use rayon::prelude::*;
+        process_copy(&source, &dest)?;
+        let first = std::fs::metadata(&dest)?;
+        assert_eq!(std::fs::metadata(&source)?.modified()?, first.modified()?);
 
-    let m = opts().get_matches();
-    let list = m.is_present("list");
-    let stats = m.is_present("stats");
-    let tone = m.is_present("tone");
-    let output = m.value_of("output").map(PathBuf::from);
-    let init = m.value_of("init");
+        // an unchanged file is not written again, which would replace it with a new file.
+        process_copy(&source, &dest)?;
+        assert_eq!(first.ino(), std::fs::metadata(&dest)?.ino());
 
-    let mut counts = BTreeMap::<String, u64>::new();
+        std::fs::write(&source, "hello world")?;
+        process_copy(&source, &dest)?;
+        assert_eq!("hello world", std::fs::read_to_string(&dest)?);
 
-    let mut configs = Vec::new();
-    configs.extend(
-        m.values_of("config")
-            .into_iter()
-            .flat_map(|c| c)
-            .map(PathBuf::from),
-    );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 
-    if let Some(config_dir) = m.value_of("config-dir") {
-        for result in ignore::Walk::new(config_dir) {
-            let result = result?;
-            let path = result.path();
+    #[test]
+    fn test_invert() -> Result<(), failure::Error> {
+        let dir = temp_dir("invert");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
+        write_test_wav(&source, &[100i16; 1000])?;
 
-            if !path.is_file() {
-                continue;
-            }
+        let replace = Replace::new(
+            String::from("a"),
+            Range::parse(".200-.500").expect("valid range"),
+        );
 
-            match path.extension().and_then(|s| s.to_str()) {
-                Some("yml") => {}
-                _ => {}
-            }
+        let generator = generator::Tone::new();
+        let mut cx = context(&generator);
+        cx.invert = true;
 
-            configs.push(path.to_owned());
-        }
+        process_single(&source, &dest, &[&replace], cx.generator, &cx)?;
+
+        let data = read_test_wav(&dest)?;
+        assert!(data[..200].iter().all(|s| *s == 0));
+        assert!(data[200..500].iter().all(|s| *s == 100));
+        assert!(data[500..].iter().all(|s| *s == 0));
+
+        assert!(opts()
+            .get_matches_from_safe(["batchcensor", "--invert", "--tone"])
+            .is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 
-    let default_root = m.value_of("root").map(Path::new);
+    #[test]
+    fn test_beat_positions() -> Result<(), failure::Error> {
+        let dir = temp_dir("beat-positions");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
+        write_test_wav(&source, &[100i16; 4000])?;
 
-    let configs = configs
-        .iter()
-        .map(|path| {
-            let f = File::open(path).with_context(|_| {
-                failure::format_err!("could not open configuration: {}", path.display())
-            })?;
+        let replace = Replace::new(
+            String::from("word"),
+            Range::parse("b2-b3").expect("valid range"),
+        );
 
-            let config: Config = serde_yaml::from_reader(f)
-                .with_context(|_| failure::format_err!("failed to parse: {}", path.display()))?;
+        let generator = generator::Silence::new();
+        let mut cx = context(&generator);
 
-            let root = match default_root {
-                Some(root) => root,
-                None => path.parent().ok_or_else(|| {
-                    failure::format_err!("config does not have a parent directory")
-                })?,
-            };
+        let e = process_single(&source, &dest, &[&replace], cx.generator, &cx)
+            .expect_err("expected error");
+        assert!(e.to_string().contains("--bpm"), "{}", e);
 
-            Ok((root, path.as_path(), config))
-        })
-        .collect::<Result<Vec<_>, failure::Error>>()?;
+        // at 120bpm in 2/4, bar 2 starts after one second.
+        cx.tempo = Tempo::new(120f32).with_time_signature("2/4");
+        process_single(&source, &dest, &[&replace], cx.generator, &cx)?;
 
-    let mut tasks = Vec::new();
+        let data = read_test_wav(&dest)?;
+        assert!(data[..1000].iter().all(|s| *s == 100));
+        assert!(data[1000..2000].iter().all(|s| *s == 0));
+        assert!(data[2000..].iter().all(|s| *s == 100));
 
-    // keep track if we are processing any files, which will determine what goes into the manifest.
-    let mut modified = BTreeSet::new();
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 
-    let mut missing = BTreeMap::<PathBuf, Missing>::new();
-    let mut silenced = BTreeMap::<PathBuf, Missing>::new();
-    let mut roots = HashMap::new();
-    let mut dirs = HashMap::<PathBuf, Vec<_>>::new();
+    #[test]
+    fn test_tuned_tone() -> Result<(), failure::Error> {
+        let dir = temp_dir("tuned-tone");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
+        write_test_wav(&source, &[0i16; 2000])?;
+
+        let mut low = Replace::new(
+            String::from("a"),
+            Range::parse("^-01.000").expect("valid range"),
+        );
+        low.frequency = Some(100f32);
+        let mut high = Replace::new(
+            String::from("b"),
+            Range::parse("01.000-$").expect("valid range"),
+        );
+        high.frequency = Some(250f32);
+        high.amplitude = Some(0.5f32);
+
+        let generator = generator::Tone::new();
+        let cx = context(&generator);
+        process_single(&source, &dest, &[&low, &high], cx.generator, &cx)?;
+
+        let data = read_test_wav(&dest)?;
+
+        // at 1000Hz, a 100Hz tone crosses zero every 5 samples, and a 250Hz tone every 2.
+        let crossings = |data: &[i16]| data.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+
+        assert_eq!(199, crossings(&data[..1000]));
+        assert_eq!(499, crossings(&data[1000..]));
+
+        let peak = |data: &[i16]| data.iter().map(|s| i32::from(*s).abs()).max();
+        assert!(peak(&data[1000..]) > peak(&data[..1000]));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 
-    // Go through all configurations and construct root directories.
-    for (root, config_path, config) in &configs {
-        let output = output
-            .as_ref()
-            .cloned()
-            .unwrap_or_else(|| root.join("output"));
+    #[test]
+    fn test_max_censor_ratio() -> Result<(), failure::Error> {
+        let dir = temp_dir("max-censor-ratio");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
+        write_test_wav(&source, &[100i16; 2000])?;
+
+        // swapped minutes and seconds, which censors almost all of the file.
+        let typo = Replace::new(
+            String::from("a"),
+            Range::parse("00.100-01:00.000").expect("valid range"),
+        );
+        let ok = Replace::new(
+            String::from("a"),
+            Range::parse("00.100-01.000").expect("valid range"),
+        );
+
+        let generator = generator::Silence::new();
+        let mut cx = context(&generator);
+        cx.max_censor_ratio = Some(0.5f32);
+
+        let e = process_single(&source, &dest, &[&typo], cx.generator, &cx)
+            .expect_err("too much censored");
+        let message = e.to_string();
+        assert!(message.contains("95.0%"), "{}", message);
+        assert!(message.contains("[a]{.100-02.000}"), "{}", message);
+        assert!(!dest.is_file());
+
+        process_single(&source, &dest, &[&ok], cx.generator, &cx)?;
+        assert!(dest.is_file());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 
-        for dir in &config.dirs {
-            let root = dir.path.to_path(&root);
+    #[test]
+    fn test_extension_mismatch() {
+        let files = vec![PathBuf::from("ar2/A.WAV"), PathBuf::from("ar2/A.oac")];
 
-            if !root.is_dir() {
-                failure::bail!("no such directory: {}", root.display());
-            }
+        let hint = extension_mismatch(&files, "wav").expect("mismatch");
+        assert!(hint.contains("found `WAV`"), "{}", hint);
+        assert!(extension_mismatch(&files, "WAV").is_none());
 
-            dirs.entry(root.clone()).or_default().push(dir);
+        let hint = extension_mismatch(&files, "flac").expect("mismatch");
+        assert!(hint.contains("found: WAV, oac"), "{}", hint);
 
-            let mut dest_root = output.to_owned();
+        // an empty directory isn't a mismatch.
+        assert!(extension_mismatch(&[], "wav").is_none());
+    }
 
-            for c in dir.path.components() {
-                dest_root.push(c.as_str());
-            }
+    #[test]
+    fn test_output_template() {
+        assert_eq!(
+            PathBuf::from("out/a/b"),
+            output_template(Path::new("out/{config}/b"), Path::new("configs/a.yml"))
+        );
+        assert_eq!(
+            PathBuf::from("out"),
+            output_template(Path::new("out"), Path::new("configs/a.yml"))
+        );
+    }
 
-            roots.insert(root, (dest_root, *config_path, config, &dir.path));
-        }
+    #[test]
+    fn test_show_progress() {
+        assert!(show_progress(false, true));
+        assert!(!show_progress(true, true));
+        assert!(!show_progress(false, false));
+        assert!(!show_progress(true, false));
     }
 
-    for (root, (dest_root, config_path, config, dir_path)) in &roots {
-        if !root.is_dir() {
-            failure::bail!("no such directory: {}", root.display());
-        }
+    #[test]
+    fn test_amplitude_from_db() {
+        assert!((amplitude_from_db(-6f32) - 0.501).abs() < 0.001);
+        assert!((amplitude_from_db(-20f32) - 0.1).abs() < 0.001);
+        assert_eq!(1f32, amplitude_from_db(0f32));
+        assert_eq!(1f32, amplitude_from_db(6f32));
+    }
 
-        // copy corresponding .oac file if present.
-        {
-            let oac = root.with_extension("oac");
+    #[test]
+    fn test_pick_channel() -> Result<(), failure::Error> {
+        let dir = temp_dir("pick-channel");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
+
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
 
-            if oac.is_file() {
-                tasks.push(Task::Copy(oac, dest_root.with_extension("oac")));
-            }
+        let mut w = hound::WavWriter::create(&source, spec)?;
+
+        for i in 0..1000 {
+            w.write_sample(100i16)?;
+            w.write_sample(i as i16)?;
         }
 
-        for result in ignore::Walk::new(&root) {
-            let result = result?;
-            let path = result.path().to_owned();
+        w.finalize()?;
 
-            if !path.is_file() {
-                continue;
-            }
+        // the range is resolved in stereo, and covers the first 100 frames.
+        let replace = Replace::new(
+            String::from("a"),
+            Range::parse("^-.100").expect("valid range"),
+        );
 
-            match path.extension().and_then(|s| s.to_str()) {
-                Some("wav") => {}
-                _ => {
-                    let dest = dest_root.join(path.strip_prefix(&root)?);
-                    // NB: straight up copy other files.
-                    tasks.push(Task::Copy(path, dest));
-                    continue;
-                }
-            }
+        let generator = generator::Silence::new();
+        let mut cx = context(&generator);
+        cx.pick_channel = Some(1);
 
-            // Keep track of all files to produce a list of files missing configuration in the end.
-            missing.insert(path, Missing(config_path, dest_root, *dir_path));
-        }
+        process_single(&source, &dest, &[&replace], cx.generator, &cx)?;
 
-        // Process all dirs.
-        for dir in dirs.get(root).into_iter().flat_map(|r| r) {
-            for (i, (path, mut replace, transcript)) in dir.files.iter().enumerate() {
-                let file_extension = dir
-                    .file_extension
-                    .as_ref()
-                    .or(config.file_extension.as_ref());
-
-                // temp storage for modified path so that we can continue dealing with references.
-                let mut path = Cow::Borrowed(path);
-
-                // replace a `$$` in any component present with the current enumeration.
-                path = utils::path_enumeration(i, path);
-                path = utils::path_file_prefix(dir.prefix.as_ref().map(|s| s.as_str()), path);
-                path = utils::path_file_suffix(dir.suffix.as_ref().map(|s| s.as_str()), path);
-
-                if let Some(file_extension) = file_extension {
-                    path = Cow::Owned(path.with_extension(file_extension));
-                }
+        let r = hound::WavReader::open(&dest)?;
+        assert_eq!(1, r.spec().channels);
 
-                let path = path.to_path(&root);
+        let data = read_test_wav(&dest)?;
+        assert_eq!(1000, data.len());
+        assert!(data[..100].iter().all(|s| *s == 0));
+        assert_eq!((100..1000).collect::<Vec<i16>>(), data[100..].to_vec());
 
-                let dest = dest_root.join(
-                    path.file_name()
-                        .ok_or_else(|| failure::format_err!("expected file name"))?,
-                );
+        cx.pick_channel = Some(2);
+        let e = process_single(&source, &dest, &[&replace], cx.generator, &cx)
+            .expect_err("channel out of range");
+        assert!(e.to_string().contains("2 channel(s)"), "{}", e);
+        assert!(process_silent(&source, &dest, &cx).is_err());
 
-                let indexed = match missing.remove(&path) {
-                    Some(indexed) => indexed,
-                    None => {
-                        failure::bail!("did not expect to censor file: {}", path.display());
-                    }
-                };
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 
-                if let Some(transcript) = transcript {
-                    // file silenced because it has marked words which do not have a range.
-                    if !transcript.missing.is_empty() {
-                        silenced.insert(path.clone(), indexed);
-                        tasks.push(Task::Silence(path, dest));
-                        continue;
-                    }
+    #[test]
+    fn test_truncated_wav() -> Result<(), failure::Error> {
+        let dir = temp_dir("truncated-wav");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
 
-                    replace.extend(transcript.replace.iter());
-                }
+        write_test_wav(&source, &[1000; 100])?;
 
-                // audio file already clean.
-                if replace.is_empty() {
-                    tasks.push(Task::Copy(path, dest));
-                    continue;
-                }
+        // NB: drop the last samples without fixing up the header.
+        let len = std::fs::metadata(&source)?.len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&source)?
+            .set_len(len - 21)?;
 
-                if stats {
-                    for r in replace.iter().cloned() {
-                        *counts.entry(r.word.to_lowercase()).or_default() += 1;
-                    }
-                }
+        let generator = generator::Silence::new();
+        let cx = context(&generator);
 
-                modified.insert(dir.path.to_owned());
-                tasks.push(Task::Process(path, dest, replace));
-            }
-        }
+        let e = process_single(&source, &dest, &[], cx.generator, &cx)
+            .expect_err("truncated file should fail");
+
+        assert_eq!(
+            format!("failed decoding {} at sample 89", source.display()),
+            e.to_string()
+        );
+        assert!(!dest.exists());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 
-    if init.is_some() {
-        if missing.is_empty() {
-            println!("nothing to initialize: there are no missing files!");
-            return Ok(());
-        }
+    #[test]
+    fn test_report_escaping() -> Result<(), failure::Error> {
+        let dir = temp_dir("report-escaping");
+        let report = dir.join("report.json");
+
+        let applied = |word: &str, start: usize| censor::Applied {
+            word: word.to_string(),
+            index: 0,
+            range: start..start + 10,
+            generator: String::from("tone"),
+            original: Vec::new(),
+        };
 
-        match init {
-            None | Some("-") => {
-                let out = io::stdout();
-                return do_init(&mut out.lock(), missing, configs.clone());
-            }
-            Some(other) => {
-                let other = Path::new(other);
+        let records = vec![
+            Record {
+                source: PathBuf::from("input/b\t\"\u{1}.wav"),
+                dest: PathBuf::from("output/b\t\"\u{1}.wav"),
+                replacements: vec![applied("\\n\u{7f}", 20)],
+            },
+            Record {
+                source: PathBuf::from("input/å ä ö.wav"),
+                dest: PathBuf::from("output/å ä ö.wav"),
+                replacements: vec![applied("smörgåsbord", 0), applied("🦀", 5)],
+            },
+        ];
+
+        write_report(&report, records)?;
+
+        let value: serde_json::Value = serde_json::from_slice(&std::fs::read(&report)?)?;
+        let entries = value.as_array().expect("an array");
+        assert_eq!(2, entries.len());
+
+        assert_eq!("input/b\t\"\u{1}.wav", entries[0]["source"]);
+        assert_eq!("output/b\t\"\u{1}.wav", entries[0]["dest"]);
+        assert_eq!("\\n\u{7f}", entries[0]["replacements"][0]["word"]);
+        assert_eq!(20, entries[0]["replacements"][0]["start_sample"]);
+        assert_eq!(30, entries[0]["replacements"][0]["end_sample"]);
+        assert_eq!("tone", entries[0]["replacements"][0]["generator"]);
+
+        assert_eq!("output/å ä ö.wav", entries[1]["dest"]);
+        assert_eq!("smörgåsbord", entries[1]["replacements"][0]["word"]);
+        assert_eq!("🦀", entries[1]["replacements"][1]["word"]);
+
+        // Control characters are escaped rather than written out raw.
+        let content = std::fs::read_to_string(&report)?;
+        assert!(content.contains("\\t\\\"\\u0001"));
+        assert!(!content.contains('\u{1}'));
+        Ok(())
+    }
 
-                let mut f = File::create(other).with_context(|_| {
-                    failure::format_err!(
-                        "failed to open init file for writing: {}",
-                        other.display()
-                    )
-                })?;
+    #[test]
+    fn test_list_json() -> Result<(), failure::Error> {
+        let config = Path::new("config.yml");
+        let dest = Path::new("output");
+        let dir = RelativePath::new("ar2");
+        let words = vec![String::from("a \"quoted\" word"), String::from("b")];
+
+        let mut missing = BTreeMap::new();
+        missing.insert(
+            PathBuf::from("ar2/A.wav"),
+            Missing {
+                config_path: config,
+                dest_root: dest,
+                dir_path: dir,
+                output_extension: None,
+                words: &[],
+            },
+        );
+
+        let mut silenced = BTreeMap::new();
+        silenced.insert(
+            PathBuf::from("ar2/B.wav"),
+            Missing {
+                config_path: config,
+                dest_root: dest,
+                dir_path: dir,
+                output_extension: None,
+                words: &words,
+            },
+        );
+
+        let mut out = Vec::new();
+        write_list_json(&mut out, &missing, &silenced)?;
+
+        let value: serde_json::Value = serde_json::from_slice(&out)?;
+        let entries = value.as_array().expect("an array");
+        assert_eq!(2, entries.len());
+
+        let field = |i: usize, key: &str| entries[i][key].clone();
+        let words = |i: usize| {
+            field(i, "missing_words")
+                .as_array()
+                .expect("an array")
+                .iter()
+                .map(|w| w.as_str().expect("a string").to_string())
+                .collect::<Vec<_>>()
+        };
 
-                return do_init(&mut f, missing, configs.clone());
-            }
-        }
+        assert_eq!(Some("ar2/A.wav"), field(0, "path").as_str());
+        assert_eq!(Some("config.yml"), field(0, "config").as_str());
+        assert_eq!(Some("missing"), field(0, "status").as_str());
+        assert!(words(0).is_empty());
+
+        assert_eq!(Some("ar2/B.wav"), field(1, "path").as_str());
+        assert_eq!(Some("silenced"), field(1, "status").as_str());
+        assert_eq!(vec!["a \"quoted\" word", "b"], words(1));
+
+        out.clear();
+        write_list_json(&mut out, &BTreeMap::new(), &BTreeMap::new())?;
+        assert_eq!("[]\n", String::from_utf8(out)?);
+        Ok(())
     }
 
-    if !missing.is_empty() || !silenced.is_empty() {
-        if !list {
-            if !missing.is_empty() {
-                eprintln!(
-                    "Missing censor configuration for {} file(s) (--list to see them)",
-                    missing.len()
-                );
-            }
+    #[test]
+    fn test_empty_range_warnings() -> Result<(), failure::Error> {
+        let dir = temp_dir("empty-range-warnings");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
+        write_test_wav(&source, &[100i16; 1000])?;
+
+        let empty = Replace::new(
+            String::from("a"),
+            Range::parse("00:00.500-00:00.500").expect("valid range"),
+        );
+
+        let generator = generator::Silence::new();
+        let cx = context(&generator);
+
+        process_single(&source, &dest, &[&empty], cx.generator, &cx)?;
+        assert_eq!(read_test_wav(&source)?, read_test_wav(&dest)?);
+
+        let applied = censor::apply_replacements(
+            &mut read_test_wav(&source)?,
+            hound::WavReader::open(&source)?.spec(),
+            1000,
+            &[&empty],
+            &generator,
+            &censor::Options::default(),
+        )?;
+
+        assert_eq!(
+            vec![format!(
+                "warning: {}: [a]{{.500-.500}} is empty, so nothing is censored",
+                source.display()
+            )],
+            empty_range_warnings(&source, &[&empty], &applied)
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 
-            if !silenced.is_empty() {
-                eprintln!(
-                    "Silenced censor configuration for {} file(s) (--list to see them)",
-                    silenced.len()
-                );
-            }
-        } else {
-            for (path, Missing(config_path, ..)) in &missing {
-                eprintln!(
-                    "{}: missing config for: {}",
-                    config_path.display(),
-                    path.display()
-                );
-            }
+    #[test]
+    fn test_memory_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
-            for (path, Missing(config_path, ..)) in &silenced {
-                eprintln!(
-                    "{}: silenced config for: {}",
-                    config_path.display(),
-                    path.display()
-                );
+        let memory = MemoryLimit::new(1000);
+        let active = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let run = |bytes: u64| {
+            let _reservation = memory.reserve(bytes);
+            let n = active.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(n, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            active.fetch_sub(1, Ordering::SeqCst);
+        };
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| run(400));
             }
-        }
+        });
 
-        for (path, Missing(_, dest_root, file)) in missing.into_iter().chain(silenced.into_iter()) {
-            let dest = dest_root.join(
-                path.file_name()
-                    .and_then(|n| n.to_str())
-                    .ok_or_else(|| failure::format_err!("expected file name"))?,
-            );
+        assert!(peak.load(Ordering::SeqCst) <= 2);
 
-            modified.insert(file.to_owned());
-            tasks.push(Task::Silence(path, dest));
-        }
+        // reservations larger than the limit still run, but only on their own.
+        peak.store(0, Ordering::SeqCst);
+
+        std::thread::scope(|s| {
+            for bytes in &[5000, 5000, 400] {
+                s.spawn(move || run(*bytes));
+            }
+        });
+
+        assert_eq!(1, peak.load(Ordering::SeqCst));
+        assert_eq!(0, *memory.reserved.lock().expect("poisoned lock"));
     }
 
-    if stats {
-        println!("# Statistics (--stats)");
+    #[test]
+    fn test_dedup_replace() -> Result<(), failure::Error> {
+        let a = Replace::new(
+            String::from("a"),
+            Range::parse("00:01-00:02").expect("valid range"),
+        );
+        let b = Replace::new(
+            String::from("b"),
+            Range::parse("00:01-00:02").expect("valid range"),
+        );
+        let copy = a.clone();
+
+        let mut replace = vec![&a, &b, &copy, &b];
+        dedup_replace(&mut replace);
+        assert_eq!(vec![&a, &b], replace);
+
+        let mut stats = Stats::default();
+        stats.processed(RelativePath::new("ar2"), &replace);
+        assert_eq!(Some(&1), stats.words.get("a"));
+        assert_eq!(
+            Some(2),
+            stats
+                .dirs
+                .get(RelativePath::new("ar2"))
+                .map(|d| d.replacements)
+        );
+        Ok(())
+    }
 
-        for (word, count) in counts {
-            println!("{} - {}", word, count);
+    #[test]
+    fn test_write_estimate() -> Result<(), failure::Error> {
+        let dir = temp_dir("write-estimate");
+        let out = dir.join("out");
+
+        let notes = dir.join("notes.txt");
+        std::fs::write(&notes, "some notes")?;
+        let a = dir.join("a.wav");
+        write_test_wav(&a, &[100i16; 1000])?;
+        let b = dir.join("b.wav");
+        write_test_wav(&b, &[100i16; 500])?;
+
+        let replace = Replace::new(
+            String::from("a"),
+            Range::parse("^-.100").expect("valid range"),
+        );
+
+        let tasks = vec![
+            Task::Copy(notes, out.join("notes.txt")),
+            Task::Process(a, out.join("a.wav"), vec![&replace], None),
+            Task::Silence(b.clone(), out.join("b.wav")),
+            Task::Silence(b, out.join("c.wav")),
+        ];
+
+        let mut text = Vec::new();
+        let estimate = write_estimate(&mut text, &tasks, &HeaderCache::new())?;
+        let text = String::from_utf8(text)?;
+
+        assert!(text.contains("copy - 1 file(s)"), "{}", text);
+        assert!(text.contains("process - 1 file(s)"), "{}", text);
+        assert!(text.contains("silence - 2 file(s)"), "{}", text);
+
+        let generator = batchcensor::generator::Silence::new();
+        let cx = context(&generator);
+
+        for task in &tasks {
+            run_task(task, &cx)?;
         }
-    } else {
-        let pb = indicatif::ProgressBar::new(tasks.len() as u64);
 
-        let generator = if tone {
-            Box::new(generator::Tone::new()) as Box<dyn Generator>
-        } else {
-            Box::new(generator::Silence::new()) as Box<dyn Generator>
-        };
+        let actual = tasks
+            .iter()
+            .map(|t| std::fs::metadata(t.dest()).map(|m| m.len()))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .sum::<u64>();
 
-        tasks
-            .into_par_iter()
-            .map(|t| {
-                let r = t
-                    .run(&*generator)
-                    .with_context(|_| failure::format_err!("failed to run: {}", t));
-                pb.inc(1);
-                r
-            })
-            .collect::<Result<(), _>>()?;
+        assert!(
+            (estimate as f64 - actual as f64).abs() / (actual as f64) < 0.01,
+            "estimate {} vs actual {}",
+            estimate,
+            actual
+        );
 
-        pb.finish();
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 
-    if let Some(oiv_manifest) = m.value_of("oiv-manifest") {
-        let out = match oiv_manifest {
-            "-" => None,
-            other => Some(Path::new(other)),
-        };
+    #[test]
+    fn test_dither() -> Result<(), failure::Error> {
+        let dir = temp_dir("dither");
+        let source = dir.join("source.wav");
+        let dest = dir.join("dest.wav");
+
+        let samples = (0..1000).map(|i| (i * 7 % 200) as i16).collect::<Vec<_>>();
+        write_test_wav(&source, &samples)?;
+
+        let generator = generator::Silence::new();
+        let mut cx = context(&generator);
+        cx.resample = Some(3000);
+
+        // without dither, interpolated samples are rounded.
+        process_single(&source, &dest, &[], &generator, &cx)?;
+        let exact = read_test_wav(&dest)?;
+        assert_eq!(
+            batchcensor::process::resample(&samples, 1, 1000, 3000),
+            exact
+        );
+
+        cx.dither = true;
+        process_single(&source, &dest, &[], &generator, &cx)?;
+        let dithered = read_test_wav(&dest)?;
+
+        assert_eq!(exact.len(), dithered.len());
+        assert!(exact
+            .iter()
+            .zip(&dithered)
+            .all(|(a, b)| (i32::from(*a) - i32::from(*b)).abs() <= 2));
+        assert_ne!(exact, dithered);
+
+        // dither is reproducible with the same seed.
+        process_single(&source, &dest, &[], &generator, &cx)?;
+        assert_eq!(dithered, read_test_wav(&dest)?);
+
+        cx.seed = 1;
+        process_single(&source, &dest, &[], &generator, &cx)?;
+        assert_ne!(dithered, read_test_wav(&dest)?);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 
-        write_oiv_manifest(&modified, out)?;
+    #[test]
+    fn test_split_command() -> Result<(), failure::Error> {
+        assert_eq!(vec!["a", "b"], split_command("  a \tb ")?);
+        assert_eq!(
+            vec!["C:\\Program Files\\vlc.exe", "{}", "it's"],
+            split_command("\"C:\\Program Files\\vlc.exe\" {} \"it's\"")?
+        );
+        assert_eq!(vec!["--out=a b", ""], split_command("--out='a b' ''")?);
+        assert!(split_command("").map(|args| args.is_empty())?);
+        assert!(split_command("cp \"a").is_err());
+        Ok(())
     }
 
-    Ok(())
+    #[cfg(unix)]
+    #[test]
+    fn test_play() -> Result<(), failure::Error> {
+        let dir = temp_dir("play").join("with space");
+        std::fs::create_dir_all(&dir)?;
+        let played = dir.join("played.wav");
+        write_test_wav(&played, &[100i16; 100])?;
+
+        // the path is appended if the command doesn't have a placeholder.
+        let appended = dir.join("appended.wav");
+        play(&format!("cp '{}'", played.display()), &appended)?;
+        assert_eq!(std::fs::read(&played)?, std::fs::read(&appended)?);
+
+        // `{}` is replaced with the path, even if it has spaces.
+        let substituted = dir.join("substituted copy.wav");
+        play(&format!("cp {{}} \"{}\"", substituted.display()), &played)?;
+        assert_eq!(std::fs::read(&played)?, std::fs::read(&substituted)?);
+
+        assert!(play("false", &played).is_err());
+        assert!(play("", &played).is_err());
+
+        std::fs::remove_dir_all(dir.parent().expect("no parent"))?;
+        Ok(())
+    }
 }
@@ -0,0 +1,158 @@
+//! Manifests for OpenIV packages (.oiv) of censored GTA V audio.
+
+use crate::Error;
+use relative_path::RelativePathBuf;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// Build the .oiv manifest for the given modified directories.
+///
+/// The first component of each directory is the archive it belongs to, and the rest is the path
+/// of its audio file in the archive, like `dlc/ar2` in `sfx/dlc/ar2`.
+pub fn manifest(modified: &BTreeSet<RelativePathBuf>) -> Result<String, Error> {
+    use std::collections::btree_map::Entry;
+
+    let mut archives = BTreeMap::new();
+
+    for m in modified {
+        let mut c = m.components();
+
+        let (rpf, audio_file) = match (c.next(), c.as_relative_path()) {
+            (Some(rpf), audio_file) if !audio_file.as_str().is_empty() => {
+                (rpf.as_str(), audio_file)
+            }
+            _ => return Err(Error::ExpectedArchive(m.as_str().to_string())),
+        };
+
+        let archive = match archives.entry(rpf) {
+            Entry::Vacant(e) => e.insert(Archive {
+                path: format!("x64/audio/sfx/{}.rpf", rpf),
+                create_if_not_exists: "True",
+                ty: String::from("RPF7"),
+                add: Vec::new(),
+            }),
+            Entry::Occupied(e) => e.into_mut(),
+        };
+
+        archive.add.push(Add {
+            source: format!("{}.awc", m.display()),
+            value: format!("{}.awc", audio_file.display()),
+        });
+    }
+
+    let mut content = Content::default();
+    content.archives.extend(archives.into_iter().map(|v| v.1));
+    return Ok(content.to_string());
+
+    #[derive(Debug)]
+    struct Add {
+        source: String,
+        value: String,
+    }
+
+    impl Add {
+        pub fn to_xml(&self, fmt: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+            let prefix = " ".repeat(depth);
+
+            writeln!(
+                fmt,
+                "{}<add source=\"{}\">{}</add>",
+                prefix, self.source, self.value
+            )?;
+
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct Archive {
+        path: String,
+        create_if_not_exists: &'static str,
+        ty: String,
+        add: Vec<Add>,
+    }
+
+    impl Archive {
+        pub fn to_xml(&self, fmt: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+            let prefix = " ".repeat(depth);
+
+            writeln!(
+                fmt,
+                "{}<archive path=\"{}\" createIfNotExist=\"{}\" type=\"{}\">",
+                prefix, self.path, self.create_if_not_exists, self.ty
+            )?;
+
+            for a in &self.add {
+                a.to_xml(fmt, depth + 2)?;
+            }
+
+            writeln!(fmt, "{}</archive>", prefix)?;
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct Content {
+        archives: Vec<Archive>,
+    }
+
+    impl Content {
+        pub fn to_xml(&self, fmt: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+            let prefix = " ".repeat(depth);
+
+            writeln!(fmt, "{}<content>", prefix)?;
+
+            for a in &self.archives {
+                a.to_xml(fmt, depth + 2)?;
+            }
+
+            writeln!(fmt, "{}</content>", prefix)?;
+            Ok(())
+        }
+    }
+
+    impl fmt::Display for Content {
+        fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.to_xml(fmt, 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::manifest;
+    use crate::Error;
+    use relative_path::RelativePath;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_manifest() -> Result<(), Error> {
+        let modified = ["sfx/ar2", "sfx/dlc/ar3", "other/ar4"]
+            .iter()
+            .map(|p| RelativePath::new(p).to_owned())
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            "<content>\n\
+             \x20 <archive path=\"x64/audio/sfx/other.rpf\" createIfNotExist=\"True\" type=\"RPF7\">\n\
+             \x20   <add source=\"other/ar4.awc\">ar4.awc</add>\n\
+             \x20 </archive>\n\
+             \x20 <archive path=\"x64/audio/sfx/sfx.rpf\" createIfNotExist=\"True\" type=\"RPF7\">\n\
+             \x20   <add source=\"sfx/ar2.awc\">ar2.awc</add>\n\
+             \x20   <add source=\"sfx/dlc/ar3.awc\">dlc/ar3.awc</add>\n\
+             \x20 </archive>\n\
+             </content>\n",
+            manifest(&modified)?
+        );
+
+        for path in &["ar2", ""] {
+            let modified = std::iter::once(RelativePath::new(path).to_owned()).collect();
+            let e = manifest(&modified).expect_err("expected error");
+            assert!(matches!(e, Error::ExpectedArchive(ref p) if p == path));
+        }
+
+        Ok(())
+    }
+}
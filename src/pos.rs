@@ -1,4 +1,5 @@
-use std::fmt;
+use crate::Error;
+use std::{fmt, str::FromStr};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Pos {
@@ -23,19 +24,43 @@ impl Pos {
         Some(samples)
     }
 
+    /// Construct the position of the given frame in a file with the given sample rate, rounded
+    /// down to the closest millisecond.
+    pub fn from_samples(samples: u32, sample_rate: u32) -> Pos {
+        let ms = u64::from(samples) * 1000 / u64::from(sample_rate.max(1));
+
+        Pos {
+            hours: (ms / 3_600_000) as u32,
+            minutes: (ms / 60_000 % 60) as u32,
+            seconds: (ms / 1000 % 60) as u32,
+            milliseconds: (ms % 1000) as u32,
+        }
+    }
+
     /// Deserialize stringa as a position.
+    ///
+    /// Both `.` and `,` are accepted as the separator between seconds and milliseconds, and the
+    /// milliseconds can be left out, like `21:42`.
     pub fn parse(s: &str) -> Option<Pos> {
+        let s = s.replace(',', ".");
         let mut main = s.split(':');
         let last = main.next_back()?;
         let mut last = last.split(".");
 
-        let seconds = match last.next()?.trim() {
+        let seconds = last.next()?.trim();
+
+        let milliseconds = match last.next() {
+            Some(fraction) => parse_fraction(fraction)?,
+            // NB: either the seconds or the milliseconds have to be present.
+            None if seconds.is_empty() => return None,
+            None => 0,
+        };
+
+        let seconds = match seconds {
             "" => 0,
             seconds => str::parse::<u32>(seconds).ok()?,
         };
 
-        let milliseconds = str::parse::<u32>(last.next()?).ok()?;
-
         let minutes = main
             .next_back()
             .and_then(|s| str::parse::<u32>(s).ok())
@@ -46,26 +71,52 @@ impl Pos {
             .and_then(|s| str::parse::<u32>(s).ok())
             .unwrap_or_default();
 
-        Some(Pos {
+        return Some(Pos {
             hours,
             minutes,
             seconds,
             milliseconds,
-        })
+        });
+
+        /// Parse the fractional part of a second positionally into milliseconds, so that `5` is
+        /// 500 milliseconds and `05` is 50. Digits beyond millisecond precision are truncated.
+        fn parse_fraction(s: &str) -> Option<u32> {
+            if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+
+            let mut milliseconds = 0;
+
+            for (i, c) in s.chars().chain(std::iter::repeat('0')).take(3).enumerate() {
+                milliseconds += c.to_digit(10)? * 10u32.pow(2 - i as u32);
+            }
+
+            Some(milliseconds)
+        }
+    }
+}
+
+impl FromStr for Pos {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pos::parse(s).ok_or_else(|| Error::ParsePos(s.to_string()))
     }
 }
 
 impl fmt::Display for Pos {
+    /// Leading fields which are zero are omitted, but every field after the first one written is
+    /// included, so that the position parses back the same.
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.hours > 0 {
             write!(fmt, "{:02}:", self.hours)?;
         }
 
-        if self.minutes > 0 {
-            write!(fmt, "{:02}:", self.hours)?;
+        if self.hours > 0 || self.minutes > 0 {
+            write!(fmt, "{:02}:", self.minutes)?;
         }
 
-        if self.seconds > 0 {
+        if self.hours > 0 || self.minutes > 0 || self.seconds > 0 {
             write!(fmt, "{:02}", self.seconds)?;
         }
 
@@ -84,9 +135,19 @@ impl<'de> serde::Deserialize<'de> for Pos {
     }
 }
 
+impl serde::Serialize for Pos {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Pos;
+    use crate::Error;
 
     #[test]
     pub fn test() {
@@ -130,4 +191,106 @@ mod tests {
             Pos::parse("12:21:42.123").expect("bad position")
         );
     }
+
+    #[test]
+    pub fn test_comma_separator() {
+        assert_eq!(
+            Pos {
+                hours: 0,
+                minutes: 0,
+                seconds: 42,
+                milliseconds: 123,
+            },
+            Pos::parse("42,123").expect("bad position")
+        );
+
+        assert_eq!(
+            Pos {
+                hours: 0,
+                minutes: 21,
+                seconds: 42,
+                milliseconds: 500,
+            },
+            Pos::parse("21:42,5").expect("bad position")
+        );
+
+        assert_eq!(
+            "42.123",
+            Pos::parse("42,123").expect("bad position").to_string()
+        );
+    }
+
+    #[test]
+    pub fn test_fraction() {
+        let pos = |s| Pos::parse(s).expect("bad position").milliseconds;
+
+        assert_eq!(500, pos("42.5"));
+        assert_eq!(50, pos("42.05"));
+        assert_eq!(123, pos("42.123"));
+        assert_eq!(123, pos("42.1234"));
+
+        for s in &[
+            "42.500",
+            "21:42.050",
+            "12:21:42.123",
+            "01:00.000",
+            "01:00:00.000",
+            "01:00:05.000",
+            "01:02:00.250",
+        ] {
+            assert_eq!(*s, Pos::parse(s).expect("bad position").to_string());
+        }
+
+        assert_eq!(
+            "42.500",
+            Pos::parse("42.5").expect("bad position").to_string()
+        );
+    }
+
+    #[test]
+    fn test_from_samples() {
+        assert_eq!(
+            Pos::parse("01:02:03.500").expect("bad position"),
+            Pos::from_samples(3_723_500 * 8, 8000)
+        );
+        assert_eq!(".999", Pos::from_samples(999, 1000).to_string());
+    }
+
+    #[test]
+    fn test_serialize() -> Result<(), Error> {
+        for s in &[".123", "42.500", "01:00:00.000"] {
+            let pos = Pos::parse(s).expect("bad position");
+            let value = serde_yaml::to_value(&pos)?;
+            assert_eq!(serde_yaml::Value::String(s.to_string()), value);
+            assert_eq!(pos, serde_yaml::from_value(value)?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            Pos::parse("42.5").expect("bad position"),
+            "42.5".parse().expect("valid position")
+        );
+        assert!(matches!(
+            "42.x".parse::<Pos>(),
+            Err(Error::ParsePos(ref s)) if s == "42.x"
+        ));
+    }
+
+    #[test]
+    fn test_without_milliseconds() {
+        let pos = |s| Pos::parse(s).expect("bad position");
+
+        assert_eq!(pos("21:42.000"), pos("21:42"));
+        assert_eq!(pos("00:05.000"), pos("00:05"));
+        assert_eq!(pos("05.000"), pos("5"));
+        assert_eq!(pos("01:02:03.000"), pos("01:02:03"));
+
+        assert_eq!(None, Pos::parse(""));
+        assert_eq!(None, Pos::parse("21:"));
+        assert_eq!(None, Pos::parse("21:42."));
+    }
 }
@@ -0,0 +1,269 @@
+//! Processing of decoded, interleaved samples.
+
+use crate::utils;
+
+/// Quantizes samples to 16 bits with triangular dither, which trades the distortion of plain
+/// truncation for a low and even noise floor.
+pub struct Dither {
+    state: u64,
+}
+
+impl Dither {
+    /// Construct a new dither, seeded with the given seed.
+    pub fn new(seed: u64) -> Self {
+        Dither { state: seed }
+    }
+
+    /// Quantize a single sample, by adding noise of up to one step in either direction and
+    /// rounding.
+    pub fn quantize(&mut self, sample: f64) -> i16 {
+        let a = self.uniform();
+        let b = self.uniform();
+        let sample = (sample + a - b).round();
+        sample.clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+    }
+
+    /// A uniformly distributed number in 0..1.
+    fn uniform(&mut self) -> f64 {
+        (utils::splitmix64(&mut self.state) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Downmix interleaved samples with the given number of channels into mono, by averaging the
+/// samples of each frame.
+pub fn downmix_mono(data: &[i16], channels: u16) -> Vec<i16> {
+    downmix_mono_with(data, channels, |sample| sample as i16)
+}
+
+/// Downmix like [downmix_mono], but quantize each averaged sample with `quantize`.
+pub fn downmix_mono_with(
+    data: &[i16],
+    channels: u16,
+    mut quantize: impl FnMut(f64) -> i16,
+) -> Vec<i16> {
+    let channels = usize::from(channels.max(1));
+
+    data.chunks(channels)
+        .map(|frame| {
+            let sum = frame.iter().map(|s| i32::from(*s)).sum::<i32>();
+            quantize(f64::from(sum) / frame.len() as f64)
+        })
+        .collect()
+}
+
+/// Extract a single channel from interleaved samples with the given number of channels.
+///
+/// Returns `None` if the channel doesn't exist.
+pub fn pick_channel(data: &[i16], channels: u16, channel: u16) -> Option<Vec<i16>> {
+    if channel >= channels {
+        return None;
+    }
+
+    let channels = usize::from(channels);
+
+    Some(
+        data.iter()
+            .skip(usize::from(channel))
+            .step_by(channels)
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Number of frames a recording with `frames` frames at sample rate `from` has after being
+/// resampled to `to`.
+pub fn resampled_frames(frames: u32, from: u32, to: u32) -> u32 {
+    (u64::from(frames) * u64::from(to) / u64::from(from.max(1))) as u32
+}
+
+/// Resample interleaved samples with the given number of channels from one sample rate to
+/// another, using linear interpolation.
+pub fn resample(data: &[i16], channels: u16, from: u32, to: u32) -> Vec<i16> {
+    resample_with(data, channels, from, to, |sample| sample.round() as i16)
+}
+
+/// Resample like [resample], but quantize each interpolated sample with `quantize`.
+pub fn resample_with(
+    data: &[i16],
+    channels: u16,
+    from: u32,
+    to: u32,
+    mut quantize: impl FnMut(f64) -> i16,
+) -> Vec<i16> {
+    let channels = usize::from(channels.max(1));
+
+    if from == to || data.is_empty() {
+        return data.to_vec();
+    }
+
+    let frames = data.len() / channels;
+    let out_frames = resampled_frames(frames as u32, from, to) as usize;
+    let step = f64::from(from) / f64::from(to);
+
+    let mut out = Vec::with_capacity(out_frames * channels);
+
+    for i in 0..out_frames {
+        let pos = i as f64 * step;
+        let a = usize::min(pos as usize, frames - 1);
+        let b = usize::min(a + 1, frames - 1);
+        let frac = pos - a as f64;
+
+        for c in 0..channels {
+            let a = f64::from(data[a * channels + c]);
+            let b = f64::from(data[b * channels + c]);
+            out.push(quantize(a + (b - a) * frac));
+        }
+    }
+
+    out
+}
+
+/// Find runs of at least `min_run` consecutive full-scale samples in any channel, which indicate
+/// that the recording has been clipped.
+///
+/// Returns the frame at which each run starts, in order.
+pub fn clipped_runs(data: &[i16], channels: u16, min_run: usize) -> Vec<usize> {
+    let channels = usize::from(channels.max(1));
+    let mut runs = Vec::new();
+
+    for channel in 0..channels {
+        let mut run = 0;
+
+        for (frame, s) in data.iter().skip(channel).step_by(channels).enumerate() {
+            if *s == i16::MAX || *s == i16::MIN {
+                run += 1;
+
+                if run == min_run {
+                    runs.push(frame + 1 - min_run);
+                }
+            } else {
+                run = 0;
+            }
+        }
+    }
+
+    runs.sort();
+    runs.dedup();
+    runs
+}
+
+/// Find the range of interleaved samples from the first to the last frame in which any channel
+/// is louder than `threshold`.
+///
+/// The range is empty if no frame is louder than the threshold.
+pub fn audible_range(data: &[i16], channels: u16, threshold: i16) -> std::ops::Range<usize> {
+    let channels = usize::from(channels.max(1));
+    let loud = |frame: &[i16]| {
+        frame
+            .iter()
+            .any(|s| s.unsigned_abs() > threshold.unsigned_abs())
+    };
+
+    let start = match data.chunks(channels).position(loud) {
+        Some(frame) => frame * channels,
+        None => return 0..0,
+    };
+
+    let end = data.len() - data.rchunks(channels).position(loud).unwrap_or_default() * channels;
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        audible_range, clipped_runs, downmix_mono, downmix_mono_with, pick_channel, resample,
+        Dither,
+    };
+    use crate::{generator::Tone, Generator};
+
+    #[test]
+    fn test_downmix_mono() {
+        let stereo = [100, 200, -100, -300, i16::MAX, i16::MAX, i16::MIN, i16::MAX];
+        let mono = downmix_mono(&stereo, 2);
+
+        assert_eq!(stereo.len() / 2, mono.len());
+        assert_eq!(vec![150, -200, i16::MAX, 0], mono);
+        assert_eq!(stereo.to_vec(), downmix_mono(&stereo, 1));
+    }
+
+    #[test]
+    fn test_pick_channel() {
+        let stereo = [100, 200, -100, -300, 1, 2];
+        assert_eq!(Some(vec![100, -100, 1]), pick_channel(&stereo, 2, 0));
+        assert_eq!(Some(vec![200, -300, 2]), pick_channel(&stereo, 2, 1));
+        assert_eq!(None, pick_channel(&stereo, 2, 2));
+        assert_eq!(Some(stereo.to_vec()), pick_channel(&stereo, 1, 0));
+    }
+
+    #[test]
+    fn test_resample() {
+        let tone = Tone::new().generate(0..44100, 44100);
+        let resampled = resample(&tone, 1, 44100, 22050);
+        assert_eq!(tone.len() / 2, resampled.len());
+
+        // every other sample lines up exactly with the source.
+        for (i, s) in resampled.iter().enumerate() {
+            assert_eq!(tone[i * 2], *s);
+        }
+
+        let stereo = [0, 100, 10, 200];
+        assert_eq!(
+            vec![0, 100, 5, 150, 10, 200, 10, 200],
+            resample(&stereo, 2, 1, 2)
+        );
+    }
+
+    #[test]
+    fn test_clipped_runs() {
+        let max = i16::MAX;
+        let min = i16::MIN;
+
+        // stereo, where the left channel clips from frame 1 and the right from frame 4.
+        let data = [0, 0, max, 0, max, 0, max, 0, 0, min, 0, min, 0, min, 0, 0];
+        assert_eq!(vec![1, 4], clipped_runs(&data, 2, 3));
+        assert_eq!(Vec::<usize>::new(), clipped_runs(&data, 2, 4));
+        // interleaved full-scale samples which span channels are not a run.
+        assert_eq!(Vec::<usize>::new(), clipped_runs(&[max, min, 0, 0], 2, 2));
+    }
+
+    #[test]
+    fn test_audible_range() {
+        let data = [0, 1, -2, 0, 0, 100, 3, 0, -50, 0, 0, 0];
+        assert_eq!(4..10, audible_range(&data, 2, 10));
+        assert_eq!(5..9, audible_range(&data, 1, 10));
+        assert_eq!(0..8, audible_range(&data[..8], 2, 0));
+        assert_eq!(0..0, audible_range(&data, 2, 100));
+        assert_eq!(0..0, audible_range(&[], 2, 0));
+    }
+
+    #[test]
+    fn test_dither() {
+        let mut dither = Dither::new(42);
+        let quantized = (0..10_000)
+            .map(|_| dither.quantize(100.25))
+            .collect::<Vec<_>>();
+
+        // the noise is at most one step in either direction.
+        assert!(quantized.iter().all(|s| (99..=101).contains(s)));
+        assert!(quantized.iter().any(|s| *s != 100));
+
+        let mean = quantized.iter().map(|s| f64::from(*s)).sum::<f64>() / quantized.len() as f64;
+        assert!((mean - 100.25).abs() < 0.05, "{}", mean);
+
+        // the same seed gives the same noise.
+        let mut again = Dither::new(42);
+        assert!(quantized.iter().all(|s| *s == again.quantize(100.25)));
+
+        // samples are clamped at full scale.
+        assert_eq!(
+            i16::MAX,
+            Dither::new(1).quantize(f64::from(i16::MAX) + 10f64)
+        );
+
+        let data = [3, 4, -3, -4, 1, 2];
+        assert_eq!(
+            downmix_mono(&data, 2),
+            downmix_mono_with(&data, 2, |s| s as i16)
+        );
+    }
+}
@@ -1,52 +1,290 @@
-use crate::Pos;
-use std::fmt;
+use crate::{Error, Pos, Tempo};
+use std::{fmt, ops, str::FromStr};
 
+/// A single endpoint of a range.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Range {
-    pub start: Option<Pos>,
-    pub end: Option<Pos>,
+pub enum Bound {
+    /// An absolute position in the file.
+    Pos(Pos),
+    /// The start of the file (`^` or `start`), optionally with an offset added to it (`^+1.0`).
+    Start(Option<Pos>),
+    /// The end of the file (`$` or `end`), optionally with an offset subtracted from it (`$-0.5`).
+    End(Option<Pos>),
+    /// The first audible sample in the file (`^^`), optionally with an offset added to it
+    /// (`^^+1.0`), which requires the decoded audio to resolve.
+    AudibleStart(Option<Pos>),
+    /// Right after the last audible sample in the file (`$$`), optionally with an offset
+    /// subtracted from it (`$$-0.5`), which requires the decoded audio to resolve.
+    AudibleEnd(Option<Pos>),
+    /// A bar and beat, both starting at 1 (`b3.2`), which requires a tempo to resolve.
+    Beat(u32, u32),
+    /// An exact frame in the file (`s44100`), which is used as-is without any time conversion.
+    ///
+    /// This is the index of a sample in a single channel, so for interleaved stereo audio the
+    /// offset is twice this.
+    Sample(u32),
 }
 
-impl Range {
-    /// Deserialize stringa as a position.
-    pub fn parse(s: &str) -> Option<Range> {
-        let mut main = s.split('-');
-        let start = pos(main.next(), "^")?;
-        let end = pos(main.next(), "$")?;
+impl Bound {
+    /// Parse a bound from the start of the given string, returning the remaining string.
+    fn parse_prefix(s: &str) -> Option<(Bound, &str)> {
+        if let Some(rest) = s.strip_prefix("^^") {
+            let (offset, rest) = start_offset(rest)?;
+            return Some((Bound::AudibleStart(offset), rest));
+        }
+
+        if let Some(rest) = s.strip_prefix('^').or_else(|| s.strip_prefix("start")) {
+            let (offset, rest) = start_offset(rest)?;
+            return Some((Bound::Start(offset), rest));
+        }
 
-        return Some(Range { start, end });
+        if let Some(rest) = s.strip_prefix("$$") {
+            let (offset, rest) = end_offset(rest)?;
+            return Some((Bound::AudibleEnd(offset), rest));
+        }
+
+        if let Some(rest) = s.strip_prefix('$').or_else(|| s.strip_prefix("end")) {
+            let (offset, rest) = end_offset(rest)?;
+            return Some((Bound::End(offset), rest));
+        }
+
+        if let Some(rest) = s.strip_prefix('b') {
+            let (bar, rest) = number_prefix(rest)?;
 
-        fn pos(pos: Option<&str>, term: &str) -> Option<Option<Pos>> {
-            let pos = match pos {
-                Some(pos) => pos,
-                None => return None,
+            let (beat, rest) = match rest.strip_prefix('.') {
+                Some(rest) => number_prefix(rest)?,
+                None => (1, rest),
             };
 
-            if pos == term {
-                return Some(None);
+            if bar == 0 || beat == 0 {
+                return None;
+            }
+
+            return Some((Bound::Beat(bar, beat), rest));
+        }
+
+        if let Some(rest) = s.strip_prefix('s') {
+            let (frame, rest) = number_prefix(rest)?;
+            return Some((Bound::Sample(frame), rest));
+        }
+
+        let (pos, rest) = pos_prefix(s)?;
+        return Some((Bound::Pos(pos), rest));
+
+        fn start_offset(s: &str) -> Option<(Option<Pos>, &str)> {
+            match s.strip_prefix('+') {
+                Some(rest) => {
+                    let (offset, rest) = pos_prefix(rest)?;
+                    Some((Some(offset), rest))
+                }
+                None => Some((None, s)),
+            }
+        }
+
+        fn end_offset(s: &str) -> Option<(Option<Pos>, &str)> {
+            // NB: a `-` is only an offset if it's followed by a position, otherwise it separates
+            // the two bounds of the range.
+            match s.strip_prefix('-') {
+                Some(offset) if offset.starts_with(is_pos_char) => {
+                    let (offset, rest) = pos_prefix(offset)?;
+                    Some((Some(offset), rest))
+                }
+                _ => Some((None, s)),
             }
+        }
+
+        fn number_prefix(s: &str) -> Option<(u32, &str)> {
+            let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            let (number, rest) = s.split_at(end);
+            Some((str::parse::<u32>(number).ok()?, rest))
+        }
 
-            let pos = Pos::parse(pos)?;
-            Some(Some(pos))
+        fn is_pos_char(c: char) -> bool {
+            c.is_ascii_digit() || c == ':' || c == '.' || c == ','
         }
+
+        fn pos_prefix(s: &str) -> Option<(Pos, &str)> {
+            let end = s.find(|c| !is_pos_char(c)).unwrap_or(s.len());
+            let (pos, rest) = s.split_at(end);
+            Some((Pos::parse(pos)?, rest))
+        }
+    }
+
+    /// Test if the bound is relative to the start or end of the file (`^` or `$`), or of the
+    /// audible part of it (`^^` or `$$`), rather than an explicit position.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            *self,
+            Bound::Start(..) | Bound::End(..) | Bound::AudibleStart(..) | Bound::AudibleEnd(..)
+        )
+    }
+
+    /// Test if the bound requires a tempo to resolve.
+    pub fn uses_tempo(&self) -> bool {
+        matches!(*self, Bound::Beat(..))
+    }
+
+    /// Test if the bound requires the decoded audio to resolve.
+    pub fn uses_audio(&self) -> bool {
+        matches!(*self, Bound::AudibleStart(..) | Bound::AudibleEnd(..))
+    }
+
+    /// Resolve the bound into an offset in interleaved samples, in a file with the given sample
+    /// rate, number of channels, and total length in interleaved samples.
+    ///
+    /// `audible` is the range of interleaved samples which are audible, which is only needed for
+    /// `^^` and `$$`.
+    ///
+    /// The offset is clamped to the length of the file. Returns `None` on overflow, or if the
+    /// bound requires a tempo or the audible range and none is provided.
+    pub fn as_samples(
+        &self,
+        sample_rate: u32,
+        channels: u16,
+        len: u32,
+        tempo: Option<&Tempo>,
+        audible: Option<&ops::Range<u32>>,
+    ) -> Option<u32> {
+        let samples = |pos: &Pos| -> Option<u32> {
+            pos.as_samples(sample_rate)?
+                .checked_mul(u32::from(channels))
+        };
+
+        let offset = match *self {
+            Bound::Pos(ref pos) => samples(pos)?,
+            Bound::Start(None) => 0,
+            Bound::Start(Some(ref offset)) => samples(offset)?,
+            Bound::End(None) => len,
+            Bound::End(Some(ref offset)) => len.saturating_sub(samples(offset)?),
+            Bound::AudibleStart(None) => audible?.start,
+            Bound::AudibleStart(Some(ref offset)) => {
+                audible?.start.saturating_add(samples(offset)?)
+            }
+            Bound::AudibleEnd(None) => audible?.end,
+            Bound::AudibleEnd(Some(ref offset)) => audible?.end.saturating_sub(samples(offset)?),
+            Bound::Beat(bar, beat) => tempo?
+                .as_samples(bar, beat, sample_rate)?
+                .checked_mul(u32::from(channels))?,
+            Bound::Sample(frame) => frame.checked_mul(u32::from(channels))?,
+        };
+
+        Some(u32::min(offset, len))
     }
 }
 
-impl fmt::Display for Range {
+impl fmt::Display for Bound {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.start {
-            Some(ref start) => start.fmt(fmt)?,
-            None => "^".fmt(fmt)?,
+        match *self {
+            Bound::Pos(ref pos) => pos.fmt(fmt),
+            Bound::Start(None) => "^".fmt(fmt),
+            Bound::Start(Some(ref offset)) => write!(fmt, "^+{}", offset),
+            Bound::End(None) => "$".fmt(fmt),
+            Bound::End(Some(ref offset)) => write!(fmt, "$-{}", offset),
+            Bound::AudibleStart(None) => "^^".fmt(fmt),
+            Bound::AudibleStart(Some(ref offset)) => write!(fmt, "^^+{}", offset),
+            Bound::AudibleEnd(None) => "$$".fmt(fmt),
+            Bound::AudibleEnd(Some(ref offset)) => write!(fmt, "$$-{}", offset),
+            Bound::Beat(bar, beat) => write!(fmt, "b{}.{}", bar, beat),
+            Bound::Sample(frame) => write!(fmt, "s{}", frame),
         }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Range {
+    pub start: Bound,
+    pub end: Bound,
+}
 
-        write!(fmt, "-")?;
+impl Range {
+    /// Deserialize stringa as a position.
+    ///
+    /// Whitespace is allowed around the range and around each of its bounds, like `1.0 - 2.0`.
+    pub fn parse(s: &str) -> Option<Range> {
+        let (start, rest) = Bound::parse_prefix(s.trim())?;
+        let rest = rest.trim_start().strip_prefix('-')?;
+        let (end, rest) = Bound::parse_prefix(rest.trim_start())?;
 
-        match self.end {
-            Some(ref end) => end.fmt(fmt)?,
-            None => "^".fmt(fmt)?,
+        if !rest.is_empty() {
+            return None;
         }
 
-        Ok(())
+        Some(Range { start, end })
+    }
+
+    /// Resolve the range into interleaved samples, in a file with the given spec and duration in
+    /// frames.
+    ///
+    /// `audible` is the range of interleaved samples which are audible, which is only needed for
+    /// `^^` and `$$`.
+    ///
+    /// Both bounds are clamped to the length of the file, so the range might be empty. Errors if a
+    /// bound overflows, if a bound requires a tempo or the audible range and none is provided, or
+    /// if the start comes after the end.
+    pub fn resolve(
+        &self,
+        spec: &hound::WavSpec,
+        duration: u32,
+        tempo: Option<&Tempo>,
+        audible: Option<&ops::Range<u32>>,
+    ) -> Result<ops::Range<usize>, Error> {
+        let len = match duration.checked_mul(u32::from(spec.channels)) {
+            Some(len) => len,
+            None => {
+                return Err(Error::DurationOverflow {
+                    duration,
+                    channels: spec.channels,
+                })
+            }
+        };
+
+        let start = resolve(&self.start, spec, len, tempo, audible)? as usize;
+        let end = resolve(&self.end, spec, len, tempo, audible)? as usize;
+
+        if start > end {
+            return Err(Error::StartAfterEnd { start, end });
+        }
+
+        return Ok(start..end);
+
+        fn resolve(
+            bound: &Bound,
+            spec: &hound::WavSpec,
+            len: u32,
+            tempo: Option<&Tempo>,
+            audible: Option<&ops::Range<u32>>,
+        ) -> Result<u32, Error> {
+            if bound.uses_tempo() && tempo.is_none() {
+                return Err(Error::MissingTempo(bound.clone()));
+            }
+
+            if bound.uses_audio() && audible.is_none() {
+                return Err(Error::MissingAudio(bound.clone()));
+            }
+
+            match bound.as_samples(spec.sample_rate, spec.channels, len, tempo, audible) {
+                Some(samples) => Ok(samples),
+                None => Err(Error::OutOfRange {
+                    bound: bound.clone(),
+                    sample_rate: spec.sample_rate,
+                    channels: spec.channels,
+                }),
+            }
+        }
+    }
+}
+
+impl FromStr for Range {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Range::parse(s).ok_or_else(|| Error::ParseRange(s.to_string()))
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}-{}", self.start, self.end)
     }
 }
 
@@ -68,3 +306,225 @@ impl serde::Serialize for Range {
         serializer.collect_str(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Bound, Range};
+    use crate::{Error, Pos, Tempo};
+
+    fn pos(s: &str) -> Pos {
+        Pos::parse(s).expect("bad position")
+    }
+
+    #[test]
+    fn test_parse() {
+        let range = Range::parse("^-$").expect("bad range");
+        assert_eq!(Bound::Start(None), range.start);
+        assert_eq!(Bound::End(None), range.end);
+
+        let range = Range::parse("01.000-02.500").expect("bad range");
+        assert_eq!(Bound::Pos(pos("01.000")), range.start);
+        assert_eq!(Bound::Pos(pos("02.500")), range.end);
+
+        let range = Range::parse("^+1.0-$-0.500").expect("bad range");
+        assert_eq!(Bound::Start(Some(pos("1.0"))), range.start);
+        assert_eq!(Bound::End(Some(pos("0.500"))), range.end);
+
+        let range = Range::parse("$-1.0-$").expect("bad range");
+        assert_eq!(Bound::End(Some(pos("1.0"))), range.start);
+        assert_eq!(Bound::End(None), range.end);
+
+        assert_eq!(None, Range::parse("1.0-2.0-3.0"));
+        assert_eq!(None, Range::parse("1.0 2.0"));
+
+        for (spaced, plain) in &[
+            (" 00:01.0 - 00:02.0 ", "00:01.0-00:02.0"),
+            ("^ - $", "^-$"),
+            ("\t$-0.5 -$ ", "$-0.5-$"),
+            ("b2 - b3.2", "b2-b3.2"),
+        ] {
+            assert_eq!(Range::parse(plain), Range::parse(spaced));
+            assert!(Range::parse(spaced).is_some(), "{:?}", spaced);
+        }
+
+        assert_eq!(None, Range::parse("^+-$"));
+        assert_eq!(None, Range::parse("starts-end"));
+
+        for (alias, canonical) in &[
+            ("start-00:02.0", "^-00:02.0"),
+            ("00:01.0-end", "00:01.0-$"),
+            ("start+1.0-end-0.5", "^+1.0-$-0.5"),
+            ("end-1.0-end", "$-1.0-$"),
+            ("start - end", "^-$"),
+        ] {
+            let range = Range::parse(alias).expect("bad range");
+            assert_eq!(Range::parse(canonical), Some(range.clone()));
+            assert_eq!(
+                Range::parse(canonical).expect("bad range").to_string(),
+                range.to_string()
+            );
+        }
+
+        let range = Range::parse("b2-b3.2").expect("bad range");
+        assert_eq!(Bound::Beat(2, 1), range.start);
+        assert_eq!(Bound::Beat(3, 2), range.end);
+        assert_eq!(None, Range::parse("b0.1-b1"));
+        assert_eq!(None, Range::parse("b1.-b2"));
+
+        let range = Range::parse("s0-s44100").expect("bad range");
+        assert_eq!(Bound::Sample(0), range.start);
+        assert_eq!(Bound::Sample(44100), range.end);
+        assert_eq!(None, Range::parse("s-s1"));
+        assert_eq!(None, Range::parse("s1.5-s2"));
+
+        for s in &[
+            "^-$",
+            "01.000-02.500",
+            "^+01.000-$-.500",
+            "$-01.000-$",
+            "b1.1-b2.3",
+            "s100-s200",
+            "^^-$$",
+            "^^+01.000-$$-.500",
+            "^-$$",
+        ] {
+            assert_eq!(*s, Range::parse(s).expect("bad range").to_string());
+        }
+
+        let range = Range::parse("^^+1.0-$$-0.5").expect("bad range");
+        assert_eq!(Bound::AudibleStart(Some(pos("1.0"))), range.start);
+        assert_eq!(Bound::AudibleEnd(Some(pos("0.5"))), range.end);
+        assert!(range.start.uses_audio() && range.start.is_open());
+        assert!(!Bound::Start(None).uses_audio());
+    }
+
+    #[test]
+    fn test_serialize() -> Result<(), Error> {
+        for s in &[
+            "^-$",
+            "^+01.000-$-.500",
+            "01:00:00.000-01:00:01.000",
+            "b1.1-b2.3",
+            "s100-s200",
+        ] {
+            let range = Range::parse(s).expect("bad range");
+            let value = serde_yaml::to_value(&range)?;
+            assert_eq!(serde_yaml::Value::String(s.to_string()), value);
+            assert_eq!(range, serde_yaml::from_value(value)?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_samples() {
+        // two seconds of stereo audio at 1000Hz.
+        let len = 4000;
+        let range = Range::parse("^+0.500-$-0.250").expect("bad range");
+        assert_eq!(Some(1000), range.start.as_samples(1000, 2, len, None, None));
+        assert_eq!(Some(3500), range.end.as_samples(1000, 2, len, None, None));
+
+        let range = Range::parse("^-$").expect("bad range");
+        assert_eq!(Some(0), range.start.as_samples(1000, 2, len, None, None));
+        assert_eq!(Some(4000), range.end.as_samples(1000, 2, len, None, None));
+
+        let range = Range::parse("01.000-10.000").expect("bad range");
+        assert_eq!(Some(2000), range.start.as_samples(1000, 2, len, None, None));
+        assert_eq!(Some(4000), range.end.as_samples(1000, 2, len, None, None));
+
+        let range = Range::parse("$-10.000-$").expect("bad range");
+        assert_eq!(Some(0), range.start.as_samples(1000, 2, len, None, None));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        // two seconds of stereo audio.
+        let resolve = |s: &str| {
+            Range::parse(s)
+                .expect("bad range")
+                .resolve(&spec, 2000, None, None)
+        };
+
+        assert_eq!(0..4000, resolve("^-$").expect("valid range"));
+        assert_eq!(
+            1000..3000,
+            resolve("00:00.500-00:01.500").expect("valid range")
+        );
+        assert_eq!(
+            3000..4000,
+            resolve("00:01.500-00:10.000").expect("valid range")
+        );
+        assert_eq!(4000..4000, resolve("00:05.000-$").expect("valid range"));
+
+        assert!(matches!(
+            resolve("00:01.500-00:00.500"),
+            Err(Error::StartAfterEnd {
+                start: 3000,
+                end: 1000
+            })
+        ));
+        assert!(matches!(
+            resolve("b1-b2"),
+            Err(Error::MissingTempo(Bound::Beat(1, 1)))
+        ));
+
+        // the audible part of the file is the middle second.
+        let audible = 1000..3000;
+        let resolve_audible = |s: &str| {
+            Range::parse(s)
+                .expect("bad range")
+                .resolve(&spec, 2000, None, Some(&audible))
+        };
+
+        assert_eq!(1000..3000, resolve_audible("^^-$$").expect("valid range"));
+        assert_eq!(
+            1200..2800,
+            resolve_audible("^^+00.100-$$-00.100").expect("valid range")
+        );
+        assert_eq!(0..1000, resolve_audible("^-^^").expect("valid range"));
+        assert_eq!(
+            4000..4000,
+            resolve_audible("^^+01.500-$").expect("valid range")
+        );
+        assert!(matches!(
+            resolve("^^-$$"),
+            Err(Error::MissingAudio(Bound::AudibleStart(None)))
+        ));
+
+        let tempo = Tempo::new(120f32);
+        let range = Range::parse("b1-b2").expect("bad range");
+        assert_eq!(
+            0..4000,
+            range
+                .resolve(&spec, 2000, Some(&tempo), None)
+                .expect("valid range")
+        );
+    }
+
+    #[test]
+    fn test_as_samples_beat() {
+        // ten seconds of stereo audio at 1000Hz.
+        let len = 20000;
+        let tempo = Tempo::new(120f32);
+        let range = Range::parse("b2-b2.3").expect("bad range");
+        assert_eq!(
+            Some(4000),
+            range.start.as_samples(1000, 2, len, Some(&tempo), None)
+        );
+        assert_eq!(
+            Some(6000),
+            range.end.as_samples(1000, 2, len, Some(&tempo), None)
+        );
+        assert_eq!(None, range.start.as_samples(1000, 2, len, None, None));
+        assert!(range.start.uses_tempo());
+        assert!(!range.start.is_open());
+        assert!(Bound::End(None).is_open());
+    }
+}
@@ -1,15 +1,87 @@
 use crate::Range;
-use std::fmt;
+use std::{cmp, fmt};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Replace {
     #[serde(rename = "kind")]
     pub word: String,
     pub range: Range,
+    /// Frequency of the tone for this replacement, if the generator is a tone.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f32>,
+    /// Amplitude from 0..1 of the tone for this replacement, if the generator is a tone.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amplitude: Option<f32>,
+    /// Milliseconds to pad the range with on each side, overriding `--pad`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pad: Option<u32>,
+    /// Which occurrence of the word in the transcript this is, starting at 1, like the `2` in
+    /// `[word#2]`. This is only used to document the replacement.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occurrence: Option<u32>,
+}
+
+impl Replace {
+    /// Construct a new replacement of the given word.
+    pub fn new(word: String, range: Range) -> Self {
+        Replace {
+            word,
+            range,
+            frequency: None,
+            amplitude: None,
+            pad: None,
+            occurrence: None,
+        }
+    }
 }
 
 impl fmt::Display for Replace {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(fmt, "[{}]{{{}}}", self.word, self.range)
+        write!(fmt, "[{}", self.word)?;
+
+        if let Some(occurrence) = self.occurrence {
+            write!(fmt, "#{}", occurrence)?;
+        }
+
+        write!(fmt, "]{{{}}}", self.range)
+    }
+}
+
+impl PartialEq for Replace {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Replace {}
+
+impl PartialOrd for Replace {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Replace {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        return self
+            .word
+            .cmp(&other.word)
+            .then_with(|| self.range.cmp(&other.range))
+            .then_with(|| cmp_float(self.frequency, other.frequency))
+            .then_with(|| cmp_float(self.amplitude, other.amplitude))
+            .then_with(|| self.pad.cmp(&other.pad))
+            .then_with(|| self.occurrence.cmp(&other.occurrence));
+
+        /// NB: floats are compared by their total order, so that replacements can be sorted.
+        fn cmp_float(a: Option<f32>, b: Option<f32>) -> cmp::Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            }
+        }
     }
 }
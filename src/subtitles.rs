@@ -0,0 +1,208 @@
+//! Subtitles in the SRT or WebVTT formats.
+
+use crate::{Bound, Error, Pos, Range, Replace, Transcript, Wordlist};
+use std::{fs, path::Path};
+
+/// Extensions of subtitle files which are looked for next to audio files.
+pub const EXTENSIONS: &[&str] = &["srt", "vtt"];
+
+/// A single cue in a subtitle file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    /// When the cue is shown.
+    pub range: Range,
+    /// The text of the cue, which might span multiple lines.
+    pub text: String,
+}
+
+/// Parsed subtitles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subtitles {
+    pub cues: Vec<Cue>,
+}
+
+impl Subtitles {
+    /// Parse subtitles in either the SRT or the WebVTT format.
+    ///
+    /// Only the timing and text of each cue is used, so cue numbers, identifiers, and settings are
+    /// ignored, as are blocks without a timing like the `WEBVTT` header and `NOTE` comments.
+    pub fn parse(text: &str) -> Result<Subtitles, Error> {
+        let text = text.trim_start_matches('\u{feff}').replace('\r', "");
+        let mut cues = Vec::new();
+
+        for block in text.split("\n\n") {
+            let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+
+            let timing = match lines.next() {
+                Some(timing) => timing,
+                None => continue,
+            };
+
+            let range = match parse_timing(timing) {
+                Some(range) => range,
+                None => return Err(Error::ParseCue(timing.to_string())),
+            };
+
+            cues.push(Cue {
+                range,
+                text: lines.collect::<Vec<_>>().join("\n"),
+            });
+        }
+
+        return Ok(Subtitles { cues });
+
+        /// Parse a timing line, like `00:00:01,000 --> 00:00:02,500`.
+        fn parse_timing(line: &str) -> Option<Range> {
+            let mut it = line.splitn(2, "-->");
+            let start = it.next()?.trim();
+            // NB: WebVTT cue settings might follow the end position.
+            let end = it.next()?.split_whitespace().next()?;
+
+            Some(Range {
+                start: Bound::Pos(Pos::parse(start)?),
+                end: Bound::Pos(Pos::parse(end)?),
+            })
+        }
+    }
+
+    /// Open the subtitles in the given file.
+    pub fn open(path: &Path) -> Result<Subtitles, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Construct a replacement for every cue which has a marked word, like `[word]`, or a word in
+    /// the given wordlist.
+    ///
+    /// Subtitles aren't timed per word, so the whole cue is censored.
+    pub fn replaces(&self, wordlist: Option<&Wordlist>) -> Result<Vec<Replace>, Error> {
+        let mut replaces = Vec::new();
+
+        for (i, cue) in self.cues.iter().enumerate() {
+            let transcript = Transcript::parse(&cue.text).map_err(|e| Error::Cue {
+                index: i + 1,
+                range: cue.range.clone(),
+                error: Box::new(e),
+            })?;
+
+            let mut words = transcript.missing.clone();
+            words.extend(transcript.replace.iter().map(|r| r.word.clone()));
+
+            if let Some(wordlist) = wordlist {
+                words.extend(
+                    transcript
+                        .plain_text()
+                        .split_whitespace()
+                        .filter(|w| wordlist.contains(w))
+                        .map(String::from),
+                );
+            }
+
+            if !words.is_empty() {
+                replaces.push(Replace::new(words.join(" "), cue.range.clone()));
+            }
+        }
+
+        Ok(replaces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Subtitles;
+    use crate::{Error, Range, Wordlist};
+
+    const SRT: &str = "1\r\n00:00:01,000 --> 00:00:02,500\r\nWhat the [heck]\r\n\r\n\
+                       2\r\n00:00:03,000 --> 00:00:04,000\r\nNothing to see\r\nhere\r\n\r\n\
+                       3\r\n01:00:05,250 --> 01:00:06,000\r\nOh, darn!\r\n";
+
+    #[test]
+    fn test_parse_srt() -> Result<(), Error> {
+        let subtitles = Subtitles::parse(SRT)?;
+
+        let ranges = subtitles
+            .cues
+            .iter()
+            .map(|c| c.range.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                Range::parse("00:00:01.000-00:00:02.500").expect("valid range"),
+                Range::parse("00:00:03.000-00:00:04.000").expect("valid range"),
+                Range::parse("01:00:05.250-01:00:06.000").expect("valid range"),
+            ],
+            ranges
+        );
+        assert_eq!("Nothing to see\nhere", subtitles.cues[1].text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_vtt() -> Result<(), Error> {
+        let subtitles = Subtitles::parse(
+            "WEBVTT\n\nNOTE a comment\n\nintro\n00:01.000 --> 00:02.500 align:start\n[heck]\n",
+        )?;
+
+        assert_eq!(1, subtitles.cues.len());
+        assert_eq!(
+            Range::parse("00:01.000-00:02.500").expect("valid range"),
+            subtitles.cues[0].range
+        );
+        assert_eq!("[heck]", subtitles.cues[0].text);
+
+        assert!(Subtitles::parse("1\n00:01.000 --> soon\nhello\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replaces() -> Result<(), Error> {
+        let subtitles = Subtitles::parse(SRT)?;
+
+        let replaces = subtitles.replaces(None)?;
+        assert_eq!(1, replaces.len());
+        assert_eq!("heck", replaces[0].word);
+        assert_eq!(subtitles.cues[0].range, replaces[0].range);
+
+        let wordlist = Wordlist::parse("darn\n");
+        let replaces = subtitles.replaces(Some(&wordlist))?;
+
+        assert_eq!(
+            vec![
+                (String::from("heck"), subtitles.cues[0].range.clone()),
+                (String::from("darn!"), subtitles.cues[2].range.clone()),
+            ],
+            replaces
+                .into_iter()
+                .map(|r| (r.word, r.range))
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let e = Subtitles::parse("1\n00:01.000 --> soon\nhello\n").expect_err("bad timing");
+        assert!(matches!(e, Error::ParseCue(ref timing) if timing == "00:01.000 --> soon"));
+
+        let subtitles =
+            Subtitles::parse("1\n00:01.000 --> 00:02.000\nok\n\n2\n00:03.000 --> 00:04.000\n[a\n")
+                .expect("valid subtitles");
+        let e = subtitles.replaces(None).expect_err("unclosed marker");
+
+        match e {
+            Error::Cue {
+                index,
+                range,
+                error,
+            } => {
+                assert_eq!(2, index);
+                assert_eq!(subtitles.cues[1].range, range);
+                assert!(matches!(*error, Error::Unclosed { open: '[', .. }));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let e = Subtitles::open(std::path::Path::new("does-not-exist.srt")).expect_err("missing");
+        assert!(matches!(e, Error::Io(..)));
+    }
+}
@@ -0,0 +1,370 @@
+//! Tasks which produce a single output file each.
+
+use crate::{Generator, HeaderCache, Replace};
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Size in bytes of the header of a plain WAV file.
+const WAV_HEADER_SIZE: u64 = 44;
+
+/// A single task that can be executed.
+pub enum Task<'a> {
+    /// Copy a single file.
+    Copy(PathBuf, PathBuf),
+    /// Regular processing with replacements, optionally with the generator to use instead of
+    /// the one in the context.
+    Process(
+        PathBuf,
+        PathBuf,
+        Vec<&'a Replace>,
+        Option<&'a dyn Generator>,
+    ),
+    // Silent processing.
+    Silence(PathBuf, PathBuf),
+}
+
+impl<'a> Task<'a> {
+    /// The source file of the task.
+    pub fn source(&self) -> &Path {
+        match *self {
+            Task::Copy(ref path, ..) => path,
+            Task::Process(ref path, ..) => path,
+            Task::Silence(ref path, ..) => path,
+        }
+    }
+
+    /// The destination file of the task.
+    pub fn dest(&self) -> &Path {
+        match *self {
+            Task::Copy(_, ref dest) => dest,
+            Task::Process(_, ref dest, ..) => dest,
+            Task::Silence(_, ref dest) => dest,
+        }
+    }
+
+    /// The weight of the task in the progress bar, which is the size of its source in bytes.
+    ///
+    /// Empty or unreadable files still weigh one, so that every task moves the bar.
+    pub fn weight(&self) -> u64 {
+        std::fs::metadata(self.source())
+            .map(|m| m.len())
+            .unwrap_or_default()
+            .max(1)
+    }
+
+    /// The number of bytes the task holds when decoding its source, or zero if it doesn't decode
+    /// any audio.
+    pub fn decoded_size(&self, headers: &HeaderCache) -> u64 {
+        match *self {
+            Task::Process(ref path, ..) => headers
+                .get(path)
+                .map(|h| h.decoded_size())
+                .unwrap_or_default(),
+            _ => 0,
+        }
+    }
+
+    /// The kind of the task, as shown in the estimate of --dry-run.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Task::Copy(..) => "copy",
+            Task::Process(..) => "process",
+            Task::Silence(..) => "silence",
+        }
+    }
+
+    /// Estimate the size in bytes of the output of the task.
+    ///
+    /// Copied and processed files are as large as their source, and the size of silenced files is
+    /// computed from the header of their source. Options which change the format of the output,
+    /// like `--downmix-mono`, are not taken into account.
+    pub fn estimated_size(&self, headers: &HeaderCache) -> u64 {
+        let size = std::fs::metadata(self.source())
+            .map(|m| m.len())
+            .unwrap_or_default();
+
+        match *self {
+            Task::Silence(ref path, ..) => match headers.get(path) {
+                Ok(h) => {
+                    let bytes = u64::from(h.spec.bits_per_sample + 7) / 8;
+                    WAV_HEADER_SIZE + u64::from(h.len) * bytes
+                }
+                // NB: files which aren't WAV are copied instead.
+                Err(..) => size,
+            },
+            _ => size,
+        }
+    }
+}
+
+impl<'a> fmt::Display for Task<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Task::Copy(ref path, ref dest) => {
+                write!(fmt, "copy {} -> {}", path.display(), dest.display())?;
+            }
+            Task::Process(ref path, ref dest, ..) => {
+                write!(fmt, "process {} -> {}", path.display(), dest.display())?;
+            }
+            Task::Silence(ref path, ref dest) => {
+                write!(fmt, "silence {} -> {}", path.display(), dest.display())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sort tasks by destination, and then by source, so that they are reported and scheduled in the
+/// same order on every run.
+pub fn sort(tasks: &mut [Task<'_>]) {
+    tasks.sort_by(|a, b| (a.dest(), a.source()).cmp(&(b.dest(), b.source())));
+}
+
+/// Keep only the first `limit` tasks, like `--limit` does after the tasks are sorted.
+///
+/// Returns the number of tasks there were, if any were removed.
+pub fn limit(tasks: &mut Vec<Task<'_>>, limit: usize) -> Option<usize> {
+    let len = tasks.len();
+
+    if limit >= len {
+        return None;
+    }
+
+    tasks.truncate(limit);
+    Some(len)
+}
+
+/// Add tasks to copy the companions of every audio file, like `A.oac` for `A.wav`, next to its
+/// destination.
+///
+/// Only tasks whose source is one of the given audio files have companions, so that the files are
+/// recognized by the extension configured for their directory. Each companion is only copied once,
+/// even if its audio file is part of several tasks.
+pub fn add_companions(tasks: &mut Vec<Task<'_>>, companions: &[String], audio: &HashSet<PathBuf>) {
+    if companions.is_empty() {
+        return;
+    }
+
+    let mut seen = HashSet::new();
+    let mut added = Vec::new();
+
+    for task in tasks.iter() {
+        if !audio.contains(task.source()) {
+            continue;
+        }
+
+        for ext in companions {
+            let companion = task.source().with_extension(ext);
+
+            if companion.is_file() && seen.insert(companion.clone()) {
+                added.push(Task::Copy(companion, task.dest().with_extension(ext)));
+            }
+        }
+    }
+
+    tasks.extend(added);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_companions, limit, sort, Task};
+    use crate::{Error, HeaderCache};
+    use std::{
+        collections::HashSet,
+        path::{Path, PathBuf},
+    };
+
+    fn temp_dir(name: &str) -> Result<PathBuf, Error> {
+        let dir = std::env::temp_dir().join(format!(
+            "batchcensor-test-task-{}-{}",
+            name,
+            std::process::id()
+        ));
+
+        if dir.is_dir() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn write_wav(path: &Path, samples: &[i16]) -> Result<(), Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut writer = hound::WavWriter::create(path, spec)?;
+
+        for s in samples {
+            writer.write_sample(*s)?;
+        }
+
+        writer.finalize()?;
+        Ok(())
+    }
+
+    fn order<'a>(tasks: &'a [Task<'_>]) -> Vec<(&'a str, &'a str)> {
+        tasks
+            .iter()
+            .map(|t| (t.source().to_str().unwrap(), t.dest().to_str().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut tasks = vec![
+            Task::Silence("in/c.wav".into(), "out/c.wav".into()),
+            Task::Copy("in/b.wav".into(), "out/a.wav".into()),
+            Task::Process("in/b.wav".into(), "out/b.wav".into(), vec![], None),
+            Task::Copy("in/a.wav".into(), "out/a.wav".into()),
+        ];
+
+        sort(&mut tasks);
+
+        assert_eq!(
+            vec![
+                ("in/a.wav", "out/a.wav"),
+                ("in/b.wav", "out/a.wav"),
+                ("in/b.wav", "out/b.wav"),
+                ("in/c.wav", "out/c.wav"),
+            ],
+            order(&tasks)
+        );
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut tasks = vec![
+            Task::Copy("in/c.wav".into(), "out/a.wav".into()),
+            Task::Copy("in/b.wav".into(), "out/b.wav".into()),
+            Task::Copy("in/a.wav".into(), "out/c.wav".into()),
+        ];
+
+        sort(&mut tasks);
+
+        // a limit at or above the number of tasks keeps all of them.
+        assert_eq!(None, limit(&mut tasks, 10));
+        assert_eq!(None, limit(&mut tasks, 3));
+        assert_eq!(3, tasks.len());
+
+        // the tasks which are kept are the first ones by destination, not by source.
+        assert_eq!(Some(3), limit(&mut tasks, 2));
+        assert_eq!(
+            vec![("in/c.wav", "out/a.wav"), ("in/b.wav", "out/b.wav")],
+            order(&tasks)
+        );
+
+        assert_eq!(Some(2), limit(&mut tasks, 0));
+        assert!(tasks.is_empty());
+    }
+
+    #[test]
+    fn test_weight() -> Result<(), Error> {
+        let dir = temp_dir("weight")?;
+        let a = dir.join("a.wav");
+        let b = dir.join("b.wav");
+        let empty = dir.join("empty.wav");
+
+        write_wav(&a, &[100i16; 1000])?;
+        write_wav(&b, &[100i16; 10])?;
+        std::fs::write(&empty, "")?;
+
+        let tasks = [
+            Task::Process(a.clone(), dir.join("out").join("a.wav"), vec![], None),
+            Task::Silence(b.clone(), dir.join("out").join("b.wav")),
+            Task::Copy(empty, dir.join("out").join("empty.wav")),
+            Task::Copy(dir.join("missing.wav"), dir.join("out").join("missing.wav")),
+        ];
+
+        let size = |path: &Path| std::fs::metadata(path).map(|m| m.len());
+        let weights = tasks.iter().map(Task::weight).collect::<Vec<_>>();
+
+        assert_eq!(vec![size(&a)?, size(&b)?, 1, 1], weights);
+        assert_eq!(size(&a)? + size(&b)? + 2, weights.iter().sum::<u64>());
+        assert!(weights[0] > weights[1]);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sizes() -> Result<(), Error> {
+        let dir = temp_dir("sizes")?;
+        let a = dir.join("a.wav");
+        let text = dir.join("a.txt");
+
+        write_wav(&a, &[100i16; 1000])?;
+        std::fs::write(&text, "not audio")?;
+
+        let process = Task::Process(a.clone(), dir.join("out").join("a.wav"), vec![], None);
+        let silence = Task::Silence(a.clone(), dir.join("out").join("a.wav"));
+        let copy = Task::Copy(text.clone(), dir.join("out").join("a.txt"));
+
+        let headers = HeaderCache::new();
+
+        // tasks reserve the size of their decoded samples.
+        assert_eq!(2000, process.decoded_size(&headers));
+        assert_eq!(0, silence.decoded_size(&headers));
+        assert_eq!(0, copy.decoded_size(&headers));
+
+        assert_eq!(
+            std::fs::metadata(&a)?.len(),
+            process.estimated_size(&headers)
+        );
+        assert_eq!(44 + 2000, silence.estimated_size(&headers));
+        assert_eq!(9, copy.estimated_size(&headers));
+        assert_eq!(
+            vec!["process", "silence", "copy"],
+            vec![process.kind(), silence.kind(), copy.kind()]
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_companions() -> Result<(), Error> {
+        let dir = temp_dir("add-companions")?;
+        let source = dir.join("A.WAV");
+        let notes = dir.join("notes.txt");
+        write_wav(&source, &[100i16; 100])?;
+        std::fs::write(dir.join("A.oac"), "file")?;
+        std::fs::write(&notes, "notes")?;
+        std::fs::write(dir.join("notes.oac"), "notes")?;
+
+        let output = dir.join("output");
+        let audio = vec![source.clone()].into_iter().collect::<HashSet<_>>();
+
+        let mut tasks = vec![
+            Task::Copy(source.clone(), output.join("a").join("A.WAV")),
+            Task::Silence(source.clone(), output.join("b").join("A.WAV")),
+            Task::Copy(notes, output.join("notes.txt")),
+        ];
+
+        // companions of other files aren't copied.
+        add_companions(&mut tasks, &[], &audio);
+        add_companions(&mut tasks, &[String::from("oac")], &HashSet::new());
+        assert_eq!(3, tasks.len());
+
+        // companions are only copied once per audio file.
+        add_companions(
+            &mut tasks,
+            &[String::from("oac"), String::from("xml")],
+            &audio,
+        );
+        assert_eq!(4, tasks.len());
+        assert_eq!(dir.join("A.oac"), tasks[3].source());
+        assert_eq!(output.join("a").join("A.oac"), tasks[3].dest());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
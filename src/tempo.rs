@@ -0,0 +1,100 @@
+/// A tempo, used to resolve positions expressed in bars and beats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tempo {
+    /// Beats per minute.
+    pub bpm: f32,
+    /// Number of beats in a bar, from the time signature.
+    pub beats_per_bar: u32,
+    /// The note value of a beat, from the time signature, like `8` for eighth notes.
+    ///
+    /// The tempo counts quarter notes, so a beat lasts `4 / unit` of them.
+    pub unit: u32,
+}
+
+impl Tempo {
+    /// Construct a new tempo in 4/4.
+    pub fn new(bpm: f32) -> Self {
+        Self {
+            bpm,
+            beats_per_bar: 4,
+            unit: 4,
+        }
+    }
+
+    /// Use the given time signature, like `3/4` or `6/8`.
+    ///
+    /// Returns `None` if the time signature is malformed.
+    pub fn with_time_signature(self, s: &str) -> Option<Self> {
+        let mut it = s.split('/');
+        let beats_per_bar = str::parse::<u32>(it.next()?.trim()).ok()?;
+        let unit = str::parse::<u32>(it.next()?.trim()).ok()?;
+
+        if it.next().is_some() || beats_per_bar == 0 || unit == 0 {
+            return None;
+        }
+
+        Some(Self {
+            beats_per_bar,
+            unit,
+            ..self
+        })
+    }
+
+    /// Convert the given bar and beat, both starting at 1, into samples at the given sample rate.
+    ///
+    /// Beats past the end of a bar carry over into the next one. Returns `None` on overflow.
+    pub fn as_samples(&self, bar: u32, beat: u32, sample_rate: u32) -> Option<u32> {
+        let beats = bar
+            .checked_sub(1)?
+            .checked_mul(self.beats_per_bar)?
+            .checked_add(beat.checked_sub(1)?)?;
+
+        let beat = 60f64 / f64::from(self.bpm) * 4f64 / f64::from(self.unit);
+        let samples = f64::from(beats) * beat * f64::from(sample_rate);
+
+        if !samples.is_finite() || samples > f64::from(u32::MAX) {
+            return None;
+        }
+
+        Some(samples.round() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tempo;
+
+    #[test]
+    fn test_as_samples() {
+        let tempo = Tempo::new(120f32);
+        assert_eq!(Some(0), tempo.as_samples(1, 1, 1000));
+        assert_eq!(Some(500), tempo.as_samples(1, 2, 1000));
+        assert_eq!(Some(2000), tempo.as_samples(2, 1, 1000));
+        // beats carry over into the next bar.
+        assert_eq!(tempo.as_samples(2, 1, 1000), tempo.as_samples(1, 5, 1000));
+        assert_eq!(None, tempo.as_samples(0, 1, 1000));
+
+        let tempo = tempo
+            .with_time_signature("3/4")
+            .expect("bad time signature");
+        assert_eq!(Some(1500), tempo.as_samples(2, 1, 1000));
+        assert_eq!(None, tempo.with_time_signature("3"));
+        assert_eq!(None, tempo.with_time_signature("0/4"));
+    }
+
+    #[test]
+    fn test_time_signature_unit() {
+        let quarters = Tempo::new(120f32)
+            .with_time_signature("6/4")
+            .expect("bad time signature");
+        let eighths = Tempo::new(120f32)
+            .with_time_signature("6/8")
+            .expect("bad time signature");
+
+        // an eighth note lasts half as long as a quarter note.
+        assert_eq!(Some(500), quarters.as_samples(1, 2, 1000));
+        assert_eq!(Some(250), eighths.as_samples(1, 2, 1000));
+        assert_eq!(Some(3000), quarters.as_samples(2, 1, 1000));
+        assert_eq!(Some(1500), eighths.as_samples(2, 1, 1000));
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{Range, Replace};
+use crate::{wordlist, Bound, Error, Range, Replace, Wordlist};
+use std::{iter, mem, str};
 
 /// A parsed stranscript.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -7,100 +8,334 @@ pub struct Transcript {
     pub replace: Vec<Replace>,
     /// Marked words without a timestamp.
     pub missing: Vec<String>,
+    /// Unmarked words with a timestamp, like `word{01.000-01.500}`.
+    ///
+    /// These are not censored, unless they match a wordlist.
+    pub timed: Vec<Replace>,
 }
 
 impl Transcript {
-    pub fn parse(text: &str) -> Result<Transcript, failure::Error> {
-        let mut it = text.chars();
+    /// Parse a transcript.
+    ///
+    /// Brackets and braces can be escaped with a backslash, like `\[`, to use them literally.
+    /// Carriage returns are ignored, so transcripts with Windows line endings parse the same.
+    ///
+    /// Unmarked words can also have a timestamp, like `word{01.000-01.500}`. Braces which are not
+    /// followed by a valid range are left as they are.
+    ///
+    /// A marked occurrence of a word, like `[word#2]`, must not be past the number of times the word
+    /// occurs in the transcript, marked or not. If the same occurrence of the word is also timed,
+    /// like the second `word{01.000-01.500}`, the marked range must be within that timing.
+    pub fn parse(text: &str) -> Result<Transcript, Error> {
+        let mut it = text.chars().enumerate().peekable();
 
         let mut replace = Vec::new();
         let mut missing = Vec::new();
+        let mut timed = Vec::new();
+        // Marked words with an occurrence, which are checked once the whole transcript is parsed.
+        let mut occurrences = Vec::new();
+        // The unmarked word being scanned, which might be followed by a timestamp.
+        let mut word = String::new();
 
-        while let Some(c) = it.next() {
+        while let Some((n, c)) = it.next() {
             match c {
+                '\\' => {
+                    word.push(escaped(&mut it).unwrap_or('\\'));
+                }
+                '{' if !word.is_empty() => match timing(&mut it) {
+                    Some(range) => timed.push(Replace::new(mem::take(&mut word), range)),
+                    None => word.push(c),
+                },
+                c if c.is_whitespace() => {
+                    word.clear();
+                }
                 '[' => {
-                    let (word, range) = Self::parse_replace(&mut it)?;
+                    word.clear();
+
+                    let (word, occurrence, range) = Self::parse_replace(text, n, &mut it)?;
+
+                    if let Some(occurrence) = occurrence {
+                        occurrences.push((word.clone(), occurrence, range.clone()));
+                    }
 
                     match range {
                         Some(range) => {
-                            replace.push(Replace { word, range });
+                            let mut r = Replace::new(word, range);
+                            r.occurrence = occurrence;
+                            replace.push(r);
                         }
                         None => {
                             missing.push(word);
                         }
                     }
                 }
-                _ => {}
+                c => word.push(c),
             }
         }
 
-        Ok(Transcript {
+        let transcript = Transcript {
             text: text.to_string(),
             replace,
             missing,
-        })
+            timed,
+        };
+
+        for (word, occurrence, range) in occurrences {
+            let within = match range {
+                Some(ref range) => transcript.within_timing(&word, occurrence, range),
+                None => true,
+            };
+
+            if occurrence as usize > transcript.count(&word) || !within {
+                return Err(Error::ParseOccurrence(format!("{}#{}", word, occurrence)));
+            }
+        }
+
+        Ok(transcript)
     }
 
-    /// Parse a single replacement: [word]{range}.
-    pub fn parse_replace(
-        it: &mut impl Iterator<Item = char>,
-    ) -> Result<(String, Option<Range>), failure::Error> {
-        let mut word = None;
-        let mut buffer = String::new();
+    /// Count the number of times the given word, or sequence of words, occurs in the spoken text
+    /// of the transcript.
+    ///
+    /// Words are compared like in a wordlist, ignoring case and surrounding punctuation.
+    fn count(&self, word: &str) -> usize {
+        let needle = word
+            .split_whitespace()
+            .map(wordlist::normalize)
+            .collect::<Vec<_>>();
+
+        if needle.is_empty() {
+            return 0;
+        }
+
+        let words = self
+            .plain_text()
+            .split_whitespace()
+            .map(wordlist::normalize)
+            .collect::<Vec<_>>();
 
-        while let Some(c) = it.next() {
+        words
+            .windows(needle.len())
+            .filter(|w| *w == &needle[..])
+            .count()
+    }
+
+    /// Test if the range of a marked occurrence of a word is within the timing of the same
+    /// occurrence among the timed words, if there is one.
+    ///
+    /// Ranges which aren't absolute positions, like `^-$`, can't be compared and are accepted.
+    fn within_timing(&self, word: &str, occurrence: u32, range: &Range) -> bool {
+        let word = wordlist::normalize(word);
+
+        let timed = match self
+            .timed
+            .iter()
+            .filter(|r| wordlist::normalize(&r.word) == word)
+            .nth((occurrence as usize).saturating_sub(1))
+        {
+            Some(timed) => timed,
+            None => return true,
+        };
+
+        match (
+            millis(&range.start),
+            millis(&range.end),
+            millis(&timed.range.start),
+            millis(&timed.range.end),
+        ) {
+            (Some(start), Some(end), Some(timed_start), Some(timed_end)) => {
+                timed_start <= start && end <= timed_end
+            }
+            _ => true,
+        }
+    }
+
+    /// Unmarked words with a timestamp which match the given wordlist.
+    pub fn matching<'a>(&'a self, wordlist: &'a Wordlist) -> impl Iterator<Item = &'a Replace> {
+        self.timed
+            .iter()
+            .filter(move |r| wordlist.contains(&r.word))
+    }
+
+    /// The spoken text of the transcript, with every marker reduced to its word and escapes
+    /// resolved.
+    ///
+    /// So `foo [bar]{01.1-$} baz{02.0-03.0}` becomes `foo bar baz`.
+    pub fn plain_text(&self) -> String {
+        let mut out = String::new();
+        let mut it = self.text.chars().enumerate().peekable();
+        let mut in_word = false;
+
+        while let Some((n, c)) = it.next() {
             match c {
-                ']' => {
-                    word = Some(buffer);
-                    break;
+                '\\' => {
+                    out.push(escaped(&mut it).unwrap_or('\\'));
+                    in_word = true;
                 }
+                '\r' => {}
+                '{' if in_word && timing(&mut it).is_some() => {
+                    in_word = false;
+                }
+                '[' => match Self::parse_replace(&self.text, n, &mut it) {
+                    Ok((word, ..)) => {
+                        out.push_str(&word);
+                        in_word = false;
+                    }
+                    // NB: only possible if the transcript was constructed without parsing it.
+                    Err(..) => return self.text.clone(),
+                },
                 c => {
-                    buffer.push(c);
+                    out.push(c);
+                    in_word = !c.is_whitespace();
                 }
             }
         }
 
-        let word = match word {
-            Some(word) => word,
-            None => {
-                failure::bail!("missing word");
-            }
+        out
+    }
+
+    /// Parse a single replacement: [word]{range}, or [word#n]{range} for the n:th occurrence of
+    /// the word.
+    ///
+    /// `start` is the position in characters of the opening `[` in `text`, which is used for
+    /// error messages. The iterator is positioned right after it.
+    pub fn parse_replace(
+        text: &str,
+        start: usize,
+        it: &mut Chars<'_>,
+    ) -> Result<(String, Option<u32>, Option<Range>), Error> {
+        let (word, occurrence) = occurrence(scan(text, start, it, '[', ']')?)?;
+
+        let start = match it.peek() {
+            Some(&(n, '{')) => n,
+            _ => return Ok((word, occurrence, None)),
         };
 
-        let open = it.next();
+        it.next();
+        let range = scan(text, start, it, '{', '}')?;
+        let range = parse_range(&range).ok_or(Error::ParseRange(range))?;
+
+        return Ok((word, occurrence, Some(range)));
+
+        /// Split off the occurrence of a word, like the `2` in `word#2`.
+        ///
+        /// A `#` which isn't followed by only digits is part of the word, like in `C#`.
+        fn occurrence(word: String) -> Result<(String, Option<u32>), Error> {
+            let (head, index) = match word.rfind('#') {
+                Some(n) => (&word[..n], &word[n + 1..]),
+                None => return Ok((word, None)),
+            };
+
+            if index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+                return Ok((word, None));
+            }
 
-        if open != Some('{') {
-            return Ok((word, None));
+            match str::parse::<u32>(index) {
+                Ok(index) if index > 0 => Ok((head.to_string(), Some(index))),
+                _ => Err(Error::ParseOccurrence(word)),
+            }
         }
 
-        let mut range = None;
-        let mut buffer = String::new();
+        /// Scan until the `close` character, erroring if the input ends or another marker is
+        /// opened before that.
+        fn scan(
+            text: &str,
+            start: usize,
+            it: &mut Chars<'_>,
+            open: char,
+            close: char,
+        ) -> Result<String, Error> {
+            let mut buffer = String::new();
 
-        while let Some(c) = it.next() {
-            match c {
-                '}' => {
-                    range = Some(buffer);
-                    break;
-                }
-                c => {
-                    buffer.push(c);
+            while let Some((_, c)) = it.next() {
+                match c {
+                    '\\' => buffer.push(escaped(it).unwrap_or('\\')),
+                    '\r' => {}
+                    c if c == close => return Ok(buffer),
+                    '[' | '{' => break,
+                    c => buffer.push(c),
                 }
             }
+
+            Err(Error::Unclosed {
+                open,
+                position: start,
+                snippet: snippet(text, start),
+            })
         }
 
-        let range = match range {
-            Some(range) => range,
-            None => {
-                failure::bail!("missing range");
+        /// A short snippet of the text starting at the given position.
+        fn snippet(text: &str, start: usize) -> String {
+            const LEN: usize = 20;
+
+            let mut snippet = text.chars().skip(start).take(LEN + 1).collect::<String>();
+
+            if snippet.chars().count() > LEN {
+                snippet = snippet.chars().take(LEN).collect();
+                snippet.push_str("...");
             }
-        };
 
-        let range = Range::parse(&range).ok_or_else(|| failure::format_err!("bad range"))?;
+            snippet
+        }
+    }
+}
+
+/// Parse the range of a marker, like the `01.000-02.000` in `[word]{01.000-02.000}`.
+fn parse_range(range: &str) -> Option<Range> {
+    // NB: the range may be quoted for readability, like `{"01.000 - 02.000"}`.
+    let trimmed = range.trim();
+    let trimmed = ['"', '\'']
+        .iter()
+        .find_map(|q| trimmed.strip_prefix(*q)?.strip_suffix(*q))
+        .unwrap_or(trimmed);
+
+    Range::parse(trimmed)
+}
+
+/// Parse the timestamp of an unmarked word, like the `{01.000-01.500}` in `word{01.000-01.500}`.
+///
+/// The iterator is positioned right after the opening `{`, and is only advanced if it is followed
+/// by a valid range.
+fn timing(it: &mut Chars<'_>) -> Option<Range> {
+    let mut lookahead = it.clone();
+    let mut buffer = String::new();
+
+    loop {
+        match lookahead.next()?.1 {
+            '}' => break,
+            '[' | '{' => return None,
+            '\r' => {}
+            c => buffer.push(c),
+        }
+    }
+
+    let range = parse_range(&buffer)?;
+    *it = lookahead;
+    Some(range)
+}
+
+/// The absolute position of a bound in milliseconds, if it has one.
+fn millis(bound: &Bound) -> Option<u32> {
+    match *bound {
+        Bound::Pos(ref pos) => pos.as_samples(1000),
+        _ => None,
+    }
+}
 
-        Ok((word, Some(range)))
+/// Consume the character escaped by a backslash, if it is one which needs escaping.
+fn escaped(it: &mut Chars<'_>) -> Option<char> {
+    match it.peek() {
+        Some(&(_, c @ ('[' | ']' | '{' | '}' | '\\'))) => {
+            it.next();
+            Some(c)
+        }
+        _ => None,
     }
 }
 
+/// Characters of a transcript being parsed, with their positions.
+pub type Chars<'a> = iter::Peekable<iter::Enumerate<str::Chars<'a>>>;
+
 impl<'de> serde::Deserialize<'de> for Transcript {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -123,25 +358,207 @@ impl serde::Serialize for Transcript {
 #[cfg(test)]
 mod tests {
     use super::Transcript;
-    use crate::{Range, Replace};
+    use crate::{Error, Range, Replace, Wordlist};
 
     #[test]
-    pub fn test() -> Result<(), failure::Error> {
+    pub fn test() -> Result<(), Error> {
         let transcript = Transcript::parse("foo [bar]{01.123-$} [baz]{^-$}")?;
 
-        let a = Replace {
-            word: String::from("bar"),
-            range: Range::parse("01.123-$").expect("valid range"),
-        };
+        let a = Replace::new(
+            String::from("bar"),
+            Range::parse("01.123-$").expect("valid range"),
+        );
 
         assert_eq!(a, transcript.replace[0]);
 
-        let b = Replace {
-            word: String::from("baz"),
-            range: Range::parse("^-$").expect("valid range"),
-        };
+        let b = Replace::new(
+            String::from("baz"),
+            Range::parse("^-$").expect("valid range"),
+        );
 
         assert_eq!(b, transcript.replace[1]);
         Ok(())
     }
+
+    #[test]
+    fn test_spaced_ranges() -> Result<(), Error> {
+        let plain = Transcript::parse("[a]{00:01.0-00:02.0} [b]{^-$}")?;
+
+        for text in &[
+            "[a]{ 00:01.0 - 00:02.0 } [b]{ ^ - $ }",
+            "[a]{\"00:01.0 - 00:02.0\"} [b]{ '^-$' }",
+        ] {
+            assert_eq!(plain.replace, Transcript::parse(text)?.replace);
+        }
+
+        assert!(matches!(
+            Transcript::parse("[a]{\"00:01.0-00:02.0}"),
+            Err(Error::ParseRange(ref range)) if range == "\"00:01.0-00:02.0"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unclosed() {
+        let e = Transcript::parse("hello [world and [other]{^-$}").expect_err("unclosed '['");
+        assert_eq!(
+            "unclosed '[' starting at position 6: [world and [other]{^...",
+            e.to_string()
+        );
+
+        let e = Transcript::parse("hello [world]{01.000-02.000").expect_err("unclosed '{'");
+        assert_eq!(
+            "unclosed '{' starting at position 13: {01.000-02.000",
+            e.to_string()
+        );
+
+        assert!(matches!(
+            Transcript::parse("[a"),
+            Err(Error::Unclosed {
+                open: '[',
+                position: 0,
+                ref snippet,
+            }) if snippet == "[a"
+        ));
+        assert!(Transcript::parse("[a]{").is_err());
+    }
+
+    #[test]
+    fn test_adjacent_markers() -> Result<(), Error> {
+        let transcript = Transcript::parse("[a][b]{^-$} [c]")?;
+        assert_eq!(
+            vec![String::from("a"), String::from("c")],
+            transcript.missing
+        );
+        assert_eq!("b", transcript.replace[0].word);
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_text() -> Result<(), Error> {
+        let transcript = Transcript::parse("foo [bar]{01.1-$} baz [qux] quux")?;
+        assert_eq!("foo bar baz qux quux", transcript.plain_text());
+
+        let transcript = Transcript::parse(r"a \[literal\] [b\]c]{^-$} back\\slash \n")?;
+        assert_eq!(r"a [literal] b]c back\slash \n", transcript.plain_text());
+        assert_eq!("b]c", transcript.replace[0].word);
+        assert!(transcript.missing.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_crlf() -> Result<(), Error> {
+        let transcript = Transcript::parse("foo [bar\r\n]{01.1-$\r\n}\r\nbaz [qux\r]")?;
+        assert_eq!("bar\n", transcript.replace[0].word);
+        assert_eq!(
+            Range::parse("01.1-$"),
+            Some(transcript.replace[0].range.clone())
+        );
+        assert_eq!(vec![String::from("qux")], transcript.missing);
+        assert_eq!("foo bar\n\nbaz qux", transcript.plain_text());
+        Ok(())
+    }
+
+    #[test]
+    fn test_timed() -> Result<(), Error> {
+        let transcript = Transcript::parse(
+            "well{00.0-00.5} [heck]{01.0-02.0} darn{ '02.5-03.0' }, {not} a{timing",
+        )?;
+
+        assert_eq!(
+            vec![
+                Replace::new(
+                    String::from("well"),
+                    Range::parse("00.0-00.5").expect("valid range")
+                ),
+                Replace::new(
+                    String::from("darn"),
+                    Range::parse("02.5-03.0").expect("valid range")
+                ),
+            ],
+            transcript.timed
+        );
+        assert_eq!("heck", transcript.replace[0].word);
+        assert_eq!("well heck darn, {not} a{timing", transcript.plain_text());
+
+        let wordlist = Wordlist::parse("Darn\nheck\n");
+        let matched = transcript.matching(&wordlist).collect::<Vec<_>>();
+        assert_eq!(vec![&transcript.timed[1]], matched);
+        Ok(())
+    }
+
+    #[test]
+    fn test_occurrence() -> Result<(), Error> {
+        let transcript = Transcript::parse("[a] b [a#2]{^-1.0} [C#]{1.0-2.0} [a#3]")?;
+
+        let mut second = Replace::new(String::from("a"), Range::parse("^-1.0").expect("range"));
+        second.occurrence = Some(2);
+
+        assert_eq!(
+            vec![
+                second,
+                Replace::new(String::from("C#"), Range::parse("1.0-2.0").expect("range")),
+            ],
+            transcript.replace
+        );
+        assert_eq!("[a#2]{^-01.000}", transcript.replace[0].to_string());
+        assert_eq!(vec!["a", "a"], transcript.missing);
+        assert_eq!("a b a C# a", transcript.plain_text());
+
+        assert!(matches!(
+            Transcript::parse("[a#0]{^-$}"),
+            Err(Error::ParseOccurrence(ref word)) if word == "a#0"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_occurrence_out_of_range() -> Result<(), Error> {
+        // occurrences count every time the word is spoken, marked or not.
+        let transcript = Transcript::parse("A, b a! [a#3]{^-$} [b a#2]{1.0-2.0}")?;
+        assert_eq!(Some(3), transcript.replace[0].occurrence);
+        assert_eq!(Some(2), transcript.replace[1].occurrence);
+        assert!(Transcript::parse("[a] [a#2]")?.replace.is_empty());
+
+        for (text, expected) in &[
+            ("[a#2]{^-$}", "a#2"),
+            ("a [b#2]{^-$}", "b#2"),
+            ("a [a#3]", "a#3"),
+            ("b a [b a#3]{^-$}", "b a#3"),
+        ] {
+            assert!(matches!(
+                Transcript::parse(text),
+                Err(Error::ParseOccurrence(ref word)) if word == expected
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_occurrence_timing() -> Result<(), Error> {
+        // the range of a marked occurrence must be within the timing of the same occurrence.
+        let transcript = Transcript::parse("a{1.0-2.0} A{3.0-4.0} [a#2]{3.2-3.8}")?;
+        assert_eq!(Some(2), transcript.replace[0].occurrence);
+        assert!(Transcript::parse("a{1.0-2.0} [a#1]{1.0-2.0}").is_ok());
+        assert!(Transcript::parse("a{0:01-0:02} [a#1]{1.5-1.9}").is_ok());
+
+        // open ranges and occurrences which aren't timed can't be checked.
+        assert!(Transcript::parse("a{1.0-2.0} [a#1]{^-$}").is_ok());
+        assert!(Transcript::parse("a{1.0-2.0} [a#2]{5.0-6.0}").is_ok());
+        assert!(Transcript::parse("a{1.0-2.0} [a#1]").is_ok());
+
+        for text in &[
+            "a{1.0-2.0} a{3.0-4.0} [a#2]{1.2-1.8}",
+            "a{1.0-2.0} a{3.0-4.0} [a#2]{2.5-3.5}",
+            "A!{1.0-2.0} [a#1]{1.5-2.5}",
+        ] {
+            assert!(matches!(
+                Transcript::parse(text),
+                Err(Error::ParseOccurrence(ref word)) if word.starts_with("a#")
+            ));
+        }
+
+        Ok(())
+    }
 }
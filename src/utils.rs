@@ -11,11 +11,11 @@ pub fn as_uppercase_radix(mut index: usize) -> String {
 
     while index > 0 {
         buf.extend(std::char::from_u32(BASE + (index % DIV) as u32));
-        index = index / DIV;
+        index /= DIV;
         count += 1;
     }
 
-    buf.extend(std::iter::repeat('A').take(2usize.saturating_sub(count)));
+    buf.extend(std::iter::repeat_n('A', 2usize.saturating_sub(count)));
     buf.into_iter().rev().collect::<String>()
 }
 
@@ -56,57 +56,72 @@ pub fn path_file_suffix<'a>(
 }
 
 /// Handle path enumeration.
-/// This replaces the first occurence of `$` with as many numbers as needed.
-pub fn path_enumeration<'a>(index: usize, path: Cow<'a, RelativePath>) -> Cow<'a, RelativePath> {
+///
+/// Every run of `$` in the path is replaced with the current enumeration (starting at 1),
+/// zero-padded to the number of `$` in the run. So `disc$/track$$` with index 2 becomes
+/// `disc3/track03`. A `$@` is replaced with the index as a two-letter uppercase radix. All runs
+/// in the path use the same index.
+pub fn path_enumeration(index: usize, path: Cow<'_, RelativePath>) -> Cow<'_, RelativePath> {
     let s = path.as_str();
 
-    let prefix_i = match s.find("$") {
-        None => return path,
-        Some(prefix_i) => prefix_i,
-    };
+    if !s.contains('$') {
+        return path;
+    }
 
     let mut buffer = String::with_capacity(s.len());
-    let (prefix, rest) = s.split_at(prefix_i);
+    let mut rest = s;
 
-    if rest.starts_with("$@") {
+    while let Some(i) = rest.find('$') {
+        let (prefix, run) = rest.split_at(i);
         buffer.push_str(prefix);
-        buffer.push_str(&as_uppercase_radix(index));
-        buffer.push_str(&rest[2..]);
-        return Cow::Owned(RelativePathBuf::from(buffer));
-    }
 
-    let rest_i;
-    let mut width = 0;
-    let mut it = rest.char_indices();
-
-    loop {
-        rest_i = match it.next() {
-            Some((_, '$')) => {
-                width += 1;
-                continue;
-            }
-            Some((n, _)) => n,
-            None => rest.len(),
-        };
-
-        break;
+        if let Some(run) = run.strip_prefix("$@") {
+            buffer.push_str(&as_uppercase_radix(index));
+            rest = run;
+            continue;
+        }
+
+        let width = run.len() - run.trim_start_matches('$').len();
+        buffer.push_str(&format!("{:0width$}", index + 1, width = width));
+        rest = &run[width..];
     }
 
-    buffer.push_str(prefix);
-    buffer.push_str(&format!("{:0width$}", index + 1, width = width));
+    buffer.push_str(rest);
+    Cow::Owned(RelativePathBuf::from(buffer))
+}
 
-    if rest_i < rest.len() {
-        buffer.push_str(&rest[rest_i..]);
+/// Derive a seed for the given path from a seed for the whole run.
+///
+/// This uses FNV-1a, so that the derived seed is stable across platforms and Rust versions.
+pub fn path_seed(seed: u64, path: &std::path::Path) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+
+    for b in seed
+        .to_le_bytes()
+        .iter()
+        .chain(path.to_string_lossy().as_bytes())
+    {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
     }
 
-    Cow::Owned(RelativePathBuf::from(buffer))
+    hash
+}
+
+/// Advance the given state and return the next pseudo-random number.
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{as_uppercase_radix, path_enumeration};
+    use super::{as_uppercase_radix, path_enumeration, path_seed};
     use relative_path::RelativePath;
-    use std::borrow::Cow;
+    use std::{borrow::Cow, path::Path};
 
     #[test]
     fn test_path_enumeration() {
@@ -121,6 +136,14 @@ mod tests {
         let path = Cow::Borrowed(RelativePath::new("foo/bar$@/foo"));
         let path = path_enumeration(0, path);
         assert_eq!("foo/barAA/foo", path.as_str());
+
+        let path = Cow::Borrowed(RelativePath::new("disc$/track$$"));
+        let path = path_enumeration(2, path);
+        assert_eq!("disc3/track03", path.as_str());
+
+        let path = Cow::Borrowed(RelativePath::new("$@/$@$$"));
+        let path = path_enumeration(27, path);
+        assert_eq!("BB/BB28", path.as_str());
     }
 
     #[test]
@@ -133,4 +156,12 @@ mod tests {
         assert_eq!("BZ", as_uppercase_radix(51));
         assert_eq!("CA", as_uppercase_radix(52));
     }
+
+    #[test]
+    fn test_path_seed() {
+        let a = path_seed(42, Path::new("output/a.wav"));
+        assert_eq!(a, path_seed(42, Path::new("output/a.wav")));
+        assert_ne!(a, path_seed(43, Path::new("output/a.wav")));
+        assert_ne!(a, path_seed(42, Path::new("output/b.wav")));
+    }
 }
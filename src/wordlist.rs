@@ -0,0 +1,67 @@
+use crate::Error;
+use std::{collections::HashSet, fs, path::Path};
+
+/// A list of words to censor wherever they have a timestamp in a transcript.
+///
+/// The list has one word per line, where empty lines and lines starting with `#` are ignored.
+/// Words are matched regardless of case and surrounding punctuation.
+#[derive(Debug, Clone, Default)]
+pub struct Wordlist {
+    words: HashSet<String>,
+}
+
+impl Wordlist {
+    /// Parse a wordlist.
+    pub fn parse(text: &str) -> Wordlist {
+        let words = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .map(normalize)
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        Wordlist { words }
+    }
+
+    /// Open the wordlist in the given file.
+    pub fn open(path: &Path) -> Result<Wordlist, Error> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Test if the given word is in the list.
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(&normalize(word))
+    }
+}
+
+/// Normalize a word for matching.
+pub(crate) fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Wordlist;
+    use crate::Error;
+    use std::path::Path;
+
+    #[test]
+    fn test_wordlist() {
+        let wordlist = Wordlist::parse("# words to censor\nHeck\n\n  darn  \n!!!\n");
+
+        assert!(wordlist.contains("heck"));
+        assert!(wordlist.contains("Darn,"));
+        assert!(wordlist.contains("\"HECK!\""));
+        assert!(!wordlist.contains("hecking"));
+        assert!(!wordlist.contains("words"));
+        assert!(!wordlist.contains(""));
+    }
+
+    #[test]
+    fn test_open_missing() {
+        let e = Wordlist::open(Path::new("does-not-exist.txt")).expect_err("missing file");
+        assert!(matches!(e, Error::Io(ref e) if e.kind() == std::io::ErrorKind::NotFound));
+    }
+}
@@ -0,0 +1,72 @@
+//! Helpers shared by the end-to-end tests, which run the batchcensor binary on files in a
+//! temporary directory.
+
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Construct a fresh temporary directory for the given test.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("batchcensor-e2e-{}-{}", name, std::process::id()));
+
+    if dir.is_dir() {
+        std::fs::remove_dir_all(&dir).expect("failed to remove temp dir");
+    }
+
+    std::fs::create_dir_all(&dir).expect("failed to create temp dir");
+    dir
+}
+
+/// Write a mono test file with the given samples.
+pub fn write_test_wav(path: &Path, samples: &[i16]) -> Result<(), failure::Error> {
+    write_test_wav_at(path, samples, 1000)
+}
+
+/// Write a mono test file with the given samples and sample rate.
+pub fn write_test_wav_at(
+    path: &Path,
+    samples: &[i16],
+    sample_rate: u32,
+) -> Result<(), failure::Error> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    std::fs::create_dir_all(path.parent().expect("no parent"))?;
+    let mut w = hound::WavWriter::create(path, spec)?;
+
+    for s in samples {
+        w.write_sample(*s)?;
+    }
+
+    w.finalize()?;
+    Ok(())
+}
+
+/// Read all samples from the given test file.
+pub fn read_test_wav(path: &Path) -> Result<Vec<i16>, failure::Error> {
+    let mut r = hound::WavReader::open(path)?;
+    Ok(r.samples::<i16>().collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Run batchcensor with the given arguments, and return what it wrote to stdout.
+///
+/// If it fails, the error is what it wrote to stderr.
+pub fn run_args(args: &[&str]) -> Result<String, failure::Error> {
+    let output = Command::new(env!("CARGO_BIN_EXE_batchcensor"))
+        .args(args)
+        .env("RUST_BACKTRACE", "0")
+        .output()?;
+
+    if !output.status.success() {
+        failure::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
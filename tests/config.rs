@@ -0,0 +1,327 @@
+mod common;
+
+use batchcensor::{generator, Config};
+use common::{read_test_wav, run_args, temp_dir, write_test_wav};
+use std::path::Path;
+
+#[test]
+fn test_config_errors_in_order() -> Result<(), failure::Error> {
+    let dir = temp_dir("config-errors-in-order");
+
+    for name in &["c.yml", "b.yml", "a.yml"] {
+        std::fs::write(dir.join(name), "dirs: [")?;
+    }
+
+    let path = |name: &str| dir.join(name).to_str().expect("bad path").to_string();
+
+    // the first configuration which fails to load, in the order they are loaded in.
+    let e = run_args(&["-d", &path("")]).expect_err("bad configs");
+    assert!(e.to_string().contains(&path("a.yml")), "{}", e);
+    assert!(!e.to_string().contains(&path("c.yml")), "{}", e);
+
+    let e = run_args(&["-c", &path("c.yml"), "-c", &path("b.yml")]).expect_err("bad configs");
+    assert!(e.to_string().contains(&path("c.yml")), "{}", e);
+    assert!(!e.to_string().contains(&path("b.yml")), "{}", e);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_default_generator() -> Result<(), failure::Error> {
+    use batchcensor::Generator as _;
+
+    let dir = temp_dir("default-generator");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "default_generator: tone:square\ndirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-$}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2").join("A.wav");
+
+    let square = generator::Tone::new().with_waveform(generator::Waveform::Square);
+    run_args(&["-c", config])?;
+    assert_eq!(square.generate(0..1000, 1000), read_test_wav(&output)?);
+
+    // generators on the command line take precedence.
+    let saw = generator::Tone::new().with_waveform(generator::Waveform::Saw);
+    run_args(&["-c", config, "--tone-waveform", "saw"])?;
+    assert_eq!(saw.generate(0..1000, 1000), read_test_wav(&output)?);
+
+    std::fs::write(
+        dir.join("config.yml"),
+        "default_generator: pink\ndirs:\n- path: ar2\n",
+    )?;
+    let e = run_args(&["-c", config]).expect_err("unknown generator");
+    assert!(e.to_string().contains("unknown default_generator"), "{}", e);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_beat_positions_require_bpm() -> Result<(), failure::Error> {
+    let dir = temp_dir("beat-positions-require-bpm");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 4000])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{.100-b2}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+
+    // missing and bad tempos are reported before anything is processed.
+    let e = run_args(&["-c", config]).expect_err("missing --bpm");
+    assert!(e.to_string().contains("`b2.1` requires --bpm"), "{}", e);
+    assert!(!dir.join("output").exists());
+
+    let e = run_args(&["-c", config, "--bpm", "fast"]).expect_err("bad --bpm");
+    assert!(e.to_string().contains("bad --bpm"), "{}", e);
+    assert!(!dir.join("output").exists());
+
+    run_args(&["-c", config, "--bpm", "120"])?;
+    assert!(dir.join("output").join("ar2").join("A.wav").is_file());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_wordlist() -> Result<(), failure::Error> {
+    let dir = temp_dir("wordlist");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"well{.000-.100} \
+         heck{.200-.300} that darn{.500-.600}. [boo]{.800-.900}\"\n",
+    )?;
+    std::fs::write(dir.join("words.txt"), "# censored\nHeck\ndarn\n")?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let words = dir.join("words.txt");
+    let words = words.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2").join("A.wav");
+
+    let expected = |ranges: &[(usize, usize)]| {
+        let mut data = vec![100i16; 1000];
+
+        for &(start, end) in ranges {
+            data[start..end].iter_mut().for_each(|s| *s = 0);
+        }
+
+        data
+    };
+
+    // only marked words are censored without a wordlist.
+    run_args(&["-c", config])?;
+    assert_eq!(expected(&[(800, 900)]), read_test_wav(&output)?);
+
+    run_args(&["-c", config, "--wordlist", words])?;
+    assert_eq!(
+        expected(&[(200, 300), (500, 600), (800, 900)]),
+        read_test_wav(&output)?
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_resolved_files() -> Result<(), failure::Error> {
+    let dir = temp_dir("resolved-files");
+
+    for name in &["X_A01.wav", "X_A02.wav", "X_A03.wav"] {
+        write_test_wav(&dir.join("ar2").join(name), &[100i16; 100])?;
+    }
+
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_prefix: X_\n  file_extension: wav\n  files:\n  \
+         - A$$: \"[a]{^-.050}\"\n  - A$$: \"hello\"\n  - A$$: \"[b]{.050-$}\"\n",
+    )?;
+
+    let config: Config = serde_yaml::from_str(&std::fs::read_to_string(dir.join("config.yml"))?)?;
+    run_args(&["-c", dir.join("config.yml").to_str().expect("bad path")])?;
+
+    let censored = |range: std::ops::Range<usize>| {
+        let mut data = vec![100i16; 100];
+        data[range].iter_mut().for_each(|s| *s = 0);
+        data
+    };
+
+    let expected = vec![censored(0..50), censored(0..0), censored(50..100)];
+
+    let resolved = config.resolved_files().collect::<Vec<_>>();
+    assert_eq!(3, resolved.len());
+
+    for ((_, path, ..), expected) in resolved.into_iter().zip(expected) {
+        let output = dir.join("output").join(path.to_path(""));
+        assert_eq!(expected, read_test_wav(&output)?, "{}", output.display());
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_subtitles() -> Result<(), failure::Error> {
+    let dir = temp_dir("subtitles");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+    std::fs::write(
+        dir.join("ar2").join("A.srt"),
+        "1\n00:00:00,100 --> 00:00:00,200\nwhat the [heck]\n\n\
+         2\n00:00:00,500 --> 00:00:00,600\noh darn\n",
+    )?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{.800-.900}\"\n",
+    )?;
+    std::fs::write(dir.join("words.txt"), "darn\n")?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let words = dir.join("words.txt");
+    let words = words.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2").join("A.wav");
+
+    let expected = |ranges: &[(usize, usize)]| {
+        let mut data = vec![100i16; 1000];
+
+        for &(start, end) in ranges {
+            data[start..end].iter_mut().for_each(|s| *s = 0);
+        }
+
+        data
+    };
+
+    // subtitles are ignored unless asked for.
+    run_args(&["-c", config])?;
+    assert_eq!(expected(&[(800, 900)]), read_test_wav(&output)?);
+
+    run_args(&["-c", config, "--subtitles"])?;
+    assert_eq!(expected(&[(100, 200), (800, 900)]), read_test_wav(&output)?);
+
+    run_args(&["-c", config, "--subtitles", "--wordlist", words])?;
+    assert_eq!(
+        expected(&[(100, 200), (500, 600), (800, 900)]),
+        read_test_wav(&output)?
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_config_ext() -> Result<(), failure::Error> {
+    let dir = temp_dir("config-ext");
+    let configs = dir.join("configs");
+    std::fs::create_dir_all(&configs)?;
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    std::fs::write(
+        configs.join("a.censor.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-.050}\"\n",
+    )?;
+    std::fs::write(configs.join("other.yml"), "not: [a, config")?;
+    std::fs::write(configs.join(".censor.yml"), "not: [a, config")?;
+
+    let root = dir.to_str().expect("bad path");
+    let config_dir = configs.to_str().expect("bad path");
+
+    // the broken config is picked up by default.
+    assert!(run_args(&["-d", config_dir, "-r", root]).is_err());
+
+    run_args(&["-d", config_dir, "-r", root, "--config-ext", "censor.yml"])?;
+
+    let mut expected = vec![0i16; 50];
+    expected.resize(100, 100);
+    assert_eq!(
+        expected,
+        read_test_wav(&dir.join("output").join("ar2").join("A.wav"))?
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_init_inplace() -> Result<(), failure::Error> {
+    let dir = temp_dir("init-inplace");
+
+    for (name, dir_path, files) in &[
+        ("a", "ar2", ["A", "B"]),
+        ("b", "ar3", ["C", "D"]),
+        ("c", "ar4", ["E", "F"]),
+    ] {
+        for file in files {
+            let path = dir.join(name).join(dir_path).join(format!("{}.wav", file));
+            write_test_wav(&path, &[100i16; 100])?;
+        }
+    }
+
+    let write_config = |name: &str, content: &str| -> Result<String, failure::Error> {
+        let path = dir.join(name).join("config.yml");
+        std::fs::write(&path, content)?;
+        Ok(path.to_str().expect("bad path").to_string())
+    };
+
+    let a = write_config(
+        "a",
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"hello\"\n",
+    )?;
+    let b = write_config(
+        "b",
+        "dirs:\n- path: ar3\n  file_extension: wav\n  files:\n  - C: \"hello\"\n",
+    )?;
+    let c = write_config(
+        "c",
+        "dirs:\n- path: ar4\n  file_extension: wav\n  files:\n  - E: a\n    F: b\n",
+    )?;
+    let original = std::fs::read_to_string(&c)?;
+
+    run_args(&["-c", &a, "-c", &b, "-c", &c, "--init-inplace"])?;
+
+    let files = |path: &str| -> Result<Vec<(String, String)>, failure::Error> {
+        let config = batchcensor::Loader::default().load(Path::new(path))?;
+        assert_eq!(1, config.dirs.len());
+
+        Ok(config.dirs[0]
+            .files
+            .iter()
+            .map(|(path, _, transcript, _)| {
+                (
+                    path.as_str().to_string(),
+                    transcript.map(|t| t.text.clone()).unwrap_or_default(),
+                )
+            })
+            .collect())
+    };
+
+    let file = |path: &str, text: &str| (String::from(path), String::from(text));
+
+    assert_eq!(vec![file("A", "hello"), file("B", "[missing]")], files(&a)?);
+    assert_eq!(vec![file("C", "hello"), file("D", "[missing]")], files(&b)?);
+    assert_eq!(original, std::fs::read_to_string(&c)?);
+
+    // configurations with includes can't be written back.
+    std::fs::write(dir.join("a").join("base.yml"), "file_extension: wav")?;
+    write_config(
+        "a",
+        "include: [base.yml]\ndirs:\n- path: ar2\n  files:\n  - A: \"hello\"\n",
+    )?;
+
+    let e = run_args(&["-c", &a, "--init-inplace"]).expect_err("expected error");
+    assert!(e.to_string().contains("with includes"), "{}", e);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
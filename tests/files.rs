@@ -0,0 +1,343 @@
+mod common;
+
+use common::{read_test_wav, run_args, temp_dir, write_test_wav};
+use std::path::Path;
+
+#[test]
+fn test_exclude() -> Result<(), failure::Error> {
+    let dir = temp_dir("exclude");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    write_test_wav(&dir.join("ar2").join("ref").join("B.wav"), &[100i16; 100])?;
+    std::fs::write(dir.join("ar2").join("ref").join("notes.txt"), "notes")?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"hello\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2");
+
+    // excluded files never count as missing, so there is nothing to initialize.
+    let init = dir.join("init.yml");
+    let init = init.to_str().expect("bad path");
+    run_args(&["-c", config, "--exclude", "**/ref/**", "--init", init])?;
+    assert!(!Path::new(init).exists());
+
+    run_args(&["-c", config, "--exclude", "**/ref/**"])?;
+    assert!(output.join("A.wav").is_file());
+    assert!(!output.join("ref").exists());
+
+    run_args(&["-c", config])?;
+    assert!(output.join("B.wav").is_file());
+    assert!(output.join("ref").join("notes.txt").is_file());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_output_extension() -> Result<(), failure::Error> {
+    let dir = temp_dir("output-extension");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    write_test_wav(&dir.join("ar2").join("B.wav"), &[100i16; 100])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "file_extension: wav\noutput_extension: WAV\ndirs:\n- path: ar2\n  files:\n  - A: \"[a]{^-.010}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    run_args(&[
+        "-c",
+        config.to_str().expect("bad path"),
+        "--companion-ext",
+        "oac",
+    ])?;
+
+    // both processed and missing files use the extension of the configuration.
+    let output = dir.join("output").join("ar2");
+    let mut written = std::fs::read_dir(&output)?
+        .map(|e| Ok(e?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, failure::Error>>()?;
+    written.sort();
+    assert_eq!(vec!["A.WAV", "B.WAV"], written);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_companions() -> Result<(), failure::Error> {
+    let dir = temp_dir("companions");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    std::fs::write(dir.join("ar2.oac"), "dir")?;
+    std::fs::write(dir.join("ar2").join("A.oac"), "file")?;
+    std::fs::write(dir.join("ar2").join("A.xml"), "file")?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  output_extension: ogg\n  files:\n  - A: \"[a]{^-.010}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output");
+
+    run_args(&["-c", config, "--companion-ext", "oac,xml"])?;
+    assert_eq!("dir", std::fs::read_to_string(output.join("ar2.oac"))?);
+    assert_eq!(
+        "file",
+        std::fs::read_to_string(output.join("ar2").join("A.oac"))?
+    );
+    assert!(output.join("ar2").join("A.xml").is_file());
+    assert!(output.join("ar2").join("A.ogg").is_file());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_configured_file_extension() -> Result<(), failure::Error> {
+    let dir = temp_dir("configured-file-extension");
+
+    write_test_wav(&dir.join("ar2").join("A.WAV"), &[100i16; 100])?;
+    write_test_wav(&dir.join("ar2").join("B.WAV"), &[100i16; 100])?;
+    std::fs::write(dir.join("ar2").join("notes.txt"), "notes")?;
+    std::fs::write(dir.join("ar2").join("A.oac"), "file")?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: WAV\n  files:\n  - A: \"[a]{^-.010}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    run_args(&[
+        "-c",
+        config.to_str().expect("bad path"),
+        "--companion-ext",
+        "oac",
+    ])?;
+
+    // files with the configured extension are audio, so unconfigured ones are silenced instead of
+    // copied.
+    let output = dir.join("output").join("ar2");
+    let a = read_test_wav(&output.join("A.WAV"))?;
+    assert!(a[..10].iter().all(|s| *s == 0));
+    assert!(a[10..].iter().all(|s| *s == 100));
+    assert_eq!(vec![0i16; 100], read_test_wav(&output.join("B.WAV"))?);
+    assert!(output.join("notes.txt").is_file());
+    assert_eq!("file", std::fs::read_to_string(output.join("A.oac"))?);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_output_inside_root() -> Result<(), failure::Error> {
+    let dir = temp_dir("output-inside-root");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    std::fs::write(dir.join("config.yml"), "dirs:\n- path: ar2\n")?;
+    std::fs::write(dir.join("all.yml"), "dirs:\n- path: .\n")?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let nested = dir.join("ar2").join("out");
+    let nested = nested.to_str().expect("bad path");
+
+    let e = run_args(&["-c", config, "-o", nested]).expect_err("nested output");
+    assert!(
+        e.to_string().contains("is inside the processed directory"),
+        "{}",
+        e
+    );
+    assert!(!Path::new(nested).exists());
+
+    // the default output is inside a directory which covers the whole root.
+    let all = dir.join("all.yml");
+    let e = run_args(&["-c", all.to_str().expect("bad path")]).expect_err("nested output");
+    assert!(
+        e.to_string().contains("is inside the processed directory"),
+        "{}",
+        e
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_output_template() -> Result<(), failure::Error> {
+    let dir = temp_dir("output-template");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    write_test_wav(&dir.join("ar3").join("B.wav"), &[100i16; 100])?;
+    std::fs::write(dir.join("a.yml"), "dirs:\n- path: ar2\n")?;
+    std::fs::write(dir.join("b.yml"), "dirs:\n- path: ar3\n")?;
+
+    let a = dir.join("a.yml");
+    let b = dir.join("b.yml");
+    let output = dir.join("out").join("{config}");
+
+    run_args(&[
+        "-c",
+        a.to_str().expect("bad path"),
+        "-c",
+        b.to_str().expect("bad path"),
+        "-o",
+        output.to_str().expect("bad path"),
+    ])?;
+
+    let out = dir.join("out");
+    assert!(out.join("a").join("ar2").join("A.wav").is_file());
+    assert!(out.join("b").join("ar3").join("B.wav").is_file());
+    assert!(!out.join("a").join("ar3").exists());
+    assert!(!out.join("b").join("ar2").exists());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_mirror_all() -> Result<(), failure::Error> {
+    let dir = temp_dir("mirror-all");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    write_test_wav(&dir.join("music").join("B.wav"), &[100i16; 100])?;
+    std::fs::write(dir.join("music").join("notes.txt"), "notes")?;
+    std::fs::write(dir.join("readme.txt"), "readme")?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-$}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output");
+
+    // unconfigured directories are left out by default.
+    run_args(&["-c", config])?;
+    assert!(!output.join("music").exists());
+
+    // NB: run twice, to make sure that the output isn't mirrored into itself.
+    for _ in 0..2 {
+        run_args(&["-c", config, "--mirror-all"])?;
+    }
+
+    assert_eq!(
+        vec![0i16; 100],
+        read_test_wav(&output.join("ar2").join("A.wav"))?
+    );
+    assert_eq!(
+        std::fs::read(dir.join("music").join("B.wav"))?,
+        std::fs::read(output.join("music").join("B.wav"))?
+    );
+    assert_eq!(
+        "notes",
+        std::fs::read_to_string(output.join("music").join("notes.txt"))?
+    );
+    assert_eq!(
+        "readme",
+        std::fs::read_to_string(output.join("readme.txt"))?
+    );
+    assert!(!output.join("config.yml").exists());
+    assert!(!output.join("output").exists());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_play() -> Result<(), failure::Error> {
+    let dir = temp_dir("play");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    write_test_wav(&dir.join("ar2").join("B.wav"), &[100i16; 100])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-$}\"\n    B: \"[b]{^-$}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let played = dir.join("played.wav");
+    let command = format!("cp {{}} {}", played.display());
+
+    // two processed files are not played.
+    run_args(&["-c", config, "--play", &command])?;
+    assert!(!played.exists());
+
+    run_args(&["-c", config, "--play", &command, "--limit", "1"])?;
+    assert_eq!(
+        std::fs::read(dir.join("output").join("ar2").join("A.wav"))?,
+        std::fs::read(&played)?
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_no_companions() -> Result<(), failure::Error> {
+    let dir = temp_dir("no-companions");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    std::fs::write(dir.join("ar2.oac"), "dir")?;
+    std::fs::write(dir.join("ar2").join("A.oac"), "file")?;
+    std::fs::create_dir_all(dir.join("meta"))?;
+    std::fs::write(dir.join("meta").join("voices.oac"), "custom")?;
+
+    let config = dir.join("config.yml");
+    std::fs::write(
+        &config,
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-.010}\"\n",
+    )?;
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output");
+
+    for flag in &["--no-companions", "--no-oac"] {
+        run_args(&["-c", config, flag])?;
+        assert!(output.join("ar2").join("A.wav").is_file());
+        assert!(!output.join("ar2.oac").exists());
+        assert!(!output.join("ar2").join("A.oac").exists());
+    }
+
+    // a configured companion is copied instead of the one next to the directory.
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  companion: meta/voices.oac\n  files:\n  - A: \"[a]{^-.010}\"\n",
+    )?;
+
+    run_args(&["-c", config])?;
+    assert_eq!("custom", std::fs::read_to_string(output.join("ar2.oac"))?);
+    assert_eq!(
+        "file",
+        std::fs::read_to_string(output.join("ar2").join("A.oac"))?
+    );
+
+    // the configured companion is relative to the configuration, not to --root.
+    let root = dir.join("root");
+    write_test_wav(&root.join("ar2").join("A.wav"), &[100i16; 100])?;
+    let root_output = dir.join("root-output");
+    run_args(&[
+        "-c",
+        config,
+        "--root",
+        root.to_str().expect("bad path"),
+        "-o",
+        root_output.to_str().expect("bad path"),
+    ])?;
+    assert_eq!(
+        "custom",
+        std::fs::read_to_string(root_output.join("ar2.oac"))?
+    );
+
+    std::fs::remove_file(dir.join("meta").join("voices.oac"))?;
+    let e = run_args(&["-c", config]).expect_err("expected missing companion");
+    assert!(e.to_string().contains("no such companion file"), "{}", e);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,621 @@
+mod common;
+
+use common::{read_test_wav, run_args, temp_dir, write_test_wav, write_test_wav_at};
+
+#[test]
+fn test_on_missing() -> Result<(), failure::Error> {
+    let dir = temp_dir("on-missing");
+    let samples = vec![100i16; 1000];
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &samples)?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"hello [world]\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2").join("A.wav");
+
+    run_args(&["-c", config])?;
+    assert_eq!(vec![0i16; 1000], read_test_wav(&output)?);
+
+    run_args(&["-c", config, "--on-missing", "skip"])?;
+    assert_eq!(samples, read_test_wav(&output)?);
+
+    let e = run_args(&["-c", config, "--on-missing", "error"]).expect_err("expected error");
+    assert!(e.to_string().contains("A.wav"), "{}", e);
+
+    assert!(run_args(&["-c", config, "--on-missing", "bogus"]).is_err());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_retain_originals() -> Result<(), failure::Error> {
+    let dir = temp_dir("retain-originals");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+    write_test_wav(&dir.join("ar3").join("A.wav"), &[200i16; 1000])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "file_extension: wav\ndirs:\n- path: ar2\n  files:\n  - A: \"[a]{.100-.200}\"\n- path: ar3\n  files:\n  - A: \"[a]{.100-.200}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let originals = dir.join("originals");
+
+    run_args(&[
+        "-c",
+        config.to_str().expect("bad path"),
+        "--retain-originals",
+        originals.to_str().expect("bad path"),
+    ])?;
+
+    // outputs with the same name in different directories keep separate clips.
+    assert_eq!(
+        vec![100i16; 100],
+        read_test_wav(&originals.join("ar2").join("A_100-200.wav"))?
+    );
+    assert_eq!(
+        vec![200i16; 100],
+        read_test_wav(&originals.join("ar3").join("A_100-200.wav"))?
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_jobs_from() -> Result<(), failure::Error> {
+    let dir = temp_dir("jobs-from");
+
+    // two configurations with different roots, which both have the same directory.
+    for root in &["one", "two"] {
+        write_test_wav(&dir.join(root).join("ar2").join("A.wav"), &[100i16; 1000])?;
+        std::fs::write(
+            dir.join(root).join("config.yml"),
+            "file_extension: wav\ndirs:\n- path: ar2\n  files:\n  - A: \"[a]{.100-.200}\"\n  - B: \"[b]{.100-.200}\"\n",
+        )?;
+    }
+
+    let one = dir.join("one").join("config.yml");
+    let two = dir.join("two").join("config.yml");
+    let jobs = dir.join("jobs.txt");
+
+    let args = [
+        "-c",
+        one.to_str().expect("bad path"),
+        "-c",
+        two.to_str().expect("bad path"),
+        "--jobs-from",
+        jobs.to_str().expect("bad path"),
+    ];
+
+    // listed files only belong to the root they are in.
+    std::fs::write(&jobs, format!("{}\n", dir.join("one/ar2/A.wav").display()))?;
+    run_args(&args)?;
+    assert!(dir.join("one/output/ar2/A.wav").is_file());
+    assert!(!dir.join("two/output/ar2/A.wav").exists());
+
+    // a listed file which is configured but missing is still an error.
+    std::fs::write(&jobs, format!("{}\n", dir.join("two/ar2/B.wav").display()))?;
+    let e = run_args(&args).expect_err("missing file");
+    assert!(
+        e.to_string().contains("did not expect to censor file"),
+        "{}",
+        e
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_silence_transitions() -> Result<(), failure::Error> {
+    let dir = temp_dir("silence-transitions");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+
+    let config = dir.join("config.yml");
+    let write_config = |transcript: &str| {
+        std::fs::write(
+            &config,
+            format!(
+                "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"{}\"\n",
+                transcript
+            ),
+        )
+    };
+
+    let output = dir.join("output").join("ar2").join("A.wav");
+    let config = config.to_str().expect("bad path");
+
+    // a word without a range silences the whole file.
+    write_config("[a]")?;
+    run_args(&["-c", config])?;
+    assert!(read_test_wav(&output)?.iter().all(|s| *s == 0));
+
+    // once the word has a range, the file is processed instead.
+    write_config("[a]{^-.100}")?;
+    run_args(&["-c", config])?;
+    let data = read_test_wav(&output)?;
+    assert!(data[..100].iter().all(|s| *s == 0));
+    assert!(data[100..].iter().all(|s| *s == 100));
+
+    // and the other way around, which must not leave the processed output in place.
+    write_config("[a]")?;
+    run_args(&["-c", config])?;
+    assert!(read_test_wav(&output)?.iter().all(|s| *s == 0));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_sample_rates() -> Result<(), failure::Error> {
+    let dir = temp_dir("sample-rates");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    write_test_wav(&dir.join("bleep.wav"), &[1000, 2000])?;
+    write_test_wav_at(&dir.join("bleep-500.wav"), &[1000, 2000], 500)?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-.010}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2").join("A.wav");
+    let bleep = dir.join("bleep.wav");
+    let bleep_500 = dir.join("bleep-500.wav");
+
+    run_args(&["-c", config, "--sample", bleep.to_str().expect("bad path")])?;
+    let data = read_test_wav(&output)?;
+    assert_eq!(vec![1000, 2000, 1000, 2000], data[..4].to_vec());
+    assert!(data[10..].iter().all(|s| *s == 100));
+
+    let e = run_args(&[
+        "-c",
+        config,
+        "--sample",
+        bleep_500.to_str().expect("bad path"),
+    ])
+    .expect_err("mismatched sample rate");
+    let message = e.to_string();
+    assert!(message.contains("A.wav (1000 Hz)"), "{}", message);
+    assert!(message.contains("--sample-resample"), "{}", message);
+
+    run_args(&[
+        "-c",
+        config,
+        "--sample",
+        bleep_500.to_str().expect("bad path"),
+        "--sample-resample",
+    ])?;
+    let data = read_test_wav(&output)?;
+    assert_eq!(vec![1000, 1500, 2000, 2000], data[..4].to_vec());
+    assert!(data[10..].iter().all(|s| *s == 100));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "flac")]
+#[test]
+fn test_output_format_flac() -> Result<(), failure::Error> {
+    let dir = temp_dir("output-format-flac");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+    write_test_wav(&dir.join("ar2").join("B.wav"), &[100i16; 1000])?;
+    write_test_wav(&dir.join("ar2").join("C.wav"), &[100i16; 1000])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-.500}\"\n    B: \"clean\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    run_args(&[
+        "-c",
+        config.to_str().expect("bad path"),
+        "--output-format",
+        "flac",
+    ])?;
+
+    let output = dir.join("output").join("ar2");
+
+    for name in &["A", "B", "C"] {
+        assert!(!output.join(name).with_extension("wav").exists());
+    }
+
+    let decode = |name: &str| -> Result<Vec<i16>, failure::Error> {
+        let mut reader = claxon::FlacReader::open(output.join(name).with_extension("flac"))?;
+        assert_eq!(1000, reader.streaminfo().sample_rate);
+
+        Ok(reader
+            .samples()
+            .map(|s| s.map(|s| s as i16))
+            .collect::<Result<Vec<_>, _>>()?)
+    };
+
+    let a = decode("A")?;
+    assert_eq!(1000, a.len());
+    assert!(a[..500].iter().all(|s| *s == 0));
+    assert!(a[500..].iter().all(|s| *s == 100));
+    assert_eq!(vec![100i16; 1000], decode("B")?);
+    // files without a configuration are silenced.
+    assert_eq!(vec![0i16; 1000], decode("C")?);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "flac"))]
+#[test]
+fn test_output_format_flac_unsupported() -> Result<(), failure::Error> {
+    let dir = temp_dir("output-format-flac-unsupported");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-.500}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let e = run_args(&[
+        "-c",
+        config.to_str().expect("bad path"),
+        "--output-format",
+        "flac",
+    ])
+    .expect_err("flac without the feature");
+    assert!(e.to_string().contains("`flac` feature"), "{}", e);
+    assert!(!dir.join("output").exists());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_deny_missing() -> Result<(), failure::Error> {
+    let dir = temp_dir("deny-missing");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    write_test_wav(&dir.join("ar2").join("B.wav"), &[100i16; 100])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"hello\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2");
+
+    // by default missing files are silenced.
+    run_args(&["-c", config])?;
+    assert_eq!(vec![0i16; 100], read_test_wav(&output.join("B.wav"))?);
+    std::fs::remove_dir_all(&output)?;
+
+    for args in &[&["--deny-missing"][..], &["--deny-missing", "--list"][..]] {
+        let mut all = vec!["-c", config];
+        all.extend(args.iter());
+        let e = run_args(&all).expect_err("missing files");
+        assert!(e.to_string().contains("1 file(s)"), "{}", e);
+        assert!(!output.exists());
+    }
+
+    // words without ranges are governed by --on-missing, not --deny-missing.
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"hello\"\n  - B: \"[b]\"\n",
+    )?;
+    run_args(&["-c", config, "--deny-missing"])?;
+    assert_eq!(vec![0i16; 100], read_test_wav(&output.join("B.wav"))?);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_strict_ranges() -> Result<(), failure::Error> {
+    let dir = temp_dir("strict-ranges");
+
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+    write_test_wav(&dir.join("ar2").join("B.wav"), &[100i16; 1000])?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+
+    std::fs::write(
+        config,
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-$}\"\n  - B: \"[b]{00:00.100-00:00.200} [c]{00:00.300-$-0.100}\"\n",
+    )?;
+
+    run_args(&["-c", config])?;
+
+    let e = run_args(&["-c", config, "--strict-ranges"]).expect_err("open ranges");
+    let message = e.to_string();
+    assert!(message.contains("in 2 place(s)"), "{}", message);
+    assert!(message.contains("A.wav: [a]{^-$}"), "{}", message);
+    assert!(message.contains("B.wav: [c]{"), "{}", message);
+    assert!(!message.contains("[b]"), "{}", message);
+
+    std::fs::write(
+        config,
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{00:00.000-00:01.000}\"\n  - B: \"[b]{00:00.100-00:00.200}\"\n",
+    )?;
+
+    run_args(&["-c", config, "--strict-ranges"])?;
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_stats_silenced() -> Result<(), failure::Error> {
+    let dir = temp_dir("stats-silenced");
+
+    for name in &["A", "B", "C", "D"] {
+        write_test_wav(
+            &dir.join("ar2").join(format!("{}.wav", name)),
+            &[100i16; 1000],
+        )?;
+    }
+
+    std::fs::write(
+        dir.join("config.yml"),
+        r#"
+dirs:
+- path: ar2
+  file_extension: wav
+  files:
+  - A: "[a]{.000-.100}"
+  - B: "[a] [b]"
+  - C: "[c]"
+- path: ar2
+  file_extension: wav
+  files:
+  - path: D
+    clean: true
+"#,
+    )?;
+
+    let config = dir.join("config.yml");
+    let out = run_args(&["-c", config.to_str().expect("bad path"), "--stats"])?;
+
+    assert!(out.contains("a - 1\n"), "{}", out);
+    assert!(out.contains("clean files - 1\n"), "{}", out);
+    assert!(
+        out.contains("silenced files - 2 (3 word(s) without a range)\n"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("ar2 - 1 processed, 2 silenced, 1 clean, 1 replacement(s)\n"),
+        "{}",
+        out
+    );
+
+    // statistics don't process anything.
+    assert!(!dir.join("output").exists());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_stats_dirs() -> Result<(), failure::Error> {
+    let dir = temp_dir("stats-dirs");
+
+    for name in &["ar2/A", "ar2/B", "ar2/C", "trv1/D", "trv1/E"] {
+        write_test_wav(&dir.join(format!("{}.wav", name)), &[100i16; 1000])?;
+    }
+
+    std::fs::write(
+        dir.join("config.yml"),
+        r#"
+dirs:
+- path: ar2
+  file_extension: wav
+  files:
+  - A: "[a]{.000-.010} [b]{.020-.030}"
+  - B: "[a]{.000-.010}"
+- path: ar2
+  file_extension: wav
+  files:
+  - path: C
+    clean: true
+- path: trv1
+  file_extension: wav
+  files:
+  - D: "[c]"
+  - E: "[c]{.000-.010}"
+"#,
+    )?;
+
+    let config = dir.join("config.yml");
+    let out = run_args(&["-c", config.to_str().expect("bad path"), "--stats"])?;
+
+    assert!(out.contains("a - 2\n"), "{}", out);
+    assert!(
+        out.contains("ar2 - 2 processed, 0 silenced, 1 clean, 3 replacement(s)\n"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("trv1 - 1 processed, 1 silenced, 0 clean, 1 replacement(s)\n"),
+        "{}",
+        out
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_show_progress() -> Result<(), failure::Error> {
+    let dir = temp_dir("no-progress");
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 100])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-.010}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let out = run_args(&["-c", config.to_str().expect("bad path"), "--no-progress"])?;
+    assert_eq!("", out);
+
+    let data = read_test_wav(&dir.join("output").join("ar2").join("A.wav"))?;
+    assert!(data[..10].iter().all(|s| *s == 0));
+    assert!(data[10..].iter().all(|s| *s == 100));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_amplitude_from_db() -> Result<(), failure::Error> {
+    let dir = temp_dir("amplitude-db");
+
+    // NB: the tone is 1000Hz, so it needs a higher sample rate to not be sampled at zero.
+    write_test_wav_at(&dir.join("ar2").join("A.wav"), &[100i16; 8000], 8000)?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-$}\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2").join("A.wav");
+
+    run_args(&["-c", config, "--tone", "--tone-amplitude-db", "-6"])?;
+    let peak = read_test_wav(&output)?.iter().map(|s| s.abs()).max();
+    let peak = f32::from(peak.expect("no samples")) / f32::from(i16::MAX);
+    assert!((peak - 0.501).abs() < 0.001, "{}", peak);
+
+    assert!(run_args(&["-c", config, "--tone-amplitude-db", "-6"]).is_err());
+    assert!(run_args(&["-c", config, "--tone", "--tone-amplitude", "2"]).is_err());
+    assert!(run_args(&[
+        "-c",
+        config,
+        "--tone",
+        "--tone-amplitude",
+        "0.5",
+        "--tone-amplitude-db",
+        "-6"
+    ])
+    .is_err());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_limit() -> Result<(), failure::Error> {
+    let dir = temp_dir("limit");
+
+    for name in &["A", "B", "C"] {
+        write_test_wav(
+            &dir.join("ar2").join(format!("{}.wav", name)),
+            &[100i16; 100],
+        )?;
+    }
+
+    std::fs::write(dir.join("config.yml"), "dirs:\n- path: ar2\n")?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2");
+
+    run_args(&["-c", config, "--limit", "2"])?;
+
+    let mut written = std::fs::read_dir(&output)?
+        .map(|e| Ok(e?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, std::io::Error>>()?;
+    written.sort();
+    assert_eq!(vec!["A.wav", "B.wav"], written);
+
+    // a limit above the number of tasks runs all of them.
+    run_args(&["-c", config, "--limit", "10"])?;
+    assert!(output.join("C.wav").is_file());
+
+    assert!(run_args(&["-c", config, "--limit", "-1"]).is_err());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_reencode_clean() -> Result<(), failure::Error> {
+    let dir = temp_dir("reencode-clean");
+    let source = dir.join("ar2").join("A.wav");
+    std::fs::create_dir_all(dir.join("ar2"))?;
+
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 1000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let mut w = hound::WavWriter::create(&source, spec)?;
+
+    for _ in 0..100 {
+        w.write_sample(100i16)?;
+        w.write_sample(300i16)?;
+    }
+
+    w.finalize()?;
+
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"nothing to see here\"\n",
+    )?;
+
+    let config = dir.join("config.yml");
+    let config = config.to_str().expect("bad path");
+    let output = dir.join("output").join("ar2").join("A.wav");
+
+    // clean files are copied as-is by default.
+    run_args(&["-c", config, "--downmix-mono"])?;
+    assert_eq!(std::fs::read(&source)?, std::fs::read(&output)?);
+
+    for args in &[&[][..], &["--invert", "--max-censor-ratio", "0.5"][..]] {
+        let mut all = vec!["-c", config, "--downmix-mono", "--reencode-clean"];
+        all.extend(args.iter());
+        run_args(&all)?;
+
+        assert_eq!(1, hound::WavReader::open(&output)?.spec().channels);
+        assert_eq!(vec![200i16; 100], read_test_wav(&output)?);
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_memory_limit() -> Result<(), failure::Error> {
+    let dir = temp_dir("memory-limit");
+    write_test_wav(&dir.join("ar2").join("A.wav"), &[100i16; 1000])?;
+    write_test_wav(&dir.join("ar2").join("B.wav"), &[100i16; 1000])?;
+    std::fs::write(
+        dir.join("config.yml"),
+        "dirs:\n- path: ar2\n  file_extension: wav\n  files:\n  - A: \"[a]{^-$}\"\n    B: \"[b]{^-$}\"\n",
+    )?;
+
+    // a limit of zero processes every file on its own.
+    let config = dir.join("config.yml");
+    run_args(&[
+        "-c",
+        config.to_str().expect("bad path"),
+        "--max-memory",
+        "0",
+    ])?;
+
+    for name in &["A.wav", "B.wav"] {
+        let output = dir.join("output").join("ar2").join(name);
+        assert_eq!(vec![0i16; 1000], read_test_wav(&output)?);
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}